@@ -1,10 +1,11 @@
 fn main() {
-    #[cfg(not(any(feature = "metal", feature = "cuda")))]
+    #[cfg(not(any(feature = "metal", feature = "cuda", feature = "vulkan")))]
     {
         eprintln!("error: ohmygpu requires GPU acceleration. Build with either:");
         eprintln!();
         eprintln!("  make build-metal   (Apple Silicon)");
         eprintln!("  make build-cuda    (NVIDIA GPU)");
+        eprintln!("  make build-vulkan  (other GPUs, experimental)");
         std::process::exit(1);
     }
 }
@@ -1,20 +1,23 @@
 //! Interactive chat command
 
+use crate::daemon_client::{ChatMessage, DaemonClient};
 use anyhow::Result;
 use std::io::{self, BufRead, Write};
 
-const DAEMON_URL: &str = "http://localhost:10692";
-
-pub async fn execute(model: &str) -> Result<()> {
-    let client = reqwest::Client::new();
+pub async fn execute(model: &str, stream: bool) -> Result<()> {
+    let client = DaemonClient::new();
 
     // Check if daemon is running
-    match client.get(format!("{}/health", DAEMON_URL)).send().await {
-        Ok(r) if r.status().is_success() => {}
-        _ => {
-            eprintln!("Error: Daemon is not running. Start it with `omg serve`");
-            std::process::exit(1);
-        }
+    if !client.health().await {
+        eprintln!("Error: Daemon is not running. Start it with `omg serve`");
+        std::process::exit(1);
+    }
+
+    // Load up front so the first reply isn't delayed by model loading
+    println!("Loading {}...", model);
+    if let Err(e) = client.load(model).await {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
     }
 
     println!("Chatting with {} (Ctrl+C to exit)", model);
@@ -41,29 +44,22 @@ pub async fn execute(model: &str) -> Result<()> {
         }
 
         // Send to daemon
-        let request = serde_json::json!({
-            "model": model,
-            "messages": [{"role": "user", "content": input}],
-            "stream": false
-        });
-
-        match client
-            .post(format!("{}/v1/chat/completions", DAEMON_URL))
-            .json(&request)
-            .send()
-            .await
-        {
-            Ok(response) if response.status().is_success() => {
-                let result: serde_json::Value = response.json().await?;
-                if let Some(content) = result["choices"][0]["message"]["content"].as_str() {
-                    println!("{}", content);
-                    println!();
+        let messages = [ChatMessage::user(input)];
+        let result = if stream {
+            stream_reply(&client, model, &messages).await
+        } else {
+            client.chat(model, &messages).await.map(|completion| {
+                println!("{}", completion.content);
+                if let Some(tps) = completion.tokens_per_second {
+                    println!(
+                        "[{} tokens, {:.1} tok/s]",
+                        completion.completion_tokens, tps
+                    );
                 }
-            }
-            Ok(response) => {
-                let error = response.text().await?;
-                eprintln!("Error: {}", error);
-            }
+            })
+        };
+        match result {
+            Ok(()) => println!(),
             Err(e) => {
                 eprintln!("Error: {}", e);
             }
@@ -72,3 +68,16 @@ pub async fn execute(model: &str) -> Result<()> {
 
     Ok(())
 }
+
+/// Print the reply as it is generated
+async fn stream_reply(client: &DaemonClient, model: &str, messages: &[ChatMessage]) -> Result<()> {
+    let mut rx = client.chat_stream(model, messages).await?;
+    let mut stdout = io::stdout();
+
+    while let Some(delta) = rx.recv().await {
+        print!("{}", delta?);
+        stdout.flush()?;
+    }
+    println!();
+    Ok(())
+}
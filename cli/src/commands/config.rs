@@ -1,5 +1,7 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use ohmygpu_core::Config;
+use std::path::{Path, PathBuf};
+use std::process::Command;
 
 pub async fn execute(key: Option<&str>, value: Option<&str>) -> Result<()> {
     let mut config = Config::load()?;
@@ -11,6 +13,71 @@ pub async fn execute(key: Option<&str>, value: Option<&str>) -> Result<()> {
             println!("[daemon]");
             println!("  host = \"{}\"", config.daemon.host);
             println!("  port = {}", config.daemon.port);
+            println!("  min_vram_mb = {}", config.daemon.min_vram_mb);
+            println!("  log_transcripts = {}", config.daemon.log_transcripts);
+            println!(
+                "  transcript_path = {}",
+                config
+                    .daemon
+                    .transcript_path
+                    .as_ref()
+                    .map(|p| format!("\"{}\"", p.display()))
+                    .unwrap_or_else(|| "(default)".to_string())
+            );
+            println!("  transcript_max_mb = {}", config.daemon.transcript_max_mb);
+            println!(
+                "  max_tokens_per_second = {}",
+                config
+                    .daemon
+                    .max_tokens_per_second
+                    .map(|v| v.to_string())
+                    .unwrap_or_else(|| "(unlimited)".to_string())
+            );
+            println!(
+                "  max_concurrent_requests_per_client = {}",
+                config
+                    .daemon
+                    .max_concurrent_requests_per_client
+                    .map(|v| v.to_string())
+                    .unwrap_or_else(|| "(unlimited)".to_string())
+            );
+            println!(
+                "  max_batch_total_tokens = {}",
+                config
+                    .daemon
+                    .max_batch_total_tokens
+                    .map(|v| v.to_string())
+                    .unwrap_or_else(|| "(unlimited)".to_string())
+            );
+            println!(
+                "  image_dir = {}",
+                config
+                    .daemon
+                    .image_dir
+                    .as_ref()
+                    .map(|p| format!("\"{}\"", p.display()))
+                    .unwrap_or_else(|| "(default)".to_string())
+            );
+            println!(
+                "  image_base_url = {}",
+                config
+                    .daemon
+                    .image_base_url
+                    .as_ref()
+                    .map(|url| format!("\"{}\"", url))
+                    .unwrap_or_else(|| "(default)".to_string())
+            );
+            println!(
+                "  image_retention_secs = {}",
+                config.daemon.image_retention_secs
+            );
+            println!(
+                "  sse_keep_alive_secs = {}",
+                config.daemon.sse_keep_alive_secs
+            );
+            println!("  cors_origins = {:?}", config.daemon.cors_origins);
+            println!("  auto_download = {}", config.daemon.auto_download);
+            println!("  log_level = \"{}\"", config.daemon.log_level);
             println!();
             println!("[models]");
             println!("  storage_path = \"{}\"", config.models.storage_path.display());
@@ -24,6 +91,33 @@ pub async fn execute(key: Option<&str>, value: Option<&str>) -> Result<()> {
             println!("  temperature = {}", config.inference.temperature);
             println!("  top_p = {}", config.inference.top_p);
             println!("  use_gpu = {}", config.inference.use_gpu);
+            println!("  gpu_id = {}", config.inference.gpu_id);
+            println!(
+                "  stream_buffer_size = {}",
+                config.inference.stream_buffer_size
+            );
+            println!(
+                "  chat_template = {}",
+                config
+                    .inference
+                    .chat_template
+                    .as_ref()
+                    .map(|t| format!("{:?}", t))
+                    .unwrap_or_else(|| "(built-in)".to_string())
+            );
+            println!(
+                "  dtype = {}",
+                config.inference.dtype.as_deref().unwrap_or("(auto)")
+            );
+            println!(
+                "  context_length = {}",
+                config
+                    .inference
+                    .context_length
+                    .map(|n| n.to_string())
+                    .unwrap_or_else(|| "(model maximum)".to_string())
+            );
+            warn_problems(&config, None);
         }
 
         // Get a specific key
@@ -35,8 +129,13 @@ pub async fn execute(key: Option<&str>, value: Option<&str>) -> Result<()> {
         // Set a specific key
         (Some(key), Some(value)) => {
             set_config_value(&mut config, key, value)?;
+            // Other invalid settings must not stop this one being fixed
+            if let Some((_, problem)) = config.problems().into_iter().find(|(k, _)| *k == key) {
+                anyhow::bail!("{} {}", key, problem);
+            }
             config.save()?;
             println!("Set {} = {}", key, value);
+            warn_problems(&config, Some(key));
         }
 
         _ => unreachable!(),
@@ -45,10 +144,174 @@ pub async fn execute(key: Option<&str>, value: Option<&str>) -> Result<()> {
     Ok(())
 }
 
+/// Print invalid settings other than `except`, which was just checked
+fn warn_problems(config: &Config, except: Option<&str>) {
+    for (key, problem) in config.problems() {
+        if Some(key) != except {
+            eprintln!("Warning: {} {}", key, problem);
+        }
+    }
+}
+
+/// Open the config file in the user's editor. Invalid TOML is never kept:
+/// the editor re-opens until the file parses, or the edit is discarded.
+pub async fn edit() -> Result<()> {
+    let path = Config::config_path()?;
+    if !path.exists() {
+        Config::default().save()?;
+        println!("Created {} with default settings", path.display());
+    }
+    let original = std::fs::read_to_string(&path)?;
+
+    loop {
+        open_in_editor(&path)?;
+
+        let content = std::fs::read_to_string(&path)?;
+        let error = match toml::from_str::<Config>(&content) {
+            Ok(config) => {
+                warn_problems(&config, None);
+                if content == original {
+                    println!("No changes.");
+                } else {
+                    println!("Saved {}", path.display());
+                }
+                return Ok(());
+            }
+            Err(e) => e,
+        };
+
+        eprintln!("Invalid config: {}", error);
+        // Without a terminal to ask on, discard rather than keep a broken file
+        let reopen = dialoguer::Confirm::new()
+            .with_prompt("Re-open the editor to fix it? (no discards your changes)")
+            .default(true)
+            .interact()
+            .unwrap_or(false);
+        if !reopen {
+            std::fs::write(&path, &original)?;
+            println!("Discarded changes; {} is unchanged.", path.display());
+            return Ok(());
+        }
+    }
+}
+
+/// Back up config.toml to config.toml.bak (config.toml.bak.2, ... if taken)
+/// and replace it with the defaults, keeping `models.hf_token` if asked. The old file is only read as TOML for
+/// the token, so a config that no longer loads can still be reset.
+pub async fn reset(keep_token: bool) -> Result<()> {
+    let path = Config::config_path()?;
+    let mut config = Config::default();
+
+    if path.exists() {
+        let content = std::fs::read_to_string(&path)?;
+        if keep_token {
+            config.models.hf_token = content.parse::<toml::Table>().ok().and_then(|table| {
+                table
+                    .get("models")?
+                    .get("hf_token")?
+                    .as_str()
+                    .map(str::to_string)
+            });
+            if config.models.hf_token.is_none() {
+                println!("No HuggingFace token found to keep.");
+            }
+        }
+
+        let backup = backup_path(&path);
+        std::fs::copy(&path, &backup)
+            .with_context(|| format!("Failed to back up config to {}", backup.display()))?;
+        println!("Backed up the current config to {}", backup.display());
+    }
+
+    config.save()?;
+    println!(
+        "Reset {} to defaults{}",
+        path.display(),
+        if config.models.hf_token.is_some() {
+            " (kept the HuggingFace token)"
+        } else {
+            ""
+        }
+    );
+    Ok(())
+}
+
+/// config.toml.bak, or the first of config.toml.bak.2, .3, ... that doesn't
+/// exist, so earlier backups are never overwritten
+fn backup_path(path: &Path) -> PathBuf {
+    let backup = path.with_extension("toml.bak");
+    let mut candidate = backup.clone();
+    let mut n = 1;
+    while candidate.exists() {
+        n += 1;
+        candidate = backup.with_extension(format!("bak.{}", n));
+    }
+    candidate
+}
+
+/// Run $VISUAL or $EDITOR (which may include arguments, e.g. "code --wait")
+/// on `path` and wait for it to exit
+fn open_in_editor(path: &Path) -> Result<()> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .ok()
+        .filter(|e| !e.trim().is_empty())
+        .unwrap_or_else(|| if cfg!(windows) { "notepad" } else { "vi" }.to_string());
+
+    let mut parts = editor.split_whitespace();
+    let program = parts.next().unwrap_or_default();
+    let status = Command::new(program)
+        .args(parts)
+        .arg(path)
+        .status()
+        .with_context(|| format!("Failed to run editor '{}'", editor))?;
+
+    if !status.success() {
+        anyhow::bail!("Editor '{}' exited with {}", editor, status);
+    }
+    Ok(())
+}
+
 fn get_config_value(config: &Config, key: &str) -> Result<String> {
     match key {
         "daemon.host" => Ok(config.daemon.host.clone()),
         "daemon.port" => Ok(config.daemon.port.to_string()),
+        "daemon.min_vram_mb" => Ok(config.daemon.min_vram_mb.to_string()),
+        "daemon.log_transcripts" => Ok(config.daemon.log_transcripts.to_string()),
+        "daemon.transcript_path" => Ok(config
+            .daemon
+            .transcript_path
+            .as_ref()
+            .map(|p| p.display().to_string())
+            .unwrap_or_default()),
+        "daemon.transcript_max_mb" => Ok(config.daemon.transcript_max_mb.to_string()),
+        "daemon.max_tokens_per_second" => Ok(config
+            .daemon
+            .max_tokens_per_second
+            .map(|v| v.to_string())
+            .unwrap_or_default()),
+        "daemon.max_concurrent_requests_per_client" => Ok(config
+            .daemon
+            .max_concurrent_requests_per_client
+            .map(|v| v.to_string())
+            .unwrap_or_default()),
+        "daemon.max_batch_total_tokens" => Ok(config
+            .daemon
+            .max_batch_total_tokens
+            .map(|v| v.to_string())
+            .unwrap_or_default()),
+        "daemon.image_dir" => Ok(config
+            .daemon
+            .image_dir
+            .as_ref()
+            .map(|p| p.display().to_string())
+            .unwrap_or_default()),
+        "daemon.image_base_url" => Ok(config.daemon.image_base_url.clone().unwrap_or_default()),
+        "daemon.image_retention_secs" => Ok(config.daemon.image_retention_secs.to_string()),
+        "daemon.sse_keep_alive_secs" => Ok(config.daemon.sse_keep_alive_secs.to_string()),
+        "daemon.cors_origins" => Ok(config.daemon.cors_origins.join(",")),
+        "daemon.auto_download" => Ok(config.daemon.auto_download.to_string()),
+        "daemon.log_level" => Ok(config.daemon.log_level.clone()),
         "models.storage_path" => Ok(config.models.storage_path.display().to_string()),
         "models.hf_token" => Ok(config
             .models
@@ -60,6 +323,15 @@ fn get_config_value(config: &Config, key: &str) -> Result<String> {
         "inference.temperature" => Ok(config.inference.temperature.to_string()),
         "inference.top_p" => Ok(config.inference.top_p.to_string()),
         "inference.use_gpu" => Ok(config.inference.use_gpu.to_string()),
+        "inference.gpu_id" => Ok(config.inference.gpu_id.to_string()),
+        "inference.stream_buffer_size" => Ok(config.inference.stream_buffer_size.to_string()),
+        "inference.chat_template" => Ok(config.inference.chat_template.clone().unwrap_or_default()),
+        "inference.dtype" => Ok(config.inference.dtype.clone().unwrap_or_default()),
+        "inference.context_length" => Ok(config
+            .inference
+            .context_length
+            .map(|n| n.to_string())
+            .unwrap_or_default()),
         _ => anyhow::bail!("Unknown config key: {}", key),
     }
 }
@@ -68,6 +340,97 @@ fn set_config_value(config: &mut Config, key: &str, value: &str) -> Result<()> {
     match key {
         "daemon.host" => config.daemon.host = value.to_string(),
         "daemon.port" => config.daemon.port = value.parse()?,
+        "daemon.min_vram_mb" => config.daemon.min_vram_mb = value.parse()?,
+        "daemon.log_transcripts" => {
+            config.daemon.log_transcripts = value.parse()?;
+            if config.daemon.log_transcripts {
+                eprintln!(
+                    "Warning: transcripts store full prompts and responses, which may \
+                     contain personal or sensitive data."
+                );
+            }
+        }
+        "daemon.transcript_path" => {
+            config.daemon.transcript_path = if value.is_empty() {
+                None
+            } else {
+                Some(value.into())
+            }
+        }
+        "daemon.transcript_max_mb" => config.daemon.transcript_max_mb = value.parse()?,
+        "daemon.max_tokens_per_second" => {
+            config.daemon.max_tokens_per_second = if value.is_empty() {
+                None
+            } else {
+                let rate: f64 = value.parse()?;
+                if !(rate > 0.0 && rate.is_finite()) {
+                    anyhow::bail!(
+                        "daemon.max_tokens_per_second must be positive (or empty for unlimited)"
+                    );
+                }
+                Some(rate)
+            }
+        }
+        "daemon.max_concurrent_requests_per_client" => {
+            config.daemon.max_concurrent_requests_per_client = if value.is_empty() {
+                None
+            } else {
+                let limit: u32 = value.parse()?;
+                if limit == 0 {
+                    anyhow::bail!(
+                        "daemon.max_concurrent_requests_per_client must be at least 1 \
+                         (or empty for unlimited)"
+                    );
+                }
+                Some(limit)
+            }
+        }
+        "daemon.max_batch_total_tokens" => {
+            config.daemon.max_batch_total_tokens = if value.is_empty() {
+                None
+            } else {
+                let limit: u64 = value.parse()?;
+                if limit == 0 {
+                    anyhow::bail!(
+                        "daemon.max_batch_total_tokens must be at least 1 (or empty for unlimited)"
+                    );
+                }
+                Some(limit)
+            }
+        }
+        "daemon.image_dir" => {
+            config.daemon.image_dir = if value.is_empty() {
+                None
+            } else {
+                Some(value.into())
+            }
+        }
+        "daemon.image_base_url" => {
+            config.daemon.image_base_url = if value.is_empty() {
+                None
+            } else if value.starts_with("http://") || value.starts_with("https://") {
+                Some(value.trim_end_matches('/').to_string())
+            } else {
+                anyhow::bail!("daemon.image_base_url must start with http:// or https://");
+            }
+        }
+        "daemon.image_retention_secs" => {
+            let secs: u64 = value.parse()?;
+            if secs == 0 {
+                anyhow::bail!("daemon.image_retention_secs must be at least 1");
+            }
+            config.daemon.image_retention_secs = secs;
+        }
+        "daemon.sse_keep_alive_secs" => config.daemon.sse_keep_alive_secs = value.parse()?,
+        "daemon.cors_origins" => {
+            config.daemon.cors_origins = value
+                .split(',')
+                .map(|origin| origin.trim().trim_end_matches('/').to_string())
+                .filter(|origin| !origin.is_empty())
+                .collect();
+        }
+        "daemon.auto_download" => config.daemon.auto_download = value.parse()?,
+        "daemon.log_level" => config.daemon.log_level = value.to_lowercase(),
         "models.storage_path" => config.models.storage_path = value.into(),
         "models.hf_token" => {
             config.models.hf_token = if value.is_empty() {
@@ -80,6 +443,29 @@ fn set_config_value(config: &mut Config, key: &str, value: &str) -> Result<()> {
         "inference.temperature" => config.inference.temperature = value.parse()?,
         "inference.top_p" => config.inference.top_p = value.parse()?,
         "inference.use_gpu" => config.inference.use_gpu = value.parse()?,
+        "inference.gpu_id" => config.inference.gpu_id = value.parse()?,
+        "inference.stream_buffer_size" => config.inference.stream_buffer_size = value.parse()?,
+        "inference.chat_template" => {
+            config.inference.chat_template = if value.is_empty() {
+                None
+            } else {
+                Some(value.to_string())
+            }
+        }
+        "inference.dtype" => {
+            config.inference.dtype = match value {
+                "" => None,
+                "f16" | "bf16" | "f32" => Some(value.to_string()),
+                _ => anyhow::bail!("inference.dtype must be f16, bf16 or f32 (or empty for auto)"),
+            }
+        }
+        "inference.context_length" => {
+            config.inference.context_length = if value.is_empty() {
+                None
+            } else {
+                Some(value.parse()?)
+            }
+        }
         _ => anyhow::bail!("Unknown config key: {}", key),
     }
     Ok(())
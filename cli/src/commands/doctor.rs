@@ -0,0 +1,400 @@
+//! Diagnose common setup problems
+
+use anyhow::Result;
+use ohmygpu_core::disk::free_space_bytes;
+use ohmygpu_core::{Config, ModelRegistry};
+use std::time::Duration;
+
+use crate::daemon_client::DaemonClient;
+use crate::gpu::{self, GpuBackend};
+
+/// Free space below which downloads are likely to fail
+const LOW_DISK_SPACE_GB: f64 = 20.0;
+
+enum Status {
+    Pass,
+    Warn,
+    Fail,
+}
+
+struct Check {
+    name: &'static str,
+    status: Status,
+    detail: String,
+    /// How to fix a warning or failure
+    hint: Option<String>,
+}
+
+impl Check {
+    fn pass(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: Status::Pass,
+            detail: detail.into(),
+            hint: None,
+        }
+    }
+
+    fn warn(name: &'static str, detail: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: Status::Warn,
+            detail: detail.into(),
+            hint: Some(hint.into()),
+        }
+    }
+
+    fn fail(name: &'static str, detail: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: Status::Fail,
+            detail: detail.into(),
+            hint: Some(hint.into()),
+        }
+    }
+}
+
+pub async fn execute() -> Result<()> {
+    println!("ohmygpu doctor");
+    println!("==============");
+    println!();
+
+    let (config_check, config) = check_config();
+    let mut checks = vec![check_gpu(&config), config_check];
+    checks.extend(check_models_dir());
+    checks.push(check_daemon(&config).await);
+    checks.push(check_hf_token(&config).await);
+    checks.push(check_registry());
+
+    let (mut warnings, mut failures) = (0, 0);
+    for check in &checks {
+        let label = match check.status {
+            Status::Pass => "pass",
+            Status::Warn => {
+                warnings += 1;
+                "warn"
+            }
+            Status::Fail => {
+                failures += 1;
+                "FAIL"
+            }
+        };
+        println!("  [{}] {}: {}", label, check.name, check.detail);
+        if let Some(hint) = &check.hint {
+            println!("         -> {}", hint);
+        }
+    }
+
+    println!();
+    println!(
+        "{} passed, {} warning(s), {} failure(s)",
+        checks.len() - warnings - failures,
+        warnings,
+        failures
+    );
+
+    if failures > 0 {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn check_gpu(config: &Config) -> Check {
+    let features: Vec<&str> = [
+        ("metal", cfg!(feature = "metal")),
+        ("cuda", cfg!(feature = "cuda")),
+        ("vulkan", cfg!(feature = "vulkan")),
+    ]
+    .into_iter()
+    .filter_map(|(name, enabled)| enabled.then_some(name))
+    .collect();
+
+    if features.is_empty() {
+        return Check::fail(
+            "GPU",
+            "built without GPU support (no metal/cuda/vulkan feature)",
+            "rebuild with `make build-metal` (macOS) or `make build-cuda` (NVIDIA)",
+        );
+    }
+    let features = features.join(", ");
+
+    let info = gpu::detect_gpu();
+    match info.backend {
+        GpuBackend::None => Check::fail(
+            "GPU",
+            format!("no GPU detected (compiled with {})", features),
+            "check the GPU driver (`nvidia-smi` for CUDA) or use a build for your GPU",
+        ),
+        GpuBackend::Vulkan => Check::warn(
+            "GPU",
+            format!("{} via Vulkan (compiled with {})", info.name, features),
+            "candle has no Vulkan backend yet, so inference runs on CPU",
+        ),
+        _ if info.vram_mb < config.daemon.min_vram_mb => Check::warn(
+            "GPU",
+            format!(
+                "{} ({}, {:.1} GB; compiled with {})",
+                info.name,
+                info.backend,
+                info.vram_mb as f64 / 1024.0,
+                features
+            ),
+            format!(
+                "less than the recommended {:.1} GB; prefer small or quantized models",
+                config.daemon.min_vram_mb as f64 / 1024.0
+            ),
+        ),
+        _ => Check::pass(
+            "GPU",
+            format!(
+                "{} ({}, {:.1} GB; compiled with {})",
+                info.name,
+                info.backend,
+                info.vram_mb as f64 / 1024.0,
+                features
+            ),
+        ),
+    }
+}
+
+/// Validate config.toml; later checks use the defaults if it is invalid
+fn check_config() -> (Check, Config) {
+    let path = match Config::config_path() {
+        Ok(path) => path,
+        Err(e) => {
+            return (
+                Check::fail(
+                    "Config",
+                    e.to_string(),
+                    "set HOME so the config directory can be found",
+                ),
+                Config::default(),
+            )
+        }
+    };
+    if !path.exists() {
+        return (
+            Check::pass(
+                "Config",
+                format!("{} not found; using defaults", path.display()),
+            ),
+            Config::default(),
+        );
+    }
+
+    let parsed = std::fs::read_to_string(&path)
+        .map_err(anyhow::Error::from)
+        .and_then(|content| Ok(toml::from_str::<Config>(&content)?));
+    match parsed {
+        Ok(config) => {
+            // Parses, but `omg serve` and the other commands would refuse it
+            let problems = config.problems();
+            let check = if problems.is_empty() {
+                Check::pass("Config", path.display().to_string())
+            } else {
+                let problems: Vec<String> = problems
+                    .iter()
+                    .map(|(key, problem)| format!("{} {}", key, problem))
+                    .collect();
+                Check::fail(
+                    "Config",
+                    format!("{} is invalid: {}", path.display(), problems.join("; ")),
+                    "fix it with `omg config <key> <value>` or `omg config edit`",
+                )
+            };
+            (check, config)
+        }
+        Err(e) => (
+            Check::fail(
+                "Config",
+                format!(
+                    "{} is invalid: {}",
+                    path.display(),
+                    e.to_string().lines().next().unwrap_or_default()
+                ),
+                "fix it with `omg config edit`",
+            ),
+            Config::default(),
+        ),
+    }
+}
+
+fn check_models_dir() -> Vec<Check> {
+    let dir = match ModelRegistry::models_dir() {
+        Ok(dir) => dir,
+        Err(e) => {
+            return vec![Check::fail(
+                "Models dir",
+                format!("cannot create the models directory: {}", e),
+                "check the permissions of ~/.config/ohmygpu",
+            )]
+        }
+    };
+
+    let probe = dir.join(".omg-doctor-write-test");
+    let writable = match std::fs::write(&probe, b"ok") {
+        Ok(()) => {
+            let _ = std::fs::remove_file(&probe);
+            Check::pass("Models dir", format!("{} is writable", dir.display()))
+        }
+        Err(e) => Check::fail(
+            "Models dir",
+            format!("{} is not writable: {}", dir.display(), e),
+            format!("fix the permissions of {}", dir.display()),
+        ),
+    };
+
+    let space = match free_space_bytes(&dir) {
+        Some(bytes) => {
+            let gb = bytes as f64 / 1_073_741_824.0;
+            if gb < LOW_DISK_SPACE_GB {
+                Check::warn(
+                    "Disk space",
+                    format!("{:.1} GB free", gb),
+                    "models take several GB each; free space or remove models with `omg model rm`",
+                )
+            } else {
+                Check::pass("Disk space", format!("{:.1} GB free", gb))
+            }
+        }
+        None => Check::pass("Disk space", "unknown on this platform"),
+    };
+
+    vec![writable, space]
+}
+
+async fn check_daemon(config: &Config) -> Check {
+    let client = DaemonClient::from_config(config);
+    if client.health().await {
+        Check::pass("Daemon", format!("running at {}", client.base_url()))
+    } else {
+        Check::warn(
+            "Daemon",
+            format!("not reachable at {}", client.base_url()),
+            "start it with `omg serve` (needed for chat, embed and the API)",
+        )
+    }
+}
+
+/// Validate the HuggingFace token with a lightweight authenticated call
+async fn check_hf_token(config: &Config) -> Check {
+    let Some(token) = &config.models.hf_token else {
+        return Check::pass(
+            "HF token",
+            "not set (only needed for gated or private models)",
+        );
+    };
+
+    let response = reqwest::Client::new()
+        .get("https://huggingface.co/api/whoami-v2")
+        .bearer_auth(token)
+        .timeout(Duration::from_secs(10))
+        .send()
+        .await;
+
+    match response {
+        Ok(response) if response.status().is_success() => {
+            let user = response
+                .json::<serde_json::Value>()
+                .await
+                .ok()
+                .and_then(|v| v.get("name").and_then(|n| n.as_str()).map(str::to_string));
+            match user {
+                Some(user) => Check::pass("HF token", format!("valid (user {})", user)),
+                None => Check::pass("HF token", "valid"),
+            }
+        }
+        Ok(response) if response.status() == reqwest::StatusCode::UNAUTHORIZED => Check::fail(
+            "HF token",
+            "rejected by HuggingFace",
+            "create a new token at https://huggingface.co/settings/tokens and set it with \
+             `omg config models.hf_token <token>`",
+        ),
+        Ok(response) => Check::warn(
+            "HF token",
+            format!("could not be verified (HTTP {})", response.status()),
+            "try again later",
+        ),
+        Err(e) => Check::warn(
+            "HF token",
+            format!("could not reach HuggingFace: {}", e),
+            "check your internet connection or proxy settings",
+        ),
+    }
+}
+
+/// Every registered model should be on disk, and every model directory
+/// should be registered
+fn check_registry() -> Check {
+    let registry = match ModelRegistry::load() {
+        Ok(registry) => registry,
+        Err(e) => {
+            return Check::fail(
+                "Registry",
+                format!("cannot be read: {}", e),
+                "fix or remove ~/.config/ohmygpu/registry.json and re-pull your models",
+            )
+        }
+    };
+    let models = registry.list();
+
+    let mut problems = Vec::new();
+    for model in &models {
+        if !model.path.exists() {
+            problems.push(format!(
+                "{}: directory {} is missing",
+                model.name,
+                model.path.display()
+            ));
+            continue;
+        }
+        let missing = model
+            .files
+            .iter()
+            .filter(|file| !model.path.join(file).exists())
+            .count();
+        if missing > 0 {
+            problems.push(format!("{}: {} file(s) missing", model.name, missing));
+        }
+    }
+
+    let orphans: Vec<String> = ModelRegistry::models_dir()
+        .ok()
+        .and_then(|dir| std::fs::read_dir(dir).ok())
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+        .filter(|path| {
+            !path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+        })
+        .filter(|path| !models.iter().any(|m| m.path.starts_with(path)))
+        .map(|path| path.display().to_string())
+        .collect();
+
+    if !problems.is_empty() {
+        return Check::fail(
+            "Registry",
+            problems.join("; "),
+            "re-pull the affected models, or unregister them with `omg model rm <name>`",
+        );
+    }
+    if !orphans.is_empty() {
+        return Check::warn(
+            "Registry",
+            format!(
+                "{} unregistered model dir(s): {}",
+                orphans.len(),
+                orphans.join(", ")
+            ),
+            "register them with `omg model import-dir <path>` or delete them",
+        );
+    }
+    Check::pass(
+        "Registry",
+        format!("{} model(s), all present on disk", models.len()),
+    )
+}
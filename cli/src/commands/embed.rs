@@ -0,0 +1,44 @@
+//! Embed text, or rank texts by similarity to a query
+
+use crate::daemon_client::DaemonClient;
+use anyhow::Result;
+use ohmygpu_core::similarity::top_k_similar;
+
+pub async fn execute(
+    model: &str,
+    texts: &[String],
+    similar_to: Option<&str>,
+    top: Option<usize>,
+) -> Result<()> {
+    let client = DaemonClient::new();
+
+    if !client.health().await {
+        eprintln!("Error: Daemon is not running. Start it with `omg serve`");
+        std::process::exit(1);
+    }
+
+    let Some(query) = similar_to else {
+        // One JSON array per input, in input order
+        for embedding in client.embed(model, texts).await? {
+            println!("{}", serde_json::to_string(&embedding)?);
+        }
+        return Ok(());
+    };
+
+    // Embed the query together with the candidates in a single request
+    let mut inputs = Vec::with_capacity(texts.len() + 1);
+    inputs.push(query.to_string());
+    inputs.extend_from_slice(texts);
+
+    let embeddings = client.embed(model, &inputs).await?;
+    let Some((query_embedding, candidates)) = embeddings.split_first() else {
+        anyhow::bail!("Daemon returned no embeddings");
+    };
+
+    let ranked = top_k_similar(query_embedding, candidates, top.unwrap_or(texts.len()));
+    for (index, score) in ranked {
+        println!("{:.4}  {}", score, texts[index]);
+    }
+
+    Ok(())
+}
@@ -1,160 +1,701 @@
 //! Image generation command
 
 use anyhow::Result;
-use candle_core::Device;
-use ohmygpu_core::Config;
-use ohmygpu_runtime_diffusion::{detect_model_type, load_model, ImageGenRequest};
-use std::path::PathBuf;
+use candle_core::{DType, Device};
+use clap::Args;
+use indicatif::{MultiProgress, ProgressBar, ProgressState, ProgressStyle};
+use ohmygpu_core::{Config, ModelRegistry, ModelSource};
+use ohmygpu_runtime_diffusion::{
+    detect_model_type, load_model, CancellationToken, DiffusionError, DiffusionModel,
+    DiffusionModelType, ImageGenRequest, LongPrompt, OutOfRange,
+};
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
 use chrono::Local;
 
-pub async fn execute(
-    model: &str,
-    prompt: &str,
-    output: &str,
-    width: u32,
-    height: u32,
-    steps: u32,
-    guidance_scale: f32,
-    negative_prompt: Option<&str>,
-    seed: Option<u64>,
-    cpu: bool,
-) -> Result<()> {
-    println!("Image Generation");
-    println!("================");
-    println!("Model: {}", model);
-    println!("Prompt: {}", prompt);
-    println!("Size: {}x{}", width, height);
-    println!("Steps: {}", steps);
-    println!("Guidance scale: {}", guidance_scale);
-    if let Some(seed) = seed {
-        println!("Seed: {}", seed);
+/// Where progress messages go: stdout, or stderr with `--batch-json` so
+/// that stdout carries only the JSON results
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Reporter {
+    json: bool,
+}
+
+impl Reporter {
+    pub(crate) fn new(batch_json: bool) -> Self {
+        Self { json: batch_json }
     }
-    println!();
 
-    // Resolve model path - try local first, then download from HuggingFace
-    let model_path = resolve_model_path(model)?;
+    pub(crate) fn status(&self, message: impl std::fmt::Display) {
+        if self.json {
+            eprintln!("{}", message)
+        } else {
+            println!("{}", message)
+        }
+    }
+}
 
-    println!("Loading model from: {}", model_path.display());
+/// Arguments of `omg gen image`
+#[derive(Args)]
+pub struct GenImageArgs {
+    /// Text prompt for image generation
+    #[arg(required_unless_present = "interactive")]
+    pub prompt: Option<String>,
+
+    /// Model to use
+    #[arg(short, long, default_value = "Tongyi-MAI/Z-Image-Turbo")]
+    pub model: String,
+
+    /// Output file path. An existing file is kept and the image is
+    /// saved as <output>-2.png, -3, ... instead.
+    #[arg(short, long, default_value = "output.png")]
+    pub output: String,
+
+    /// Replace existing files at the output path
+    #[arg(long)]
+    pub overwrite: bool,
+
+    /// Image width in pixels
+    #[arg(long, default_value_t = 1024)]
+    pub width: u32,
+
+    /// Image height in pixels
+    #[arg(long, default_value_t = 1024)]
+    pub height: u32,
+
+    /// Number of inference steps
+    #[arg(short, long, default_value_t = 9)]
+    pub steps: u32,
+
+    /// Guidance scale for CFG
+    #[arg(short, long, default_value_t = 5.0)]
+    pub guidance_scale: f32,
+
+    /// Negative prompt (for CFG)
+    #[arg(long)]
+    pub negative_prompt: Option<String>,
+
+    /// Seed for reproducibility. With --count, image i uses seed + i;
+    /// without a seed a random one is picked and printed.
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Number of images to generate (saved as <output>-1.png, -2, ...;
+    /// numbering continues past existing files)
+    #[arg(short = 'n', long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    pub count: u32,
+
+    /// Run on CPU instead of GPU
+    #[arg(long)]
+    pub cpu: bool,
+
+    /// Run the VAE in a different dtype than the transformer; defaults to
+    /// the pipeline dtype
+    #[arg(long, value_parser = ["f16", "bf16", "f32"])]
+    pub vae_dtype: Option<String>,
+
+    /// Load the model once, then read prompts in a loop
+    /// (/steps N, /seed N, /size WxH, /exit)
+    #[arg(short, long)]
+    pub interactive: bool,
+
+    /// Print one JSON object per generated image to stdout (path, seed,
+    /// width, height, elapsed_ms, model); progress goes to stderr
+    #[arg(long, conflicts_with = "interactive")]
+    pub batch_json: bool,
+
+    /// Interpret `(text:1.3)` emphasis in prompts (off: prompts are
+    /// used literally)
+    #[arg(long)]
+    pub prompt_weighting: bool,
+
+    /// Model architecture (zimage or flux); skips detection from
+    /// transformer/config.json
+    #[arg(long)]
+    pub model_type: Option<String>,
+
+    /// Keep --steps and --guidance-scale outside the model's
+    /// recommended ranges instead of clamping them
+    #[arg(long)]
+    pub allow_any: bool,
+
+    /// Prompts longer than the text encoder takes: "truncate" drops
+    /// the excess with a warning, "chunk" encodes the prompt in windows
+    /// and averages them
+    #[arg(long, default_value = "truncate")]
+    pub long_prompt: String,
+}
 
-    // Detect model type
-    let model_type = detect_model_type(&model_path)?;
-    println!("Detected model type: {:?}", model_type);
+pub async fn execute(args: GenImageArgs) -> Result<()> {
+    let reporter = Reporter::new(args.batch_json);
+    Config::load()?.validate()?;
+    let model_type = args
+        .model_type
+        .as_deref()
+        .map(str::parse::<DiffusionModelType>)
+        .transpose()?;
+    let long_prompt: LongPrompt = args.long_prompt.parse()?;
+    let vae_dtype = args
+        .vae_dtype
+        .as_deref()
+        .map(|d| {
+            d.parse::<DType>().map_err(|_| {
+                anyhow::anyhow!("Unsupported VAE dtype '{}' (use f16, bf16 or f32)", d)
+            })
+        })
+        .transpose()?;
+    let model = args.model.as_str();
+
+    reporter.status("Image Generation");
+    reporter.status("================");
+    reporter.status(format!("Model: {}", model));
+    if let Some(prompt) = &args.prompt {
+        reporter.status(format!("Prompt: {}", prompt));
+    }
+    reporter.status(format!("Size: {}x{}", args.width, args.height));
+    reporter.status(format!("Steps: {}", args.steps));
+    reporter.status(format!("Guidance scale: {}", args.guidance_scale));
+    if args.count > 1 {
+        reporter.status(format!("Images: {}", args.count));
+    }
+    if let Some(vae_dtype) = vae_dtype {
+        reporter.status(format!("VAE dtype: {:?}", vae_dtype));
+    }
+    reporter.status("");
 
-    // Setup device
-    let device = if cpu {
-        println!("Using CPU (this will be slow)");
-        Device::Cpu
-    } else {
-        #[cfg(feature = "metal")]
-        {
-            println!("Using Metal GPU");
-            Device::new_metal(0)?
-        }
-        #[cfg(feature = "cuda")]
-        {
-            println!("Using CUDA GPU");
-            Device::new_cuda(0)?
+    let pipeline = load_pipeline(reporter, model, args.cpu, vae_dtype, model_type)?;
+
+    // Create request
+    let mut request = ImageGenRequest {
+        prompt: args.prompt.clone().unwrap_or_default(),
+        negative_prompt: args.negative_prompt.clone(),
+        width: args.width,
+        height: args.height,
+        steps: args.steps,
+        guidance_scale: args.guidance_scale,
+        seed: args.seed,
+        num_images: args.count,
+        prompt_weighting: args.prompt_weighting,
+        long_prompt,
+    };
+    apply_caps(reporter, pipeline.as_ref(), &mut request, args.allow_any);
+
+    let ctrl_c = CtrlC::install();
+
+    if args.prompt.is_some() {
+        generate_and_save(reporter, pipeline.as_ref(), &request, &args, &ctrl_c)?;
+    }
+
+    if args.interactive {
+        run_interactive(reporter, pipeline.as_ref(), &mut request, &args, &ctrl_c)?;
+    }
+
+    reporter.status("\nDone!");
+    Ok(())
+}
+
+/// Resolve, detect and load the diffusion model onto the selected device
+fn load_pipeline(
+    reporter: Reporter,
+    model: &str,
+    cpu: bool,
+    vae_dtype: Option<DType>,
+    model_type: Option<DiffusionModelType>,
+) -> Result<Box<dyn DiffusionModel>> {
+    // Resolve model path - try local first, then download from HuggingFace
+    let model_path = resolve_model_path(reporter, model)?;
+
+    reporter.status(format!("Loading model from: {}", model_path.display()));
+
+    let model_type = match model_type {
+        Some(model_type) => {
+            reporter.status(format!("Model type: {:?}", model_type));
+            model_type
         }
-        #[cfg(not(any(feature = "metal", feature = "cuda")))]
-        {
-            println!("No GPU acceleration available, using CPU");
-            Device::Cpu
+        None => {
+            let model_type = detect_model_type(&model_path)?;
+            reporter.status(format!("Detected model type: {:?}", model_type));
+            model_type
         }
     };
 
+    let device = select_device(reporter, cpu)?;
+
     // Load model
-    println!("\nLoading model...");
-    let pipeline = load_model(&model_path, model_type, &device)?;
-    println!("Model loaded: {}", pipeline.name());
+    reporter.status("\nLoading model...");
+    let pipeline =
+        load_model(&model_path, model_type, &device, vae_dtype).inspect_err(print_hint)?;
+    reporter.status(format!("Model loaded: {}", pipeline.name()));
 
-    // Create request
-    let request = ImageGenRequest {
-        prompt: prompt.to_string(),
-        negative_prompt: negative_prompt.map(|s| s.to_string()),
-        width,
-        height,
-        steps,
-        guidance_scale,
-        seed,
-    };
+    Ok(pipeline)
+}
+
+/// The GPU this build supports, or the CPU with `cpu`
+pub(crate) fn select_device(reporter: Reporter, cpu: bool) -> Result<Device> {
+    if cpu {
+        reporter.status("Using CPU (this will be slow)");
+        return Ok(Device::Cpu);
+    }
+    #[cfg(feature = "metal")]
+    {
+        reporter.status("Using Metal GPU");
+        Ok(Device::new_metal(0)?)
+    }
+    #[cfg(feature = "cuda")]
+    {
+        reporter.status("Using CUDA GPU");
+        Ok(Device::new_cuda(0)?)
+    }
+    #[cfg(not(any(feature = "metal", feature = "cuda")))]
+    {
+        reporter.status("No GPU acceleration available, using CPU");
+        Ok(Device::Cpu)
+    }
+}
+
+/// Clamp `steps` and `guidance_scale` into the model's recommended ranges,
+/// or with `allow_any` only warn about values outside them
+fn apply_caps(
+    reporter: Reporter,
+    pipeline: &dyn DiffusionModel,
+    request: &mut ImageGenRequest,
+    allow_any: bool,
+) {
+    let caps = pipeline.caps();
+    if allow_any {
+        for param in caps.check(request) {
+            reporter.status(format!(
+                "Warning: {} for {}; using it anyway (--allow-any)",
+                param,
+                pipeline.name()
+            ));
+        }
+        return;
+    }
+    for param in caps.clamp(request) {
+        let used = match param {
+            OutOfRange::Steps { .. } => request.steps.to_string(),
+            OutOfRange::GuidanceScale { .. } => request.guidance_scale.to_string(),
+        };
+        reporter.status(format!(
+            "Warning: {} for {}; using {} (pass --allow-any to keep it)",
+            param,
+            pipeline.name(),
+            used
+        ));
+    }
+}
+
+/// Routes Ctrl+C to the running generation: a press while generating
+/// cancels it after the current step, a press with nothing running (or a
+/// second press) exits.
+#[derive(Clone, Default)]
+struct CtrlC(Arc<Mutex<Option<CancellationToken>>>);
+
+impl CtrlC {
+    fn install() -> Self {
+        let ctrl_c = Self::default();
+        let current = ctrl_c.0.clone();
+        tokio::spawn(async move {
+            while tokio::signal::ctrl_c().await.is_ok() {
+                match current.lock().unwrap().take() {
+                    Some(token) => {
+                        eprintln!("\nCancelling after the current step (Ctrl+C again to quit)...");
+                        token.cancel();
+                    }
+                    None => std::process::exit(130),
+                }
+            }
+        });
+        ctrl_c
+    }
 
-    // Generate image
-    println!("\nGenerating image...");
-    let start = std::time::Instant::now();
-    let response = pipeline.generate(&request)?;
-    let elapsed = start.elapsed();
-    println!("Generation completed in {:.2}s", elapsed.as_secs_f64());
+    /// A fresh token for the next generation
+    fn start(&self) -> CancellationToken {
+        let token = CancellationToken::new();
+        *self.0.lock().unwrap() = Some(token.clone());
+        token
+    }
+
+    fn finish(&self) {
+        *self.0.lock().unwrap() = None;
+    }
+}
+
+fn generate_and_save(
+    reporter: Reporter,
+    pipeline: &dyn DiffusionModel,
+    request: &ImageGenRequest,
+    args: &GenImageArgs,
+    ctrl_c: &CtrlC,
+) -> Result<()> {
+    // Choose the seed up front so it can be reported; a random seed stays
+    // random for the next generation in interactive mode
+    let mut request = request.clone();
+    let seed = request.resolve_seed();
+    if request.num_images > 1 {
+        reporter.status(format!("\nSeed: {} (image i uses seed + i)", seed));
+    } else {
+        reporter.status(format!("\nSeed: {}", seed));
+    }
 
     // Resolve output path
-    let output_path = resolve_output_path(output)?;
+    let output_path = resolve_output_path(&args.output)?;
+
+    // Bars go to stderr and are hidden when it is not a terminal
+    let bars = MultiProgress::new();
+    let images_bar =
+        (request.num_images > 1).then(|| bars.add(ProgressBar::new(request.num_images as u64)));
+    if let Some(images_bar) = &images_bar {
+        images_bar.set_style(progress_style("images", "Images"));
+    }
+
+    // Batch numbering skips past existing files and only moves forward, so
+    // images of one batch never share a path
+    let mut next_number = 1;
+    for (index, image_seed) in (0..request.num_images).zip(request.seeds()) {
+        // Generate image
+        let steps_bar = bars.add(ProgressBar::new(request.steps as u64));
+        steps_bar.set_style(progress_style("steps", "Steps "));
+        if request.num_images > 1 {
+            steps_bar.set_message(format!(
+                "image {}/{} (seed {})",
+                index + 1,
+                request.num_images,
+                image_seed
+            ));
+        }
+        let start = std::time::Instant::now();
+        let token = ctrl_c.start();
+        let response =
+            pipeline.generate_with_progress(&request.for_image(index), token, &mut |progress| {
+                steps_bar.set_position(progress.step as u64)
+            });
+        ctrl_c.finish();
+        steps_bar.finish_and_clear();
+        let response = match response {
+            Ok(response) => response,
+            Err(e) => {
+                if let Some(images_bar) = &images_bar {
+                    images_bar.abandon();
+                }
+                print_hint(&e);
+                return Err(e);
+            }
+        };
+        let elapsed = start.elapsed();
+
+        // Save image
+        let path = match (request.num_images > 1, args.overwrite) {
+            (true, true) => numbered_path(&output_path, index + 1),
+            (true, false) => {
+                let (path, number) = free_numbered_path(&output_path, next_number);
+                next_number = number + 1;
+                path
+            }
+            (false, true) => output_path.clone(),
+            (false, false) => free_path(&output_path),
+        };
+        bars.suspend(|| {
+            if request.num_images > 1 {
+                reporter.status(format!(
+                    "Image {}/{} (seed {}) generated in {:.2}s",
+                    index + 1,
+                    request.num_images,
+                    image_seed,
+                    elapsed.as_secs_f64()
+                ));
+            } else {
+                reporter.status(format!(
+                    "Generation completed in {:.2}s",
+                    elapsed.as_secs_f64()
+                ));
+            }
+            reporter.status(format!("Saving to: {}", path.display()));
+        });
+        save_image(&response.pixels, response.width, response.height, &path)?;
+        if let Some(images_bar) = &images_bar {
+            images_bar.inc(1);
+        }
 
-    // Save image
-    println!("\nSaving to: {}", output_path.display());
-    save_image(&response.pixels, response.width, response.height, &output_path)?;
+        if reporter.json {
+            let result = serde_json::json!({
+                "path": path.display().to_string(),
+                "seed": image_seed,
+                "width": response.width,
+                "height": response.height,
+                "elapsed_ms": elapsed.as_millis() as u64,
+                "model": args.model,
+            });
+            let mut stdout = io::stdout().lock();
+            writeln!(stdout, "{}", result)?;
+            stdout.flush()?;
+        }
+    }
 
-    println!("\nDone!");
+    if let Some(images_bar) = images_bar {
+        images_bar.finish_and_clear();
+    }
     Ok(())
 }
 
-fn resolve_model_path(model: &str) -> Result<PathBuf> {
+/// A bar with position, elapsed time and an ETA from the average time per
+/// unit so far (steady for diffusion steps, unlike a recent-rate estimate)
+fn progress_style(unit: &str, label: &str) -> ProgressStyle {
+    ProgressStyle::with_template(&format!(
+        "{} [{{bar:30.cyan/blue}}] {{pos}}/{{len}} {} [{{elapsed_precise}}, ETA {{average_eta}}] {{msg}}",
+        label, unit
+    ))
+    .unwrap_or_else(|_| ProgressStyle::default_bar())
+    .with_key(
+        "average_eta",
+        |state: &ProgressState, w: &mut dyn std::fmt::Write| {
+            let done = state.pos();
+            let remaining = state.len().unwrap_or(done).saturating_sub(done);
+            if done == 0 {
+                let _ = w.write_str("--:--");
+            } else {
+                let eta = state.elapsed().as_secs_f64() / done as f64 * remaining as f64;
+                let eta = eta.round() as u64;
+                let _ = write!(w, "{:02}:{:02}", eta / 60, eta % 60);
+            }
+        },
+    )
+    .progress_chars("#>-")
+}
 
-    // Check if it's an absolute path
-    let path = PathBuf::from(model);
-    if path.is_absolute() && path.exists() {
-        return Ok(path);
+/// Suggest a fix for errors the user can do something about
+fn print_hint(error: &anyhow::Error) {
+    match error.downcast_ref::<DiffusionError>() {
+        Some(DiffusionError::MissingComponent { .. }) => {
+            eprintln!(
+                "Hint: the model files are incomplete; download the model again \
+                 with `omg model pull <repo>`"
+            );
+        }
+        Some(DiffusionError::InvalidDimensions { multiple_of, .. }) => {
+            eprintln!(
+                "Hint: pass --width and --height as multiples of {}",
+                multiple_of
+            );
+        }
+        _ => {}
     }
+}
 
-    // Check if it's a relative path that exists
+/// `dir/name.png` -> `dir/name-<n>.png`
+fn numbered_path(path: &Path, n: u32) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let file_name = match path.extension() {
+        Some(ext) => format!("{}-{}.{}", stem, n, ext.to_string_lossy()),
+        None => format!("{}-{}", stem, n),
+    };
+    path.with_file_name(file_name)
+}
+
+/// The first `dir/name-<n>.png` from `n` on that doesn't exist yet, with
+/// its number
+fn free_numbered_path(path: &Path, mut n: u32) -> (PathBuf, u32) {
+    loop {
+        let candidate = numbered_path(path, n);
+        if !candidate.exists() {
+            return (candidate, n);
+        }
+        n += 1;
+    }
+}
+
+/// `path`, or `dir/name-<n>.png` from 2 on when it already exists
+pub(crate) fn free_path(path: &Path) -> PathBuf {
     if path.exists() {
-        return Ok(path.canonicalize()?);
+        free_numbered_path(path, 2).0
+    } else {
+        path.to_path_buf()
     }
+}
+
+/// Read prompts in a loop, reusing the loaded model for every generation
+fn run_interactive(
+    reporter: Reporter,
+    pipeline: &dyn DiffusionModel,
+    request: &mut ImageGenRequest,
+    args: &GenImageArgs,
+    ctrl_c: &CtrlC,
+) -> Result<()> {
+    println!();
+    println!("Interactive mode: enter a prompt to generate an image.");
+    print_interactive_help();
+
+    let stdin = io::stdin();
+    let mut stdout = io::stdout();
 
-    // Try to load from config storage path
-    if let Ok(config) = Config::load() {
-        let storage_path = PathBuf::from(&config.models.storage_path);
-        let model_path = storage_path.join(model);
-        if model_path.exists() {
-            return Ok(model_path);
+    loop {
+        print!("\nimage> ");
+        stdout.flush()?;
+
+        let mut input = String::new();
+        if stdin.lock().read_line(&mut input)? == 0 {
+            // EOF
+            break;
+        }
+
+        let input = input.trim();
+        if input.is_empty() {
+            continue;
         }
 
-        // Try with repo name (e.g., "Tongyi-MAI--Z-Image-Turbo")
-        let hf_name = model.replace('/', "--");
-        let model_dir = storage_path.join(&hf_name);
-        if model_dir.exists() {
-            // Check if it has snapshots subdirectory (HuggingFace structure)
-            let snapshots = model_dir.join("snapshots");
-            if snapshots.exists() {
-                if let Some(entry) = std::fs::read_dir(&snapshots)?.next() {
-                    return Ok(entry?.path());
+        if let Some(command) = input.strip_prefix('/') {
+            let mut parts = command.split_whitespace();
+            let name = parts.next().unwrap_or_default();
+            let arg = parts.next();
+
+            match (name, arg) {
+                ("exit" | "quit", _) => break,
+                ("help", _) => print_interactive_help(),
+                ("steps", Some(arg)) => match arg.parse() {
+                    Ok(steps) => {
+                        request.steps = steps;
+                        apply_caps(reporter, pipeline, request, args.allow_any);
+                        println!("Steps: {}", request.steps);
+                    }
+                    Err(_) => eprintln!("Invalid step count: {}", arg),
+                },
+                ("seed", Some("random")) => {
+                    request.seed = None;
+                    println!("Seed: random");
+                }
+                ("seed", Some(arg)) => match arg.parse() {
+                    Ok(seed) => {
+                        request.seed = Some(seed);
+                        println!("Seed: {}", seed);
+                    }
+                    Err(_) => eprintln!("Invalid seed: {}", arg),
+                },
+                ("size", Some(arg)) => match parse_size(arg) {
+                    Some((width, height)) => {
+                        request.width = width;
+                        request.height = height;
+                        println!("Size: {}x{}", width, height);
+                    }
+                    None => eprintln!("Invalid size (expected WxH): {}", arg),
+                },
+                _ => {
+                    eprintln!("Unknown command: /{}", command);
+                    print_interactive_help();
                 }
             }
-            return Ok(model_dir);
+            continue;
         }
 
-        // Also check with "models--" prefix for backwards compatibility
-        let hf_path = storage_path.join(format!("models--{}", hf_name));
-        if hf_path.exists() {
-            let snapshots = hf_path.join("snapshots");
-            if snapshots.exists() {
-                if let Some(entry) = std::fs::read_dir(&snapshots)?.next() {
-                    return Ok(entry?.path());
-                }
+        request.prompt = input.to_string();
+        match generate_and_save(reporter, pipeline, request, args, ctrl_c) {
+            Ok(()) => {}
+            Err(e) if matches!(e.downcast_ref(), Some(DiffusionError::Cancelled)) => {
+                eprintln!("Cancelled");
             }
-            return Ok(hf_path);
+            Err(e) => eprintln!("Error: {}", e),
         }
     }
 
-    // Treat as HuggingFace repo ID - download all required files
-    println!("Model not found locally, downloading from HuggingFace: {}", model);
+    Ok(())
+}
 
-    // Use configured storage path or default
-    let cache_dir = Config::load()
+fn print_interactive_help() {
+    println!("Commands:");
+    println!("  /steps N        Set the number of inference steps");
+    println!("  /seed N|random  Set a fixed seed, or go back to random seeds");
+    println!("  /size WxH       Set the image size (e.g. 768x1024)");
+    println!("  /exit           Quit");
+    println!("Ctrl+C cancels the image being generated.");
+}
+
+fn parse_size(size: &str) -> Option<(u32, u32)> {
+    let (width, height) = size.split_once(['x', 'X'])?;
+    Some((width.trim().parse().ok()?, height.trim().parse().ok()?))
+}
+
+/// Find the model locally, downloading it only when no local copy exists.
+///
+/// Local copies are looked up in order: a registry entry with this exact
+/// name, a path on disk, a registry entry pulled from this repo under
+/// another name, then the storage directory under the usual layouts. The
+/// chosen path is printed so mismatches can be diagnosed.
+fn resolve_model_path(reporter: Reporter, model: &str) -> Result<PathBuf> {
+    let registry = ModelRegistry::load().ok();
+    let hf_name = model.replace('/', "--");
+
+    // Exact registry match, including directories added with `model import-dir`
+    if let Some(info) = registry
+        .as_ref()
+        .and_then(|r| r.get(model).or_else(|| r.get(&hf_name)))
+    {
+        if info.path.exists() {
+            reporter.status(format!("Using registered model '{}'", info.name));
+            return snapshot_or_dir(reporter, &info.path);
+        }
+        reporter.status(format!(
+            "Warning: registered model '{}' points to {}, which no longer exists",
+            info.name,
+            info.path.display()
+        ));
+    }
+
+    // A path on disk
+    let path = PathBuf::from(model);
+    if path.exists() {
+        return Ok(path.canonicalize()?);
+    }
+
+    // Pulled from this repo but registered under another name
+    if let Some(info) = registry.as_ref().and_then(|r| {
+        r.list().into_iter().find(|info| {
+            matches!(&info.source, ModelSource::HuggingFace { repo_id } if repo_id == model)
+                && info.path.exists()
+        })
+    }) {
+        reporter.status(format!(
+            "Using registered model '{}' (pulled from {})",
+            info.name, model
+        ));
+        return snapshot_or_dir(reporter, &info.path);
+    }
+
+    // The storage directory, under the names downloads have used
+    let storage_path = Config::load()
         .map(|c| PathBuf::from(&c.models.storage_path))
         .unwrap_or_else(|_| {
             dirs::home_dir()
                 .unwrap_or_else(|| PathBuf::from("."))
                 .join(".config/ohmygpu/models")
         });
+    let candidates = [
+        storage_path.join(model),
+        storage_path.join(&hf_name),
+        // hf-hub's cache layout, kept for backwards compatibility
+        storage_path.join(format!("models--{}", hf_name)),
+    ];
+    if let Some(dir) = candidates.iter().find(|dir| dir.exists()) {
+        reporter.status(format!("Using local copy in {}", dir.display()));
+        return snapshot_or_dir(reporter, dir);
+    }
+
+    if !model.contains('/') {
+        anyhow::bail!(
+            "Model '{}' not found: not a registered model or an existing path (looked in {}). \
+             Use a HuggingFace repo id (org/name) to download it.",
+            model,
+            storage_path.display()
+        );
+    }
+
+    // Treat as HuggingFace repo ID - download all required files
+    reporter.status(format!(
+        "Model not found locally (looked in the registry and {}), downloading from HuggingFace: {}",
+        storage_path.display(),
+        model
+    ));
+    let cache_dir = storage_path;
 
     let api = hf_hub::api::sync::ApiBuilder::new()
         .with_cache_dir(cache_dir.clone())
@@ -166,28 +707,28 @@ fn resolve_model_path(model: &str) -> Result<PathBuf> {
     // Files are downloaded to ~/.cache/huggingface/hub/models--<repo>/snapshots/<hash>/
 
     // Force download of essential files for Z-Image
-    println!("Downloading tokenizer...");
+    reporter.status("Downloading tokenizer...");
     let _ = repo.get("tokenizer/tokenizer.json")?;
 
-    println!("Downloading text encoder...");
+    reporter.status("Downloading text encoder...");
     let _ = repo.get("text_encoder/config.json");
     for i in 1..=3 {
         let file = format!("text_encoder/model-{:05}-of-00003.safetensors", i);
         if let Err(e) = repo.get(&file) {
-            println!("Warning: could not download {}: {}", file, e);
+            reporter.status(format!("Warning: could not download {}: {}", file, e));
         }
     }
 
-    println!("Downloading transformer...");
+    reporter.status("Downloading transformer...");
     let _ = repo.get("transformer/config.json")?;
     for i in 1..=3 {
         let file = format!("transformer/diffusion_pytorch_model-{:05}-of-00003.safetensors", i);
         if let Err(e) = repo.get(&file) {
-            println!("Warning: could not download {}: {}", file, e);
+            reporter.status(format!("Warning: could not download {}: {}", file, e));
         }
     }
 
-    println!("Downloading VAE...");
+    reporter.status("Downloading VAE...");
     let _ = repo.get("vae/config.json");
     let _ = repo.get("vae/diffusion_pytorch_model.safetensors")?;
 
@@ -200,20 +741,11 @@ fn resolve_model_path(model: &str) -> Result<PathBuf> {
     // Auto-rename: remove "models--" prefix
     if model_cache_with_prefix.exists() && !model_cache_clean.exists() {
         std::fs::rename(&model_cache_with_prefix, &model_cache_clean)?;
-        println!("Renamed to: {}", model_cache_clean.display());
+        reporter.status(format!("Renamed to: {}", model_cache_clean.display()));
     }
 
-    let snapshots = model_cache_clean.join("snapshots");
-    if snapshots.exists() {
-        // Get the most recent snapshot
-        let mut entries: Vec<_> = std::fs::read_dir(&snapshots)?
-            .filter_map(|e| e.ok())
-            .collect();
-        entries.sort_by_key(|e| std::cmp::Reverse(e.metadata().ok().and_then(|m| m.modified().ok())));
-
-        if let Some(entry) = entries.first() {
-            return Ok(entry.path());
-        }
+    if model_cache_clean.join("snapshots").exists() {
+        return snapshot_or_dir(reporter, &model_cache_clean);
     }
 
     anyhow::bail!(
@@ -222,8 +754,51 @@ fn resolve_model_path(model: &str) -> Result<PathBuf> {
     )
 }
 
+/// The snapshot to load from a HuggingFace cache directory, or `dir` itself
+/// for a plain model directory.
+///
+/// Prefers the snapshot `refs/main` points at, then the only one; with
+/// several and no ref, the newest is used and all of them are listed.
+fn snapshot_or_dir(reporter: Reporter, dir: &Path) -> Result<PathBuf> {
+    let snapshots = dir.join("snapshots");
+    if !snapshots.is_dir() {
+        return Ok(dir.to_path_buf());
+    }
+
+    if let Ok(hash) = std::fs::read_to_string(dir.join("refs").join("main")) {
+        let snapshot = snapshots.join(hash.trim());
+        if snapshot.is_dir() {
+            reporter.status(format!("Using snapshot {} (refs/main)", hash.trim()));
+            return Ok(snapshot);
+        }
+    }
+
+    let mut entries: Vec<PathBuf> = std::fs::read_dir(&snapshots)?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_dir())
+        .collect();
+    entries.sort_by_key(|p| std::cmp::Reverse(p.metadata().ok().and_then(|m| m.modified().ok())));
+
+    match entries.as_slice() {
+        [] => anyhow::bail!("No snapshots in {}", snapshots.display()),
+        [only] => Ok(only.clone()),
+        [newest, ..] => {
+            reporter.status(format!(
+                "Warning: {} snapshots in {} and no refs/main; using the newest:",
+                entries.len(),
+                snapshots.display()
+            ));
+            for entry in &entries {
+                reporter.status(format!("  {}", entry.display()));
+            }
+            Ok(newest.clone())
+        }
+    }
+}
+
 /// Resolve output path, defaulting to ~/Documents/ohmygpu/
-fn resolve_output_path(output: &str) -> Result<PathBuf> {
+pub(crate) fn resolve_output_path(output: &str) -> Result<PathBuf> {
     let path = PathBuf::from(output);
 
     // If it's an absolute path, use as-is
@@ -251,7 +826,7 @@ fn resolve_output_path(output: &str) -> Result<PathBuf> {
     }
 }
 
-fn save_image(pixels: &[u8], width: u32, height: u32, path: &PathBuf) -> Result<()> {
+pub(crate) fn save_image(pixels: &[u8], width: u32, height: u32, path: &PathBuf) -> Result<()> {
     // pixels are in RGB format, convert to image
     let img = image::RgbImage::from_raw(width, height, pixels.to_vec())
         .ok_or_else(|| anyhow::anyhow!("Failed to create image from pixels"))?;
@@ -21,7 +21,7 @@ use rmcp::{
 };
 use serde::Deserialize;
 
-const DAEMON_URL: &str = "http://localhost:10692";
+use crate::daemon_client::{ChatMessage, DaemonClient};
 
 // ============================================================================
 // Request types for tools
@@ -41,7 +41,7 @@ pub struct ChatRequest {
 
 #[derive(Debug, Clone)]
 pub struct OhmyGpuMcp {
-    client: reqwest::Client,
+    client: DaemonClient,
     tool_router: ToolRouter<Self>,
 }
 
@@ -49,7 +49,7 @@ pub struct OhmyGpuMcp {
 impl OhmyGpuMcp {
     pub fn new() -> Self {
         Self {
-            client: reqwest::Client::new(),
+            client: DaemonClient::new(),
             tool_router: Self::tool_router(),
         }
     }
@@ -57,65 +57,27 @@ impl OhmyGpuMcp {
     /// Chat with a local AI model
     #[tool(description = "Chat with a local AI model running on ohmygpu. Returns the model's response.")]
     async fn chat(&self, Parameters(req): Parameters<ChatRequest>) -> Result<String, String> {
-        let request = serde_json::json!({
-            "model": req.model,
-            "messages": [{"role": "user", "content": req.message}],
-            "stream": false
-        });
-
-        let response = self
+        let completion = self
             .client
-            .post(format!("{}/v1/chat/completions", DAEMON_URL))
-            .json(&request)
-            .send()
+            .chat(&req.model, &[ChatMessage::user(req.message)])
             .await
-            .map_err(|e| format!("Failed to connect to ohmygpu daemon: {}. Is it running? Start with `omg serve`", e))?;
+            .map_err(|e| format!("Chat request failed: {:#}", e))?;
 
-        if !response.status().is_success() {
-            let error_text = response.text().await.unwrap_or_default();
-            return Err(format!("Chat request failed: {}", error_text));
+        if completion.content.is_empty() {
+            Ok("No response".to_string())
+        } else {
+            Ok(completion.content)
         }
-
-        let result: serde_json::Value = response
-            .json()
-            .await
-            .map_err(|e| format!("Failed to parse response: {}", e))?;
-
-        let content = result["choices"][0]["message"]["content"]
-            .as_str()
-            .unwrap_or("No response")
-            .to_string();
-
-        Ok(content)
     }
 
     /// List available models
     #[tool(description = "List all AI models installed on ohmygpu")]
     async fn list_models(&self) -> Result<String, String> {
-        let response = self
+        let models = self
             .client
-            .get(format!("{}/v1/models", DAEMON_URL))
-            .send()
-            .await
-            .map_err(|e| format!("Failed to connect to ohmygpu daemon: {}. Is it running? Start with `omg serve`", e))?;
-
-        if !response.status().is_success() {
-            return Err("Failed to list models".to_string());
-        }
-
-        let result: serde_json::Value = response
-            .json()
+            .list_models()
             .await
-            .map_err(|e| format!("Failed to parse response: {}", e))?;
-
-        let models: Vec<String> = result["data"]
-            .as_array()
-            .map(|arr| {
-                arr.iter()
-                    .filter_map(|m| m["id"].as_str().map(String::from))
-                    .collect()
-            })
-            .unwrap_or_default();
+            .map_err(|e| format!("Failed to list models: {:#}", e))?;
 
         if models.is_empty() {
             Ok("No models installed. Use `omg pull <model>` to download a model.".to_string())
@@ -134,20 +96,10 @@ impl OhmyGpuMcp {
     /// Check daemon status
     #[tool(description = "Check if the ohmygpu daemon is running and healthy")]
     async fn status(&self) -> Result<String, String> {
-        match self
-            .client
-            .get(format!("{}/health", DAEMON_URL))
-            .send()
-            .await
-        {
-            Ok(response) if response.status().is_success() => {
-                Ok("ohmygpu daemon is running and healthy".to_string())
-            }
-            Ok(response) => Err(format!(
-                "ohmygpu daemon returned status: {}",
-                response.status()
-            )),
-            Err(_) => Err("ohmygpu daemon is not running. Start it with `omg serve`".to_string()),
+        if self.client.health().await {
+            Ok("ohmygpu daemon is running and healthy".to_string())
+        } else {
+            Err("ohmygpu daemon is not running. Start it with `omg serve`".to_string())
         }
     }
 }
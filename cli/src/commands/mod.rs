@@ -1,12 +1,20 @@
 pub mod chat;
 pub mod config;
+pub mod doctor;
+pub mod embed;
 pub mod generate;
 pub mod mcp;
+pub mod model_export;
 pub mod model_gc;
+pub mod model_import_dir;
 pub mod model_info;
+pub mod model_pin;
+pub mod model_set_params;
+pub mod model_unload;
 pub mod models;
 pub mod pull;
 pub mod remove;
 pub mod search;
 pub mod serve;
 pub mod update;
+pub mod video;
@@ -0,0 +1,198 @@
+//! Export and import models as portable tarballs
+//!
+//! An export contains `ohmygpu-model.json` (the registry entry plus the size
+//! of every file) followed by the model directory under `model/`, keeping
+//! the subdirectory layout diffusion models rely on.
+
+use anyhow::{Context, Result};
+use ohmygpu_core::{Config, ModelInfo, ModelRegistry};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+const MANIFEST_NAME: &str = "ohmygpu-model.json";
+const MODEL_DIR: &str = "model";
+
+#[derive(Serialize, Deserialize)]
+struct ExportManifest {
+    model: ModelInfo,
+    /// Path relative to the model directory -> size in bytes
+    files: BTreeMap<String, u64>,
+}
+
+pub async fn export(model: &str, output: &Path) -> Result<()> {
+    let registry = ModelRegistry::load()?;
+    let info = registry.get(model).cloned().ok_or_else(|| {
+        anyhow::anyhow!(
+            "Model '{}' not found. Run `omg model list` to see installed models.",
+            model
+        )
+    })?;
+
+    if !info.path.is_dir() {
+        anyhow::bail!("Model directory {:?} does not exist", info.path);
+    }
+
+    // Collect files first so the manifest can lead the archive
+    let mut files = BTreeMap::new();
+    for entry in walkdir::WalkDir::new(&info.path).follow_links(true) {
+        let entry = entry?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let relative = entry.path().strip_prefix(&info.path)?;
+        files.insert(archive_path(relative), entry.metadata()?.len());
+    }
+
+    let total_bytes: u64 = files.values().sum();
+    println!(
+        "Exporting '{}' ({} files, {:.1} GB) to {}...",
+        info.name,
+        files.len(),
+        total_bytes as f64 / 1_073_741_824.0,
+        output.display()
+    );
+
+    let manifest = ExportManifest {
+        model: info.clone(),
+        files,
+    };
+    let manifest_json = serde_json::to_vec_pretty(&manifest)?;
+
+    let file = File::create(output).with_context(|| format!("Failed to create {:?}", output))?;
+    let mut builder = tar::Builder::new(BufWriter::new(file));
+
+    let mut header = tar::Header::new_gnu();
+    header.set_size(manifest_json.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(chrono::Utc::now().timestamp() as u64);
+    header.set_cksum();
+    builder.append_data(&mut header, MANIFEST_NAME, manifest_json.as_slice())?;
+
+    for relative in manifest.files.keys() {
+        builder.append_path_with_name(
+            info.path.join(relative),
+            format!("{}/{}", MODEL_DIR, relative),
+        )?;
+    }
+
+    builder.into_inner()?.flush()?;
+
+    println!("Exported '{}' to {}", info.name, output.display());
+    Ok(())
+}
+
+pub async fn import(file: &Path) -> Result<()> {
+    let archive_file = File::open(file).with_context(|| format!("Failed to open {:?}", file))?;
+    let mut archive = tar::Archive::new(BufReader::new(archive_file));
+    let mut entries = archive.entries()?;
+
+    // The manifest is always the first entry
+    let manifest: ExportManifest = {
+        let mut entry = entries
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("{:?} is empty", file))??;
+        if entry.path()?.as_ref() != Path::new(MANIFEST_NAME) {
+            anyhow::bail!("{:?} is not an ohmygpu model export", file);
+        }
+        let mut json = String::new();
+        entry.read_to_string(&mut json)?;
+        serde_json::from_str(&json).context("Invalid model manifest")?
+    };
+    let mut info = manifest.model;
+
+    let mut registry = ModelRegistry::load()?;
+    if registry.get(&info.name).is_some() {
+        anyhow::bail!(
+            "Model '{}' is already installed. Remove it first with `omg model rm {}`.",
+            info.name,
+            info.name
+        );
+    }
+
+    let models_dir = Config::load()?.models_dir();
+    let dir_name = info.name.replace('/', "--");
+    let target = models_dir.join(&dir_name);
+    if target.exists() {
+        anyhow::bail!("{:?} already exists", target);
+    }
+
+    println!(
+        "Importing '{}' ({} files)...",
+        info.name,
+        manifest.files.len()
+    );
+
+    // Unpack into a staging directory so a failed import leaves nothing behind
+    let staging = models_dir.join(format!(".import-{}", dir_name));
+    if staging.exists() {
+        fs::remove_dir_all(&staging)?;
+    }
+    fs::create_dir_all(&staging)?;
+
+    let unpacked = unpack_entries(entries, &staging)
+        .and_then(|model_dir| verify_sizes(&model_dir, &manifest.files).map(|_| model_dir));
+    let model_dir = match unpacked {
+        Ok(model_dir) => model_dir,
+        Err(e) => {
+            let _ = fs::remove_dir_all(&staging);
+            return Err(e);
+        }
+    };
+
+    fs::rename(&model_dir, &target)?;
+    fs::remove_dir_all(&staging)?;
+
+    info.path = target;
+    let name = info.name.clone();
+    registry.add(info)?;
+
+    println!("Imported '{}'", name);
+    Ok(())
+}
+
+/// Unpack the `model/` entries into `staging`, returning the model directory
+fn unpack_entries<R: Read>(entries: tar::Entries<'_, R>, staging: &Path) -> Result<PathBuf> {
+    let model_dir = staging.join(MODEL_DIR);
+    fs::create_dir_all(&model_dir)?;
+
+    for entry in entries {
+        let mut entry = entry?;
+        if !entry.path()?.starts_with(MODEL_DIR) {
+            continue;
+        }
+        // unpack_in refuses paths that would escape the staging directory
+        if !entry.unpack_in(staging)? {
+            anyhow::bail!("Archive contains an unsafe path: {:?}", entry.path()?);
+        }
+    }
+    Ok(model_dir)
+}
+
+fn verify_sizes(model_dir: &Path, files: &BTreeMap<String, u64>) -> Result<()> {
+    for (relative, expected) in files {
+        let actual = fs::metadata(model_dir.join(relative))
+            .map(|m| m.len())
+            .with_context(|| format!("Archive is missing {}", relative))?;
+        if actual != *expected {
+            anyhow::bail!(
+                "Size mismatch for {}: expected {} bytes, got {}",
+                relative,
+                expected,
+                actual
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Relative path with `/` separators, as stored in the archive
+fn archive_path(relative: &Path) -> String {
+    relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
@@ -16,6 +16,14 @@ pub async fn execute() -> Result<()> {
         .map(|m| m.path.clone())
         .collect();
 
+    // Nothing inside a pinned model's directory is touched
+    let pinned: Vec<_> = registry
+        .list()
+        .into_iter()
+        .filter(|m| m.pinned)
+        .map(|m| m.path.clone())
+        .collect();
+
     let mut cleaned_bytes: u64 = 0;
     let mut cleaned_files: u32 = 0;
 
@@ -45,6 +53,10 @@ pub async fn execute() -> Result<()> {
         {
             let path = entry.path();
             if path.extension().map(|e| e == "part").unwrap_or(false) {
+                if pinned.iter().any(|dir| path.starts_with(dir)) {
+                    println!("  Skipping {} (pinned model)", path.display());
+                    continue;
+                }
                 let size = path.metadata().map(|m| m.len()).unwrap_or(0);
                 println!("  Partial download: {} ({} bytes)", path.display(), size);
                 cleaned_bytes += size;
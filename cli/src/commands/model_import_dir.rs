@@ -0,0 +1,74 @@
+//! Register an existing local model directory
+
+use anyhow::{Context, Result};
+use ohmygpu_core::{ModelInfo, ModelRegistry, ModelSource, ModelType, SamplingParams};
+use std::path::Path;
+
+pub async fn execute(path: &Path, name: Option<&str>, model_type: Option<&str>) -> Result<()> {
+    let path = path
+        .canonicalize()
+        .with_context(|| format!("Model directory {:?} does not exist", path))?;
+    if !path.is_dir() {
+        anyhow::bail!("{:?} is not a directory", path);
+    }
+
+    let name = match name {
+        Some(name) => name.to_string(),
+        None => path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .ok_or_else(|| anyhow::anyhow!("Cannot derive a name from {:?}; use --name", path))?,
+    };
+
+    let mut registry = ModelRegistry::load()?;
+    if registry.get(&name).is_some() {
+        anyhow::bail!(
+            "Model '{}' is already registered. Pick another name with --name.",
+            name
+        );
+    }
+
+    let model_type = match model_type {
+        Some(model_type) => model_type.parse()?,
+        None => ModelType::detect(&path),
+    };
+    if model_type == ModelType::Unknown {
+        eprintln!("Warning: could not detect the model type; set it with --type");
+    }
+
+    let mut files = Vec::new();
+    let mut size_bytes = 0;
+    for entry in walkdir::WalkDir::new(&path).follow_links(true) {
+        let entry = entry?;
+        if entry.file_type().is_file() {
+            size_bytes += entry.metadata()?.len();
+            files.push(entry.path().strip_prefix(&path)?.display().to_string());
+        }
+    }
+    files.sort();
+
+    let info = ModelInfo {
+        name: name.clone(),
+        source: ModelSource::Local,
+        model_type,
+        path,
+        size_bytes,
+        files,
+        downloaded_at: chrono::Utc::now(),
+        sampling: SamplingParams::default(),
+        pinned: false,
+    };
+
+    println!("Registered '{}'", info.name);
+    println!("  Type: {}", info.model_type.as_str());
+    println!("  Size: {:.2} GB", info.size_bytes as f64 / 1_073_741_824.0);
+    println!("  Path: {:?}", info.path);
+    println!();
+    println!(
+        "Files stay where they are; `omg model rm {}` only unregisters it.",
+        name
+    );
+
+    registry.add(info)?;
+    Ok(())
+}
@@ -1,7 +1,15 @@
 //! Show model information
 
+use crate::commands::model_set_params;
+use crate::gpu::{self, GpuBackend, GpuInfo};
 use anyhow::Result;
-use ohmygpu_core::ModelRegistry;
+use candle_core::DType;
+use ohmygpu_core::{Config, ModelInfo, ModelRegistry, ModelType};
+use ohmygpu_runtime_candle::{resolve_dtype, DeviceKind};
+use std::path::{Path, PathBuf};
+
+/// Diffusion pipeline components loaded onto the GPU together
+const DIFFUSION_COMPONENTS: &[&str] = &["transformer", "text_encoder", "vae"];
 
 pub async fn execute(model: &str) -> Result<()> {
     let registry = ModelRegistry::load()?;
@@ -12,11 +20,25 @@ pub async fn execute(model: &str) -> Result<()> {
             println!("Source: {:?}", info.source);
             println!("Type: {:?}", info.model_type);
             println!("Path: {}", info.path.display());
+            if info.pinned {
+                println!(
+                    "Pinned: yes (`omg model unpin {}` to allow removal)",
+                    info.name
+                );
+            }
+            if info.model_type == ModelType::LLM {
+                println!(
+                    "Sampling defaults: {}",
+                    model_set_params::describe(&info.sampling)
+                );
+            }
 
             // Show file sizes if available
             if info.path.exists() {
                 let size = dir_size(&info.path)?;
                 println!("Size: {}", format_size(size));
+                println!();
+                print_recommendation(info);
             }
 
             // TODO: Show more details like architecture, quantization, etc.
@@ -32,6 +54,180 @@ pub async fn execute(model: &str) -> Result<()> {
     Ok(())
 }
 
+/// Estimate the memory the model needs and compare it with the GPU the
+/// daemon would run it on
+fn print_recommendation(info: &ModelInfo) {
+    let config = Config::load().unwrap_or_default();
+    let gpus = gpu::detect_all_gpus();
+    let gpu = gpus
+        .iter()
+        .find(|g| g.index == config.inference.gpu_id)
+        .cloned()
+        .unwrap_or_else(gpu::detect_gpu);
+
+    let estimate = match info.model_type {
+        ModelType::LLM => estimate_llm(&info.path, &config, &gpu),
+        ModelType::ImageGeneration => estimate_diffusion(&info.path),
+        _ => None,
+    }
+    .unwrap_or_else(|| Estimate {
+        vram_mb: gpu::estimate_vram_mb(info.size_bytes),
+        basis: "from the size on disk".to_string(),
+    });
+
+    println!(
+        "Estimated memory: {} ({})",
+        format_size(estimate.vram_mb * 1024 * 1024),
+        estimate.basis
+    );
+
+    let recommendation = match gpu.backend {
+        GpuBackend::None => "No GPU detected: the model will run on CPU (slow)".to_string(),
+        _ if gpu.vram_mb == 0 => format!(
+            "GPU memory of {} is unknown; cannot tell whether the model fits",
+            gpu.name
+        ),
+        _ if estimate.vram_mb * 10 <= gpu.vram_mb * 9 => format!(
+            "Fits in VRAM ({} of {} on GPU {})",
+            format_size(estimate.vram_mb * 1024 * 1024),
+            format_size(gpu.vram_mb * 1024 * 1024),
+            gpu.index
+        ),
+        _ if estimate.vram_mb <= gpu.vram_mb => format!(
+            "Tight fit in VRAM ({} of {} on GPU {}): other GPU apps may cause out-of-memory errors",
+            format_size(estimate.vram_mb * 1024 * 1024),
+            format_size(gpu.vram_mb * 1024 * 1024),
+            gpu.index
+        ),
+        _ => format!(
+            "Will not fit in VRAM ({} needed, {} on GPU {}): expect spilling or out-of-memory \
+             errors; use a smaller or quantized variant",
+            format_size(estimate.vram_mb * 1024 * 1024),
+            format_size(gpu.vram_mb * 1024 * 1024),
+            gpu.index
+        ),
+    };
+    println!("Recommendation: {}", recommendation);
+}
+
+struct Estimate {
+    vram_mb: u64,
+    /// How the estimate was made, shown to the user
+    basis: String,
+}
+
+/// Weights from the parameter count at the dtype the runtime will pick,
+/// plus a full-context KV cache and ~10% runtime overhead
+fn estimate_llm(path: &Path, config: &Config, gpu: &GpuInfo) -> Option<Estimate> {
+    let config_json: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(find_config_json(path)?).ok()?).ok()?;
+    let get = |keys: &[&str]| keys.iter().find_map(|k| config_json.get(*k)?.as_u64());
+
+    let hidden = get(&["hidden_size", "n_embd"])?;
+    let layers = get(&["num_hidden_layers", "n_layer"])?;
+    let vocab = get(&["vocab_size"])?;
+    let intermediate = get(&["intermediate_size", "n_inner"]).unwrap_or(4 * hidden);
+    let heads = get(&["num_attention_heads", "n_head"]).unwrap_or(1).max(1);
+    let kv_heads = get(&["num_key_value_heads"]).unwrap_or(heads);
+    let context = get(&["max_position_embeddings", "n_positions", "max_seq_len"]).unwrap_or(2048);
+    let tied = config_json
+        .get("tie_word_embeddings")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+
+    let kv_dim = hidden / heads * kv_heads;
+    let attention = 2 * hidden * hidden + 2 * hidden * kv_dim;
+    let mlp = 3 * hidden * intermediate;
+    let embeddings = if tied {
+        vocab * hidden
+    } else {
+        2 * vocab * hidden
+    };
+    let params = layers * (attention + mlp) + embeddings;
+
+    let architecture = config_json
+        .get("model_type")
+        .and_then(|v| v.as_str())
+        .unwrap_or("llama");
+    let (dtype, bytes) = llm_dtype(config, gpu, architecture);
+
+    let weights = params * bytes;
+    let kv_cache = 2 * layers * context * kv_dim * bytes;
+    let total = (weights + kv_cache) * 11 / 10;
+
+    Some(Estimate {
+        vram_mb: total / (1024 * 1024),
+        basis: format!(
+            "~{:.1}B parameters in {}, KV cache for {} tokens",
+            params as f64 / 1e9,
+            dtype,
+            context
+        ),
+    })
+}
+
+/// The weight dtype the runtime will pick for this GPU and architecture
+fn llm_dtype(config: &Config, gpu: &GpuInfo, architecture: &str) -> (&'static str, u64) {
+    let device = match gpu.backend {
+        GpuBackend::Cuda => DeviceKind::Cuda,
+        GpuBackend::Metal => DeviceKind::Metal,
+        _ => DeviceKind::Cpu,
+    };
+    let configured = config
+        .inference
+        .dtype
+        .as_deref()
+        .and_then(|d| d.parse::<DType>().ok());
+    match resolve_dtype(device, architecture, configured).dtype {
+        DType::F16 => ("F16", 2),
+        DType::BF16 => ("BF16", 2),
+        _ => ("F32", 4),
+    }
+}
+
+/// All pipeline components are resident at once, so add up their weights
+fn estimate_diffusion(path: &Path) -> Option<Estimate> {
+    let root = walkdir::WalkDir::new(path)
+        .follow_links(true)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .find(|e| e.file_type().is_dir() && e.file_name() == "transformer")?
+        .path()
+        .parent()?
+        .to_path_buf();
+
+    let mut parts = Vec::new();
+    let mut total = 0;
+    for component in DIFFUSION_COMPONENTS {
+        let size = dir_size(&root.join(component)).unwrap_or(0);
+        if size > 0 {
+            parts.push(format!("{} {}", component, format_size(size)));
+            total += size;
+        }
+    }
+
+    if total == 0 {
+        return None;
+    }
+    Some(Estimate {
+        vram_mb: gpu::estimate_vram_mb(total),
+        basis: parts.join(", "),
+    })
+}
+
+/// The shallowest config.json, which may sit in a HuggingFace snapshot
+/// directory below the model root
+fn find_config_json(path: &Path) -> Option<PathBuf> {
+    walkdir::WalkDir::new(path)
+        .follow_links(true)
+        .max_depth(3)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file() && e.file_name() == "config.json")
+        .min_by_key(|e| e.depth())
+        .map(|e| e.into_path())
+}
+
 fn dir_size(path: &std::path::Path) -> Result<u64> {
     let mut size = 0;
     if path.is_file() {
@@ -0,0 +1,35 @@
+//! Pin a model so it is never deleted, or unpin it
+
+use anyhow::Result;
+use ohmygpu_core::ModelRegistry;
+
+pub async fn execute(model: &str, pinned: bool) -> Result<()> {
+    let mut registry = ModelRegistry::load()?;
+    let Some(info) = registry.get_mut(model) else {
+        eprintln!("Model '{}' not found", model);
+        eprintln!();
+        eprintln!("Use `omg model list` to see installed models");
+        std::process::exit(1);
+    };
+
+    if info.pinned == pinned {
+        println!(
+            "{} is already {}",
+            model,
+            if pinned { "pinned" } else { "unpinned" }
+        );
+        return Ok(());
+    }
+    info.pinned = pinned;
+    registry.save()?;
+
+    if pinned {
+        println!(
+            "Pinned {}; `omg model rm` and `omg model gc` leave it alone",
+            model
+        );
+    } else {
+        println!("Unpinned {}; it can be removed again", model);
+    }
+    Ok(())
+}
@@ -0,0 +1,73 @@
+//! Set a model's default sampling parameters
+
+use anyhow::{bail, Result};
+use ohmygpu_core::{ModelRegistry, SamplingParams};
+
+pub async fn execute(
+    model: &str,
+    temperature: Option<f32>,
+    top_p: Option<f32>,
+    clear: bool,
+) -> Result<()> {
+    if temperature.is_none() && top_p.is_none() && !clear {
+        bail!("Nothing to set; pass --temp and/or --top-p (or --clear)");
+    }
+    if let Some(temperature) = temperature {
+        if !(0.0..=2.0).contains(&temperature) {
+            bail!("--temp must be between 0 and 2, got {}", temperature);
+        }
+    }
+    if let Some(top_p) = top_p {
+        if !(top_p > 0.0 && top_p <= 1.0) {
+            bail!(
+                "--top-p must be greater than 0 and at most 1, got {}",
+                top_p
+            );
+        }
+    }
+
+    let mut registry = ModelRegistry::load()?;
+    let Some(info) = registry.get_mut(model) else {
+        eprintln!("Model '{}' not found", model);
+        eprintln!();
+        eprintln!("Use `omg model list` to see installed models");
+        std::process::exit(1);
+    };
+
+    if clear {
+        info.sampling = SamplingParams::default();
+    }
+    if temperature.is_some() {
+        info.sampling.temperature = temperature;
+    }
+    if top_p.is_some() {
+        info.sampling.top_p = top_p;
+    }
+
+    let sampling = info.sampling.clone();
+    registry.save()?;
+
+    if sampling.is_empty() {
+        println!(
+            "Cleared sampling defaults for {}; the [inference] defaults apply",
+            model
+        );
+    } else {
+        println!("Sampling defaults for {}: {}", model, describe(&sampling));
+        println!("Requests that omit these parameters use them from now on.");
+    }
+    Ok(())
+}
+
+/// e.g. "temperature 0.6, top_p 0.95 (global default)"
+pub fn describe(sampling: &SamplingParams) -> String {
+    let value = |v: Option<f32>| match v {
+        Some(v) => v.to_string(),
+        None => "global default".to_string(),
+    };
+    format!(
+        "temperature {}, top_p {}",
+        value(sampling.temperature),
+        value(sampling.top_p)
+    )
+}
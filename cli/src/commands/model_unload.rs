@@ -0,0 +1,18 @@
+//! Unload a model from the daemon
+
+use anyhow::Result;
+
+use crate::daemon_client::DaemonClient;
+
+pub async fn execute(model: &str) -> Result<()> {
+    let client = DaemonClient::new();
+    let loaded = client.running_models().await?;
+    if !loaded.iter().any(|running| running.name == model) {
+        println!("{} is not loaded", model);
+        return Ok(());
+    }
+
+    client.unload(model).await?;
+    println!("Unloaded {}", model);
+    Ok(())
+}
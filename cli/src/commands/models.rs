@@ -1,7 +1,10 @@
 use anyhow::Result;
 use ohmygpu_core::ModelRegistry;
 
-pub async fn execute() -> Result<()> {
+use crate::daemon_client::{DaemonClient, RunningModel};
+
+/// List installed models; with `running`, mark the ones loaded in the daemon
+pub async fn execute(running: bool) -> Result<()> {
     let registry = ModelRegistry::load()?;
     let models = registry.list();
 
@@ -11,20 +14,97 @@ pub async fn execute() -> Result<()> {
         return Ok(());
     }
 
-    println!("{:<40} {:<12} {:<10} {}", "NAME", "TYPE", "SIZE", "DOWNLOADED");
-    println!("{}", "-".repeat(80));
+    // `None` when the daemon could not be asked, as opposed to nothing loaded
+    let loaded = if running {
+        let client = DaemonClient::new();
+        match client.running_models().await {
+            Ok(loaded) => Some(loaded),
+            Err(_) => {
+                println!(
+                    "Note: ohmygpu daemon is not running at {}; showing installed models only.",
+                    client.base_url()
+                );
+                println!("Start it with `omg serve`.\n");
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    match &loaded {
+        Some(_) => {
+            println!(
+                "{:<40} {:<12} {:<10} {:<12} {}",
+                "NAME", "TYPE", "SIZE", "DOWNLOADED", "STATUS"
+            );
+            println!("{}", "-".repeat(100));
+        }
+        None => {
+            println!(
+                "{:<40} {:<12} {:<10} {}",
+                "NAME", "TYPE", "SIZE", "DOWNLOADED"
+            );
+            println!("{}", "-".repeat(80));
+        }
+    }
 
     for model in models {
-        let size = format!("{:.2} GB", model.size_bytes as f64 / 1_073_741_824.0);
+        let name = if model.pinned {
+            format!("{} (pinned)", model.name)
+        } else {
+            model.name.clone()
+        };
+        let size = format_gb(model.size_bytes);
         let date = model.downloaded_at.format("%Y-%m-%d").to_string();
-        println!(
-            "{:<40} {:<12} {:<10} {}",
-            model.name,
-            model.model_type.as_str(),
-            size,
-            date
-        );
+        match &loaded {
+            Some(loaded) => {
+                let status = loaded
+                    .iter()
+                    .find(|m| m.name == model.name)
+                    .map(status)
+                    .unwrap_or_default();
+                println!(
+                    "{:<40} {:<12} {:<10} {:<12} {}",
+                    name,
+                    model.model_type.as_str(),
+                    size,
+                    date,
+                    status
+                );
+            }
+            None => println!(
+                "{:<40} {:<12} {:<10} {}",
+                name,
+                model.model_type.as_str(),
+                size,
+                date
+            ),
+        }
+    }
+
+    if loaded.as_ref().is_some_and(|loaded| loaded.is_empty()) {
+        println!("\nNo model is loaded in the daemon.");
     }
 
     Ok(())
 }
+
+/// e.g. "loaded (cuda:0, 2.05 GB VRAM)"
+fn status(model: &RunningModel) -> String {
+    if model.size_vram > 0 {
+        format!(
+            "loaded ({}, {} VRAM)",
+            model.device,
+            format_gb(model.size_vram)
+        )
+    } else if model.device.is_empty() {
+        "loaded".to_string()
+    } else {
+        format!("loaded ({})", model.device)
+    }
+}
+
+fn format_gb(bytes: u64) -> String {
+    format!("{:.2} GB", bytes as f64 / 1_073_741_824.0)
+}
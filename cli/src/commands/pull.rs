@@ -6,10 +6,15 @@ pub async fn execute(model: &str, file: Option<&str>) -> Result<()> {
     println!("Pulling model: {}", model);
 
     let downloader = HuggingFaceDownloader::new();
-    let model_info = downloader.download(model, file).await?;
+    let mut model_info = downloader.download(model, file).await?;
 
-    // Register the model
+    // Register the model, keeping sampling defaults and the pin from a
+    // previous pull
     let mut registry = ModelRegistry::load()?;
+    if let Some(existing) = registry.get(&model_info.name) {
+        model_info.sampling = existing.sampling.clone();
+        model_info.pinned = existing.pinned;
+    }
     registry.add(model_info.clone())?;
 
     println!("\nModel downloaded successfully!");
@@ -1,5 +1,5 @@
 use anyhow::Result;
-use ohmygpu_core::ModelRegistry;
+use ohmygpu_core::{ModelRegistry, ModelSource};
 use std::fs;
 
 pub async fn execute(model: &str) -> Result<()> {
@@ -10,8 +10,18 @@ pub async fn execute(model: &str) -> Result<()> {
 
     match model_info {
         Some(info) => {
-            // Remove files
-            if info.path.exists() {
+            if info.pinned {
+                anyhow::bail!(
+                    "Model '{}' is pinned; run `omg model unpin {}` first to remove it",
+                    model,
+                    model
+                );
+            }
+
+            // Local models were registered in place; their files are the user's
+            if matches!(info.source, ModelSource::Local) {
+                println!("Keeping local files at {:?}", info.path);
+            } else if info.path.exists() {
                 println!("Removing model files from {:?}...", info.path);
                 fs::remove_dir_all(&info.path)?;
             }
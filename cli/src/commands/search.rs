@@ -1,7 +1,12 @@
 use anyhow::Result;
 use ohmygpu_core::downloaders::HuggingFaceDownloader;
+use std::io::Write;
+
+pub async fn execute(query: &str, jsonl: bool) -> Result<()> {
+    if jsonl {
+        return execute_jsonl(query).await;
+    }
 
-pub async fn execute(query: &str) -> Result<()> {
     println!("Searching HuggingFace for: {}\n", query);
 
     let downloader = HuggingFaceDownloader::new();
@@ -25,6 +30,26 @@ pub async fn execute(query: &str) -> Result<()> {
     Ok(())
 }
 
+/// Print one JSON object per result, for piping into tools like `jq`
+async fn execute_jsonl(query: &str) -> Result<()> {
+    let downloader = HuggingFaceDownloader::new();
+    let results = downloader.search(query).await?;
+
+    let mut stdout = std::io::stdout().lock();
+    for model in results {
+        let line = serde_json::json!({
+            "id": model.id,
+            "downloads": model.downloads,
+            "likes": model.likes,
+            "pipeline_tag": model.pipeline_tag,
+        });
+        writeln!(stdout, "{}", line)?;
+        stdout.flush()?;
+    }
+
+    Ok(())
+}
+
 fn format_number(n: u64) -> String {
     if n >= 1_000_000 {
         format!("{:.1}M", n as f64 / 1_000_000.0)
@@ -1,13 +1,40 @@
 //! Daemon server management
 
-use crate::daemon;
+use crate::daemon_client::DaemonClient;
+use crate::{daemon, gpu};
 use anyhow::Result;
+use ohmygpu_core::Config;
+use ohmygpu_daemon::logging::LogFilter;
+use ohmygpu_daemon::{ConfigOverrides, ServerOptions};
 use std::net::SocketAddr;
 
-/// Start the daemon in foreground
-pub async fn execute(port: u16) -> Result<()> {
+/// Start the daemon in foreground, with `overrides` (from command-line
+/// flags) on top of the config file
+pub async fn execute(
+    port: u16,
+    overrides: ConfigOverrides,
+    watch: bool,
+    cors_dev: bool,
+    log_filter: LogFilter,
+) -> Result<()> {
     let addr: SocketAddr = format!("0.0.0.0:{}", port).parse()?;
 
+    let mut config = Config::load()?;
+    overrides.apply(&mut config);
+    config.validate()?;
+
+    let gpus = gpu::detect_all_gpus();
+    if !gpus.is_empty() && !gpus.iter().any(|g| g.index == config.inference.gpu_id) {
+        anyhow::bail!(
+            "GPU {} not found (available: {})",
+            config.inference.gpu_id,
+            gpus.iter()
+                .map(|g| g.index.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+
     // Write PID file for this process
     let pid = std::process::id();
     daemon::write_pid(pid)?;
@@ -20,17 +47,39 @@ pub async fn execute(port: u16) -> Result<()> {
     println!("Starting ohmygpu daemon (PID: {})...", pid);
     println!("Listening on http://{}", addr);
     println!();
+    println!("GPUs (using {}):", config.inference.gpu_id);
+    gpu::print_gpus(&gpus);
+    println!();
     println!("API endpoints:");
     println!("  OpenAI:  POST /v1/chat/completions");
     println!("  Ollama:  POST /api/chat");
     println!("  Models:  GET  /v1/models");
     println!("  Health:  GET  /health");
     println!();
+    if config.inference.chat_template.is_some() {
+        println!("Using custom chat template.");
+        println!();
+    }
+    if watch {
+        println!("Watching config.toml for changes.");
+        println!();
+    }
+    if cors_dev {
+        println!("Warning: --cors-dev lets any web page served from localhost call this API.");
+        println!("It is meant for front-end development; do not use it in production.");
+        println!();
+    }
     println!("Press Ctrl+C to stop.");
     println!();
 
     // Run server - cleanup on exit
-    let result = ohmygpu_daemon::run_server(addr).await;
+    let options = ServerOptions {
+        watch_config: watch,
+        overrides,
+        cors_dev,
+        log_filter: Some(log_filter),
+    };
+    let result = ohmygpu_daemon::run_server(addr, config, options).await;
     cleanup();
     result?;
 
@@ -76,24 +125,13 @@ pub async fn status() -> Result<()> {
         println!("URL: {}", status.url);
 
         // Get loaded models
-        let client = reqwest::Client::new();
-        if let Ok(response) = client
-            .get(format!("{}/v1/models", status.url))
-            .send()
-            .await
-        {
-            if let Ok(models) = response.json::<serde_json::Value>().await {
-                if let Some(data) = models["data"].as_array() {
-                    if data.is_empty() {
-                        println!("Models loaded: none");
-                    } else {
-                        println!("Models loaded:");
-                        for model in data {
-                            if let Some(id) = model["id"].as_str() {
-                                println!("  - {}", id);
-                            }
-                        }
-                    }
+        if let Ok(models) = DaemonClient::new().list_models().await {
+            if models.is_empty() {
+                println!("Models loaded: none");
+            } else {
+                println!("Models loaded:");
+                for id in models {
+                    println!("  - {}", id);
                 }
             }
         }
@@ -0,0 +1,74 @@
+//! `omg gen video`: text-to-video through `ohmygpu_runtime_video`
+
+use anyhow::Result;
+use ohmygpu_core::ModelRegistry;
+use ohmygpu_runtime_video::{load_model, VideoError, VideoGenRequest};
+use std::path::PathBuf;
+
+use super::generate::{free_path, resolve_output_path, save_image, select_device, Reporter};
+
+/// Generate a video and save its frames as `<output>/frame_00001.png`, ...
+pub async fn execute(
+    request: VideoGenRequest,
+    model: Option<&str>,
+    output: &str,
+    cpu: bool,
+) -> Result<()> {
+    println!("Video Generation");
+    println!("================");
+    println!("Prompt: {}", request.prompt);
+    println!("Size: {}x{}", request.width, request.height);
+    println!(
+        "Frames: {} at {} fps ({:.1}s)",
+        request.num_frames,
+        request.fps,
+        request.duration_secs()
+    );
+    println!("Steps: {}", request.steps);
+    println!();
+
+    let Some(model) = model else {
+        return Err(VideoError::NoVideoModels.into());
+    };
+    let model_path = resolve_model_path(model)?;
+    println!("Loading model from: {}", model_path.display());
+
+    let device = select_device(Reporter::default(), cpu)?;
+    let pipeline = load_model(&model_path, &device)?;
+    println!("Model loaded: {}", pipeline.name());
+
+    let start = std::time::Instant::now();
+    let response = pipeline.generate(&request)?;
+    println!(
+        "Generated {} frames in {:.2}s",
+        response.frames.len(),
+        start.elapsed().as_secs_f64()
+    );
+
+    // A directory of frames; an existing one is never written into
+    let dir = free_path(&resolve_output_path(output)?);
+    std::fs::create_dir_all(&dir)?;
+    for (index, pixels) in response.frames.iter().enumerate() {
+        let path = dir.join(format!("frame_{:05}.png", index + 1));
+        save_image(pixels, response.width, response.height, &path)?;
+    }
+    println!("Saved to: {} ({} fps)", dir.display(), response.fps);
+
+    Ok(())
+}
+
+/// A registered model or a directory on disk. Unlike images, nothing is
+/// downloaded: there is no video backend to check a download against.
+fn resolve_model_path(model: &str) -> Result<PathBuf> {
+    if let Some(info) = ModelRegistry::load()?.get(model) {
+        return Ok(info.path.clone());
+    }
+    let path = PathBuf::from(model);
+    if path.is_dir() {
+        return Ok(path);
+    }
+    anyhow::bail!(
+        "Model '{}' not found; pass a registered model name or a model directory",
+        model
+    )
+}
@@ -5,7 +5,7 @@ use std::fs;
 use std::path::PathBuf;
 use std::process::Command;
 
-const DAEMON_URL: &str = "http://localhost:10692";
+use crate::daemon_client::DaemonClient;
 
 /// Get the PID file path
 pub fn pid_file_path() -> Result<PathBuf> {
@@ -68,7 +68,7 @@ pub async fn get_status() -> DaemonStatus {
     let mut status = DaemonStatus {
         running: false,
         pid: None,
-        url: DAEMON_URL.to_string(),
+        url: DaemonClient::new().base_url().to_string(),
     };
 
     // Check PID file first
@@ -96,15 +96,7 @@ pub async fn get_status() -> DaemonStatus {
 
 /// Check daemon health via HTTP
 pub async fn check_health() -> bool {
-    let client = reqwest::Client::builder()
-        .timeout(std::time::Duration::from_secs(2))
-        .build()
-        .unwrap_or_default();
-
-    match client.get(format!("{}/health", DAEMON_URL)).send().await {
-        Ok(response) => response.status().is_success(),
-        Err(_) => false,
-    }
+    DaemonClient::new().health().await
 }
 
 /// Stop daemon by PID
@@ -0,0 +1,282 @@
+//! Typed HTTP client for the ohmygpu daemon
+
+use anyhow::{Context, Result};
+use futures_util::StreamExt;
+use ohmygpu_core::Config;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// How long a health check waits before treating the daemon as down
+const HEALTH_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChatMessage {
+    pub role: String,
+    pub content: String,
+}
+
+impl ChatMessage {
+    pub fn user(content: impl Into<String>) -> Self {
+        Self {
+            role: "user".to_string(),
+            content: content.into(),
+        }
+    }
+}
+
+/// A non-streaming chat completion
+#[derive(Debug, Clone)]
+pub struct ChatCompletion {
+    pub content: String,
+    pub completion_tokens: u32,
+    pub tokens_per_second: Option<f64>,
+}
+
+/// A model the daemon has in memory
+#[derive(Debug, Clone, Deserialize)]
+pub struct RunningModel {
+    pub name: String,
+    /// Bytes of the model held in GPU memory
+    #[serde(default)]
+    pub size_vram: u64,
+    #[serde(default)]
+    pub device: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct DaemonClient {
+    client: reqwest::Client,
+    base_url: String,
+}
+
+impl DaemonClient {
+    /// Client for the daemon address in the user's config
+    pub fn new() -> Self {
+        Self::from_config(&Config::load().unwrap_or_default())
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        // A daemon listening on all interfaces is still reached via loopback
+        let host = match config.daemon.host.as_str() {
+            "0.0.0.0" | "::" => "127.0.0.1",
+            host => host,
+        };
+
+        Self {
+            client: reqwest::Client::new(),
+            base_url: format!("http://{}:{}", host, config.daemon.port),
+        }
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    /// Whether the daemon is up and answering health checks
+    pub async fn health(&self) -> bool {
+        match self
+            .client
+            .get(self.url("/health"))
+            .timeout(HEALTH_TIMEOUT)
+            .send()
+            .await
+        {
+            Ok(response) => response.status().is_success(),
+            Err(_) => false,
+        }
+    }
+
+    /// Ids of the models the daemon can serve
+    pub async fn list_models(&self) -> Result<Vec<String>> {
+        #[derive(Deserialize)]
+        struct ModelsResponse {
+            data: Vec<ModelObject>,
+        }
+
+        #[derive(Deserialize)]
+        struct ModelObject {
+            id: String,
+        }
+
+        let response = self.send(self.client.get(self.url("/v1/models"))).await?;
+        let models: ModelsResponse = response
+            .json()
+            .await
+            .context("Failed to parse model list")?;
+        Ok(models.data.into_iter().map(|m| m.id).collect())
+    }
+
+    pub async fn chat(&self, model: &str, messages: &[ChatMessage]) -> Result<ChatCompletion> {
+        let request = serde_json::json!({
+            "model": model,
+            "messages": messages,
+            "stream": false
+        });
+
+        let response = self
+            .send(
+                self.client
+                    .post(self.url("/v1/chat/completions"))
+                    .json(&request),
+            )
+            .await?;
+        let result: serde_json::Value = response
+            .json()
+            .await
+            .context("Failed to parse chat response")?;
+
+        Ok(ChatCompletion {
+            content: result["choices"][0]["message"]["content"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+            completion_tokens: result["usage"]["completion_tokens"].as_u64().unwrap_or(0) as u32,
+            tokens_per_second: result["timings"]["tokens_per_second"].as_f64(),
+        })
+    }
+
+    /// Stream a chat completion; the receiver yields text deltas as they arrive.
+    pub async fn chat_stream(
+        &self,
+        model: &str,
+        messages: &[ChatMessage],
+    ) -> Result<tokio::sync::mpsc::Receiver<Result<String>>> {
+        let request = serde_json::json!({
+            "model": model,
+            "messages": messages,
+            "stream": true
+        });
+
+        let response = self
+            .send(
+                self.client
+                    .post(self.url("/v1/chat/completions"))
+                    .json(&request),
+            )
+            .await?;
+
+        let (tx, rx) = tokio::sync::mpsc::channel(100);
+        tokio::spawn(async move {
+            let mut bytes = response.bytes_stream();
+            let mut buffer = String::new();
+
+            while let Some(chunk) = bytes.next().await {
+                let chunk = match chunk {
+                    Ok(chunk) => chunk,
+                    Err(e) => {
+                        let _ = tx.send(Err(e.into())).await;
+                        return;
+                    }
+                };
+                buffer.push_str(&String::from_utf8_lossy(&chunk));
+
+                // Handle every complete SSE line received so far
+                while let Some(newline) = buffer.find('\n') {
+                    let line = buffer[..newline].trim().to_string();
+                    buffer.drain(..=newline);
+
+                    let Some(data) = line.strip_prefix("data:") else {
+                        continue;
+                    };
+                    let data = data.trim();
+                    if data == "[DONE]" {
+                        return;
+                    }
+
+                    let Ok(event) = serde_json::from_str::<serde_json::Value>(data) else {
+                        continue;
+                    };
+                    if let Some(content) = event["choices"][0]["delta"]["content"].as_str() {
+                        if tx.send(Ok(content.to_string())).await.is_err() {
+                            return;
+                        }
+                    }
+                }
+            }
+        });
+
+        Ok(rx)
+    }
+
+    /// Embed each input with an embedding model, in input order
+    pub async fn embed(&self, model: &str, inputs: &[String]) -> Result<Vec<Vec<f32>>> {
+        #[derive(Deserialize)]
+        struct EmbeddingList {
+            data: Vec<EmbeddingObject>,
+        }
+
+        #[derive(Deserialize)]
+        struct EmbeddingObject {
+            index: usize,
+            embedding: Vec<f32>,
+        }
+
+        let request = serde_json::json!({ "model": model, "input": inputs });
+        let response = self
+            .send(self.client.post(self.url("/v1/embeddings")).json(&request))
+            .await?;
+        let mut list: EmbeddingList = response
+            .json()
+            .await
+            .context("Failed to parse embedding response")?;
+
+        list.data.sort_by_key(|e| e.index);
+        Ok(list.data.into_iter().map(|e| e.embedding).collect())
+    }
+
+    /// Models currently loaded in the daemon
+    pub async fn running_models(&self) -> Result<Vec<RunningModel>> {
+        #[derive(Deserialize)]
+        struct PsResponse {
+            models: Vec<RunningModel>,
+        }
+
+        let response = self
+            .send(self.client.get(self.url("/api/ps")).timeout(HEALTH_TIMEOUT))
+            .await?;
+        let ps: PsResponse = response
+            .json()
+            .await
+            .context("Failed to parse running models")?;
+        Ok(ps.models)
+    }
+
+    /// Load a model into memory without generating
+    pub async fn load(&self, model: &str) -> Result<()> {
+        let request = serde_json::json!({ "model": model, "prompt": "" });
+        self.send(self.client.post(self.url("/api/generate")).json(&request))
+            .await?;
+        Ok(())
+    }
+
+    /// Unload a model if it is the one in memory
+    pub async fn unload(&self, model: &str) -> Result<()> {
+        let request = serde_json::json!({ "model": model, "prompt": "", "keep_alive": 0 });
+        self.send(self.client.post(self.url("/api/generate")).json(&request))
+            .await?;
+        Ok(())
+    }
+
+    /// Send a request, turning connection failures and error statuses into
+    /// readable errors
+    async fn send(&self, request: reqwest::RequestBuilder) -> Result<reqwest::Response> {
+        let response = request.send().await.with_context(|| {
+            format!(
+                "Failed to connect to ohmygpu daemon at {}. Is it running? Start it with `omg serve`",
+                self.base_url
+            )
+        })?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            anyhow::bail!("Daemon returned {}: {}", status, body);
+        }
+
+        Ok(response)
+    }
+}
@@ -3,6 +3,8 @@ use std::process::Command;
 
 #[derive(Debug, Clone)]
 pub struct GpuInfo {
+    /// Device index as used by the backend (and `--gpu N`)
+    pub index: u32,
     pub backend: GpuBackend,
     pub vram_mb: u64,
     pub name: String,
@@ -13,6 +15,8 @@ pub struct GpuInfo {
 pub enum GpuBackend {
     Metal,
     Cuda,
+    /// Detected via Vulkan; inference currently runs on CPU
+    Vulkan,
     None,
 }
 
@@ -21,41 +25,97 @@ impl std::fmt::Display for GpuBackend {
         match self {
             GpuBackend::Metal => write!(f, "Metal"),
             GpuBackend::Cuda => write!(f, "CUDA"),
+            GpuBackend::Vulkan => write!(f, "Vulkan"),
             GpuBackend::None => write!(f, "None"),
         }
     }
 }
 
-const MIN_VRAM_MB: u64 = 8 * 1024; // 8GB
-
+/// Detect the best available GPU (the one with the most memory)
 pub fn detect_gpu() -> GpuInfo {
-    // Check compile-time feature flags first
+    detect_all_gpus()
+        .into_iter()
+        .max_by_key(|gpu| gpu.vram_mb)
+        .unwrap_or_else(|| GpuInfo {
+            index: 0,
+            backend: GpuBackend::None,
+            vram_mb: 0,
+            name: "No GPU".to_string(),
+        })
+}
+
+/// Detect all usable GPUs for the compiled-in backend
+pub fn detect_all_gpus() -> Vec<GpuInfo> {
+    #[allow(unused_mut)]
+    let mut gpus = Vec::new();
+
     #[cfg(feature = "metal")]
-    {
-        if let Some(info) = detect_metal() {
-            return info;
-        }
-    }
+    gpus.extend(detect_metal());
 
     #[cfg(feature = "cuda")]
-    {
-        if let Some(info) = detect_cuda() {
-            return info;
+    gpus.extend(detect_cuda());
+
+    #[cfg(feature = "vulkan")]
+    gpus.extend(detect_vulkan());
+
+    gpus
+}
+
+/// Print detected GPUs with their indices
+pub fn print_gpus(gpus: &[GpuInfo]) {
+    if gpus.is_empty() {
+        println!("  (no GPU detected)");
+    }
+    for gpu in gpus {
+        if gpu.vram_mb == 0 {
+            println!("  [{}] {} ({})", gpu.index, gpu.name, gpu.backend);
+            continue;
         }
+        println!(
+            "  [{}] {} ({}, {:.1} GB)",
+            gpu.index,
+            gpu.name,
+            gpu.backend,
+            gpu.vram_mb as f64 / 1024.0
+        );
     }
+}
 
-    // No GPU detected
-    GpuInfo {
-        backend: GpuBackend::None,
-        vram_mb: 0,
-        name: "No GPU".to_string(),
+#[cfg(feature = "metal")]
+fn detect_metal() -> Option<GpuInfo> {
+    // On Apple Silicon, GPU memory is unified with system RAM, but the GPU
+    // may only use part of it. Prefer Metal's own working-set limit.
+    let vram_mb = metal_working_set_mb().or_else(|| {
+        // macOS caps GPU allocations at roughly 2/3-3/4 of system RAM
+        system_memory_mb().map(|mb| mb * 2 / 3)
+    })?;
+
+    // Get chip name from system_profiler
+    let name = get_apple_chip_name().unwrap_or_else(|| "Apple Silicon".to_string());
+
+    Some(GpuInfo {
+        index: 0,
+        backend: GpuBackend::Metal,
+        vram_mb,
+        name,
+    })
+}
+
+/// Metal's recommended max working set size, i.e. how much memory the GPU
+/// can use without hurting performance
+#[cfg(feature = "metal")]
+fn metal_working_set_mb() -> Option<u64> {
+    match candle_core::Device::new_metal(0).ok()? {
+        candle_core::Device::Metal(device) => {
+            let bytes = device.recommended_max_working_set_size() as u64;
+            (bytes > 0).then_some(bytes / (1024 * 1024))
+        }
+        _ => None,
     }
 }
 
 #[cfg(feature = "metal")]
-fn detect_metal() -> Option<GpuInfo> {
-    // On Apple Silicon, GPU memory is unified with system RAM
-    // Use sysctl to get total memory
+fn system_memory_mb() -> Option<u64> {
     let output = Command::new("sysctl")
         .args(["-n", "hw.memsize"])
         .output()
@@ -69,16 +129,7 @@ fn detect_metal() -> Option<GpuInfo> {
         .trim()
         .parse()
         .ok()?;
-    let vram_mb = mem_bytes / (1024 * 1024);
-
-    // Get chip name from system_profiler
-    let name = get_apple_chip_name().unwrap_or_else(|| "Apple Silicon".to_string());
-
-    Some(GpuInfo {
-        backend: GpuBackend::Metal,
-        vram_mb,
-        name,
-    })
+    Some(mem_bytes / (1024 * 1024))
 }
 
 #[cfg(feature = "metal")]
@@ -96,38 +147,105 @@ fn get_apple_chip_name() -> Option<String> {
 }
 
 #[cfg(feature = "cuda")]
-fn detect_cuda() -> Option<GpuInfo> {
-    // Use nvidia-smi to get GPU info
-    let output = Command::new("nvidia-smi")
+fn detect_cuda() -> Vec<GpuInfo> {
+    // Use nvidia-smi to get info for every device
+    let output = match Command::new("nvidia-smi")
         .args([
-            "--query-gpu=name,memory.total",
+            "--query-gpu=index,name,memory.total",
             "--format=csv,noheader,nounits",
         ])
         .output()
-        .ok()?;
+    {
+        Ok(output) if output.status.success() => output,
+        _ => return Vec::new(),
+    };
 
-    if !output.status.success() {
-        return None;
-    }
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(parse_nvidia_smi_line)
+        .collect()
+}
 
-    let output_str = String::from_utf8_lossy(&output.stdout);
-    let line = output_str.lines().next()?;
+#[cfg(feature = "cuda")]
+fn parse_nvidia_smi_line(line: &str) -> Option<GpuInfo> {
     let parts: Vec<&str> = line.split(", ").collect();
 
-    if parts.len() < 2 {
+    if parts.len() < 3 {
         return None;
     }
 
-    let name = parts[0].trim().to_string();
-    let vram_mb: u64 = parts[1].trim().parse().ok()?;
+    let index: u32 = parts[0].trim().parse().ok()?;
+    let name = parts[1].trim().to_string();
+    let vram_mb: u64 = parts[2].trim().parse().ok()?;
 
     Some(GpuInfo {
+        index,
         backend: GpuBackend::Cuda,
         vram_mb,
         name,
     })
 }
 
+#[cfg(feature = "vulkan")]
+fn detect_vulkan() -> Vec<GpuInfo> {
+    let output = match Command::new("vulkaninfo").arg("--summary").output() {
+        Ok(output) if output.status.success() => output,
+        _ => return Vec::new(),
+    };
+
+    parse_vulkaninfo_summary(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Parse the "Devices" section of `vulkaninfo --summary`:
+///
+/// ```text
+/// GPU0:
+///     deviceType         = PHYSICAL_DEVICE_TYPE_DISCRETE_GPU
+///     deviceName         = AMD Radeon RX 6800 XT (RADV NAVI21)
+/// ```
+#[cfg(feature = "vulkan")]
+fn parse_vulkaninfo_summary(summary: &str) -> Vec<GpuInfo> {
+    let mut gpus = Vec::new();
+    let mut current: Option<(u32, Option<String>, bool)> = None;
+
+    let mut finish = |current: Option<(u32, Option<String>, bool)>| {
+        // Skip software rasterizers such as llvmpipe
+        if let Some((index, Some(name), false)) = current {
+            gpus.push(GpuInfo {
+                index,
+                backend: GpuBackend::Vulkan,
+                // Not reported by the summary
+                vram_mb: 0,
+                name,
+            });
+        }
+    };
+
+    for line in summary.lines().map(str::trim) {
+        if let Some(index) = line
+            .strip_prefix("GPU")
+            .and_then(|rest| rest.strip_suffix(':'))
+            .and_then(|index| index.parse().ok())
+        {
+            finish(current.take());
+            current = Some((index, None, false));
+            continue;
+        }
+
+        let (Some(gpu), Some((key, value))) = (current.as_mut(), line.split_once('=')) else {
+            continue;
+        };
+        match key.trim() {
+            "deviceName" => gpu.1 = Some(value.trim().to_string()),
+            "deviceType" => gpu.2 = value.trim() == "PHYSICAL_DEVICE_TYPE_CPU",
+            _ => {}
+        }
+    }
+    finish(current);
+
+    gpus
+}
+
 #[allow(dead_code)]
 pub enum GpuCheckResult {
     /// GPU meets all requirements
@@ -138,24 +256,34 @@ pub enum GpuCheckResult {
     NoGpu,
 }
 
-pub fn check_gpu_requirements() -> GpuCheckResult {
+/// Estimate the GPU memory needed to run a model from its weights size
+pub fn estimate_vram_mb(weights_bytes: u64) -> u64 {
+    // Weights plus ~20% for activations, KV cache and runtime overhead
+    weights_bytes / (1024 * 1024) * 6 / 5
+}
+
+pub fn check_gpu_requirements(min_vram_mb: u64) -> GpuCheckResult {
     let info = detect_gpu();
 
     match info.backend {
         GpuBackend::None => GpuCheckResult::NoGpu,
-        _ if info.vram_mb < MIN_VRAM_MB => GpuCheckResult::LowVram(info),
+        // Memory is unknown and inference runs on CPU, so there is nothing
+        // meaningful to check yet
+        GpuBackend::Vulkan => GpuCheckResult::Ok(info),
+        _ if info.vram_mb < min_vram_mb => GpuCheckResult::LowVram(info),
         _ => GpuCheckResult::Ok(info),
     }
 }
 
-pub fn prompt_low_vram_confirmation(info: &GpuInfo) -> Result<bool> {
+pub fn prompt_low_vram_confirmation(info: &GpuInfo, min_vram_mb: u64) -> Result<bool> {
     use dialoguer::Confirm;
 
     println!();
-    println!("  GPU: {} ({})", info.name, info.backend);
+    println!("  GPU {}: {} ({})", info.index, info.name, info.backend);
     println!(
-        "  VRAM: {:.1} GB (minimum recommended: 8 GB)",
-        info.vram_mb as f64 / 1024.0
+        "  VRAM: {:.1} GB (recommended: {:.1} GB)",
+        info.vram_mb as f64 / 1024.0,
+        min_vram_mb as f64 / 1024.0
     );
     println!();
 
@@ -174,7 +302,11 @@ pub fn print_no_gpu_error() {
     eprintln!("  omg requires one of the following:");
     eprintln!("    - Apple Silicon Mac with Metal support");
     eprintln!("    - NVIDIA GPU with CUDA support");
+    eprintln!("    - Vulkan-capable GPU (experimental, build with --features vulkan)");
     eprintln!();
     eprintln!("  With at least 8 GB of GPU memory (recommended).");
     eprintln!();
+    eprintln!("  To skip this check (e.g. in CI), pass --no-gpu-check.");
+    eprintln!("  Inference may then be slow or unsupported.");
+    eprintln!();
 }
@@ -1,9 +1,15 @@
 mod commands;
 mod daemon;
+mod daemon_client;
 mod gpu;
 
 use anyhow::Result;
 use clap::{Parser, Subcommand};
+use commands::generate::GenImageArgs;
+use ohmygpu_core::{Config, ModelRegistry};
+use ohmygpu_daemon::ConfigOverrides;
+use ohmygpu_runtime_video::VideoGenRequest;
+use std::path::PathBuf;
 
 #[derive(Parser)]
 #[command(name = "ohmygpu")]
@@ -11,6 +17,11 @@ use clap::{Parser, Subcommand};
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+
+    /// Skip the startup GPU check (for CI and containers). Inference may be
+    /// slow or unsupported without a GPU.
+    #[arg(long, global = true)]
+    no_gpu_check: bool,
 }
 
 #[derive(Subcommand)]
@@ -33,6 +44,40 @@ enum Commands {
         /// Port to listen on
         #[arg(short, long, default_value = "10692")]
         port: u16,
+
+        /// GPU device index to use (see the GPU list printed at startup)
+        #[arg(long)]
+        gpu: Option<u32>,
+
+        /// Chat template overriding the built-in prompt format
+        /// (format string with {system}/{user}/{assistant}, or a file path)
+        #[arg(long)]
+        template: Option<String>,
+
+        /// Context window for chat models (prompt + completion tokens),
+        /// below the model's maximum to save memory
+        #[arg(long)]
+        ctx: Option<usize>,
+
+        /// Weight dtype for chat models, instead of the one picked for the
+        /// device and architecture
+        #[arg(long, value_parser = ["f16", "bf16", "f32"])]
+        dtype: Option<String>,
+
+        /// Cap on the tokens (prompt + max_tokens) of all chat requests
+        /// being processed; further requests wait for capacity
+        #[arg(long)]
+        max_batch_total_tokens: Option<u64>,
+
+        /// Reload config.toml when it changes (request defaults and the log
+        /// level apply live, model settings on the next load)
+        #[arg(long)]
+        watch: bool,
+
+        /// Allow browser requests from any localhost origin, for front-end
+        /// development (not for production)
+        #[arg(long)]
+        cors_dev: bool,
     },
 
     /// Generate content (image, video, audio)
@@ -45,13 +90,44 @@ enum Commands {
     Chat {
         /// Model to chat with
         model: String,
+
+        /// Wait for each complete reply instead of streaming it
+        /// (also prints token count and speed)
+        #[arg(long)]
+        no_stream: bool,
+    },
+
+    /// Embed text with an embedding model (via the daemon)
+    Embed {
+        /// Embedding model to use
+        #[arg(short, long)]
+        model: String,
+
+        /// Texts to embed, or the candidates to rank with --similar-to
+        #[arg(required = true)]
+        texts: Vec<String>,
+
+        /// Rank the texts by cosine similarity to this query
+        #[arg(long)]
+        similar_to: Option<String>,
+
+        /// Show only the N most similar texts
+        #[arg(long, requires = "similar_to")]
+        top: Option<usize>,
     },
 
     /// Start MCP server for Claude Desktop integration
     Mcp,
 
+    /// Diagnose common setup problems (GPU, config, disk, daemon, tokens)
+    Doctor,
+
     /// View or set configuration
+    #[command(args_conflicts_with_subcommands = true)]
     Config {
+        #[command(subcommand)]
+        action: Option<ConfigCommands>,
+
         /// Config key (e.g., "daemon.port", "inference.temperature")
         key: Option<String>,
 
@@ -63,6 +139,10 @@ enum Commands {
     Search {
         /// Search query
         query: String,
+
+        /// Print one JSON object per line instead of a table
+        #[arg(long)]
+        jsonl: bool,
     },
 
     /// Self-update to the latest version
@@ -73,7 +153,11 @@ enum Commands {
 enum ModelCommands {
     /// List installed models
     #[command(alias = "ls")]
-    List,
+    List {
+        /// Mark models loaded in the daemon, with their VRAM use
+        #[arg(long)]
+        running: bool,
+    },
 
     /// Pull/download a model from HuggingFace
     Pull {
@@ -98,8 +182,89 @@ enum ModelCommands {
         model: String,
     },
 
+    /// Protect a model from `model rm` and `model gc`
+    Pin {
+        /// Model name
+        model: String,
+    },
+
+    /// Allow a pinned model to be removed again
+    Unpin {
+        /// Model name
+        model: String,
+    },
+
+    /// Free the memory of a model loaded in the daemon, keeping the
+    /// daemon running
+    Unload {
+        /// Model name
+        model: String,
+    },
+
+    /// Set default sampling parameters used when a request omits them
+    SetParams {
+        /// Model name
+        model: String,
+
+        /// Default temperature
+        #[arg(long = "temp")]
+        temperature: Option<f32>,
+
+        /// Default nucleus sampling threshold
+        #[arg(long)]
+        top_p: Option<f32>,
+
+        /// Remove the model's defaults (applied before --temp/--top-p)
+        #[arg(long)]
+        clear: bool,
+    },
+
     /// Garbage collect unused cache files
     Gc,
+
+    /// Package a model and its metadata into a tarball
+    Export {
+        /// Model name
+        model: String,
+
+        /// Output file (e.g. phi-2.tar)
+        output: PathBuf,
+    },
+
+    /// Register a model directory that is already on disk (files are
+    /// used in place, not copied)
+    ImportDir {
+        /// Model directory (containing config.json, weights, ...)
+        path: PathBuf,
+
+        /// Name to register it under (default: the directory name)
+        #[arg(long)]
+        name: Option<String>,
+
+        /// Model type (llm, embedding, image-gen, ...; default: detected)
+        #[arg(long = "type")]
+        model_type: Option<String>,
+    },
+
+    /// Install a model from a tarball created by `omg model export`
+    Import {
+        /// Tarball to import
+        file: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigCommands {
+    /// Open config.toml in $EDITOR, re-opening it until the TOML is valid
+    Edit,
+
+    /// Back up config.toml to config.toml.bak (or .bak.2, ...) and start over
+    /// with defaults
+    Reset {
+        /// Keep the HuggingFace token (models.hf_token)
+        #[arg(long)]
+        keep_token: bool,
+    },
 }
 
 #[derive(Subcommand)]
@@ -114,28 +279,39 @@ enum ServeCommands {
 #[derive(Subcommand)]
 enum GenCommands {
     /// Generate an image from a text prompt
-    Image {
-        /// Text prompt for image generation
+    Image(GenImageArgs),
+
+    /// Generate a video (no video backends are available yet)
+    Video {
+        /// Text prompt
         prompt: String,
 
-        /// Model to use
-        #[arg(short, long, default_value = "Tongyi-MAI/Z-Image-Turbo")]
-        model: String,
+        /// Video model (registry name or model directory)
+        #[arg(short, long)]
+        model: Option<String>,
 
-        /// Output file path
-        #[arg(short, long, default_value = "output.png")]
+        /// Output directory for the frames (frame_00001.png, ...)
+        #[arg(short, long, default_value = "video")]
         output: String,
 
-        /// Image width in pixels
-        #[arg(long, default_value_t = 1024)]
+        /// Frame width in pixels
+        #[arg(long, default_value_t = 832)]
         width: u32,
 
-        /// Image height in pixels
-        #[arg(long, default_value_t = 1024)]
+        /// Frame height in pixels
+        #[arg(long, default_value_t = 480)]
         height: u32,
 
+        /// Number of frames to generate
+        #[arg(long, default_value_t = 49, value_parser = clap::value_parser!(u32).range(1..))]
+        frames: u32,
+
+        /// Frames per second
+        #[arg(long, default_value_t = 16, value_parser = clap::value_parser!(u32).range(1..))]
+        fps: u32,
+
         /// Number of inference steps
-        #[arg(short, long, default_value_t = 9)]
+        #[arg(short, long, default_value_t = 30)]
         steps: u32,
 
         /// Guidance scale for CFG
@@ -146,7 +322,7 @@ enum GenCommands {
         #[arg(long)]
         negative_prompt: Option<String>,
 
-        /// Random seed for reproducibility
+        /// Seed for reproducibility
         #[arg(long)]
         seed: Option<u64>,
 
@@ -154,23 +330,12 @@ enum GenCommands {
         #[arg(long)]
         cpu: bool,
     },
-
-    /// Generate a video (coming soon)
-    Video {
-        /// Text prompt
-        prompt: String,
-    },
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Initialize logging
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::from_default_env()
-                .add_directive(tracing::Level::INFO.into()),
-        )
-        .init();
+    // Initialize logging; `serve` applies daemon.log_level
+    let log_filter = ohmygpu_daemon::logging::init();
 
     let cli = Cli::parse();
 
@@ -179,27 +344,43 @@ async fn main() -> Result<()> {
         return commands::mcp::execute().await;
     }
 
+    // Doctor reports GPU problems itself instead of refusing to start
+    if matches!(cli.command, Commands::Doctor) {
+        return commands::doctor::execute().await;
+    }
+
     // Check GPU requirements at startup
-    match gpu::check_gpu_requirements() {
-        gpu::GpuCheckResult::NoGpu => {
-            gpu::print_no_gpu_error();
-            std::process::exit(1);
-        }
-        gpu::GpuCheckResult::LowVram(info) => {
-            if !gpu::prompt_low_vram_confirmation(&info)? {
-                std::process::exit(0);
+    if cli.no_gpu_check {
+        tracing::warn!("GPU check skipped; inference may be slow or unsupported");
+    } else {
+        let min_vram_mb = required_vram_mb(&cli.command);
+        match gpu::check_gpu_requirements(min_vram_mb) {
+            gpu::GpuCheckResult::NoGpu => {
+                gpu::print_no_gpu_error();
+                std::process::exit(1);
+            }
+            gpu::GpuCheckResult::LowVram(info) => {
+                if !gpu::prompt_low_vram_confirmation(&info, min_vram_mb)? {
+                    std::process::exit(0);
+                }
+            }
+            gpu::GpuCheckResult::Ok(info) if info.backend == gpu::GpuBackend::Vulkan => {
+                tracing::warn!(
+                    "Vulkan GPU detected ({}); no Vulkan compute backend yet, inference runs on CPU",
+                    info.name
+                );
+            }
+            gpu::GpuCheckResult::Ok(_) => {
+                // GPU meets requirements, continue
             }
-        }
-        gpu::GpuCheckResult::Ok(_) => {
-            // GPU meets requirements, continue
         }
     }
 
     match cli.command {
         // Model management
         Commands::Model { action } => match action {
-            ModelCommands::List => {
-                commands::models::execute().await?;
+            ModelCommands::List { running } => {
+                commands::models::execute(running).await?;
             }
             ModelCommands::Pull { model, file } => {
                 commands::pull::execute(&model, file.as_deref()).await?;
@@ -210,19 +391,68 @@ async fn main() -> Result<()> {
             ModelCommands::Info { model } => {
                 commands::model_info::execute(&model).await?;
             }
+            ModelCommands::Pin { model } => {
+                commands::model_pin::execute(&model, true).await?;
+            }
+            ModelCommands::Unpin { model } => {
+                commands::model_pin::execute(&model, false).await?;
+            }
+            ModelCommands::Unload { model } => {
+                commands::model_unload::execute(&model).await?;
+            }
+            ModelCommands::SetParams {
+                model,
+                temperature,
+                top_p,
+                clear,
+            } => {
+                commands::model_set_params::execute(&model, temperature, top_p, clear).await?;
+            }
             ModelCommands::Gc => {
                 commands::model_gc::execute().await?;
             }
+            ModelCommands::ImportDir {
+                path,
+                name,
+                model_type,
+            } => {
+                commands::model_import_dir::execute(&path, name.as_deref(), model_type.as_deref())
+                    .await?;
+            }
+            ModelCommands::Export { model, output } => {
+                commands::model_export::export(&model, &output).await?;
+            }
+            ModelCommands::Import { file } => {
+                commands::model_export::import(&file).await?;
+            }
         },
 
         // Serve daemon
-        Commands::Serve { action, daemon, port } => match action {
+        Commands::Serve {
+            action,
+            daemon,
+            port,
+            gpu,
+            template,
+            ctx,
+            dtype,
+            max_batch_total_tokens,
+            watch,
+            cors_dev,
+        } => match action {
             None => {
                 // Start server
                 if daemon {
                     commands::serve::execute_background(port).await?;
                 } else {
-                    commands::serve::execute(port).await?;
+                    let overrides = ConfigOverrides {
+                        gpu_id: gpu,
+                        chat_template: template,
+                        context_length: ctx,
+                        dtype,
+                        max_batch_total_tokens,
+                    };
+                    commands::serve::execute(port, overrides, watch, cors_dev, log_filter).await?;
                 }
             }
             Some(ServeCommands::Status) => {
@@ -235,53 +465,71 @@ async fn main() -> Result<()> {
 
         // Generate content
         Commands::Gen { action } => match action {
-            GenCommands::Image {
+            GenCommands::Image(args) => {
+                commands::generate::execute(args).await?;
+            }
+            GenCommands::Video {
                 prompt,
                 model,
                 output,
                 width,
                 height,
+                frames,
+                fps,
                 steps,
                 guidance_scale,
                 negative_prompt,
                 seed,
                 cpu,
             } => {
-                commands::generate::execute(
-                    &model,
-                    &prompt,
-                    &output,
+                let request = VideoGenRequest {
+                    prompt,
+                    negative_prompt,
                     width,
                     height,
+                    num_frames: frames,
+                    fps,
                     steps,
                     guidance_scale,
-                    negative_prompt.as_deref(),
                     seed,
-                    cpu,
-                )
-                .await?;
-            }
-            GenCommands::Video { prompt: _ } => {
-                println!("Video generation coming soon!");
+                };
+                commands::video::execute(request, model.as_deref(), &output, cpu).await?;
             }
         },
 
         // Interactive chat
-        Commands::Chat { model } => {
-            commands::chat::execute(&model).await?;
+        Commands::Chat { model, no_stream } => {
+            commands::chat::execute(&model, !no_stream).await?;
+        }
+
+        Commands::Embed {
+            model,
+            texts,
+            similar_to,
+            top,
+        } => {
+            commands::embed::execute(&model, &texts, similar_to.as_deref(), top).await?;
         }
 
         // MCP (handled above with early return)
-        Commands::Mcp => unreachable!(),
+        Commands::Mcp | Commands::Doctor => unreachable!(),
 
         // Config
-        Commands::Config { key, value } => {
-            commands::config::execute(key.as_deref(), value.as_deref()).await?;
-        }
+        Commands::Config { action, key, value } => match action {
+            Some(ConfigCommands::Edit) => {
+                commands::config::edit().await?;
+            }
+            Some(ConfigCommands::Reset { keep_token }) => {
+                commands::config::reset(keep_token).await?;
+            }
+            None => {
+                commands::config::execute(key.as_deref(), value.as_deref()).await?;
+            }
+        },
 
         // Search
-        Commands::Search { query } => {
-            commands::search::execute(&query).await?;
+        Commands::Search { query, jsonl } => {
+            commands::search::execute(&query, jsonl).await?;
         }
 
         // Update
@@ -292,3 +540,27 @@ async fn main() -> Result<()> {
 
     Ok(())
 }
+
+/// GPU memory the command needs: the estimate for the model it names when
+/// that model is installed, otherwise the configured minimum.
+fn required_vram_mb(command: &Commands) -> u64 {
+    let config = Config::load().unwrap_or_default();
+
+    let model = match command {
+        Commands::Chat { model, .. } | Commands::Embed { model, .. } => Some(model),
+        Commands::Gen {
+            action: GenCommands::Image(args),
+        } => Some(&args.model),
+        _ => None,
+    };
+
+    model
+        .and_then(|model| {
+            let registry = ModelRegistry::load().ok()?;
+            let info = registry
+                .get(model)
+                .or_else(|| registry.get(&model.replace('/', "--")))?;
+            (info.size_bytes > 0).then(|| gpu::estimate_vram_mb(info.size_bytes))
+        })
+        .unwrap_or(config.daemon.min_vram_mb)
+}
@@ -1,7 +1,7 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
@@ -27,6 +27,78 @@ pub struct DaemonConfig {
     /// Host to bind to
     #[serde(default = "default_host")]
     pub host: String,
+
+    /// GPU memory (MB) below which the CLI asks for confirmation, when the
+    /// command doesn't name a model whose requirement can be estimated
+    #[serde(default = "default_min_vram_mb")]
+    pub min_vram_mb: u64,
+
+    /// Append every completed request and response to a JSONL transcript.
+    /// Prompts may contain personal data, so this is off by default.
+    #[serde(default)]
+    pub log_transcripts: bool,
+
+    /// Transcript file (default: ~/.config/ohmygpu/logs/transcripts.jsonl)
+    #[serde(default)]
+    pub transcript_path: Option<PathBuf>,
+
+    /// Size (MB) at which the transcript is rotated to `<file>.1`
+    #[serde(default = "default_transcript_max_mb")]
+    pub transcript_max_mb: u64,
+
+    /// Cap on the tokens per second streamed to each client; unset streams
+    /// as fast as the model generates
+    #[serde(default)]
+    pub max_tokens_per_second: Option<f64>,
+
+    /// Generation requests a single client (IP address) may have in
+    /// flight; further requests get 429. Unset is unlimited.
+    #[serde(default)]
+    pub max_concurrent_requests_per_client: Option<u32>,
+
+    /// Cap on the tokens (prompt + max_tokens) of all chat requests being
+    /// processed at once; requests that would exceed it wait until enough
+    /// finish. Unset is unlimited.
+    #[serde(default)]
+    pub max_batch_total_tokens: Option<u64>,
+
+    /// Directory for generated images served at `/images/<id>.png`
+    /// (default: `ohmygpu-images` in the system temp directory)
+    #[serde(default)]
+    pub image_dir: Option<PathBuf>,
+
+    /// Base of the image URLs handed to clients (default:
+    /// `http://<host>:<port>`); set it when clients reach the daemon
+    /// through a proxy or another host name
+    #[serde(default)]
+    pub image_base_url: Option<String>,
+
+    /// Seconds a generated image is kept before it is deleted
+    #[serde(default = "default_image_retention_secs")]
+    pub image_retention_secs: u64,
+
+    /// Seconds between `:keep-alive` comments on a stream that is still
+    /// waiting for its first token, so proxies don't drop it during a long
+    /// prefill; 0 disables them
+    #[serde(default = "default_sse_keep_alive_secs")]
+    pub sse_keep_alive_secs: u64,
+
+    /// Browser origins allowed to call the API (e.g.
+    /// "http://localhost:3000"), or "*" for any (the default). Empty allows
+    /// none; the API is still reachable from non-browser clients.
+    #[serde(default = "default_cors_origins")]
+    pub cors_origins: Vec<String>,
+
+    /// Download a HuggingFace repo id that isn't installed when a request
+    /// names it, instead of failing. Off so a typo can't start a large
+    /// download on the server.
+    #[serde(default)]
+    pub auto_download: bool,
+
+    /// Daemon log level: error, warn, info, debug or trace. RUST_LOG
+    /// directives for single modules still apply on top.
+    #[serde(default = "default_log_level")]
+    pub log_level: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -63,6 +135,32 @@ pub struct InferenceConfig {
     /// Use GPU acceleration (Metal on macOS, CUDA on Linux)
     #[serde(default = "default_use_gpu")]
     pub use_gpu: bool,
+
+    /// GPU device index to run inference on
+    #[serde(default)]
+    pub gpu_id: u32,
+
+    /// Tokens buffered between generation and a streaming client. When the
+    /// buffer is full, generation waits for the client to catch up.
+    #[serde(default = "default_stream_buffer_size")]
+    pub stream_buffer_size: usize,
+
+    /// Chat template overriding the built-in prompt format: a format string
+    /// with {system}/{user}/{assistant} placeholders, or a path to a file
+    /// containing one
+    #[serde(default)]
+    pub chat_template: Option<String>,
+
+    /// Weight dtype for chat models ("f16", "bf16" or "f32"). Unset picks
+    /// per device: BF16 on CUDA, F16 on Metal for architectures known to
+    /// work (falling back to F32), F32 otherwise.
+    #[serde(default)]
+    pub dtype: Option<String>,
+
+    /// Context window (prompt + completion tokens) for chat models, below
+    /// the model's maximum to save memory. Unset uses the model's maximum.
+    #[serde(default)]
+    pub context_length: Option<usize>,
 }
 
 fn default_port() -> u16 {
@@ -73,6 +171,30 @@ fn default_host() -> String {
     "127.0.0.1".to_string()
 }
 
+fn default_min_vram_mb() -> u64 {
+    8 * 1024
+}
+
+fn default_transcript_max_mb() -> u64 {
+    100
+}
+
+fn default_image_retention_secs() -> u64 {
+    60 * 60
+}
+
+fn default_sse_keep_alive_secs() -> u64 {
+    15
+}
+
+fn default_log_level() -> String {
+    "info".to_string()
+}
+
+fn default_cors_origins() -> Vec<String> {
+    vec!["*".to_string()]
+}
+
 fn default_max_tokens() -> u32 {
     2048
 }
@@ -89,6 +211,10 @@ fn default_use_gpu() -> bool {
     true
 }
 
+fn default_stream_buffer_size() -> usize {
+    100
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
@@ -104,6 +230,20 @@ impl Default for DaemonConfig {
         Self {
             port: default_port(),
             host: default_host(),
+            min_vram_mb: default_min_vram_mb(),
+            log_transcripts: false,
+            transcript_path: None,
+            transcript_max_mb: default_transcript_max_mb(),
+            max_tokens_per_second: None,
+            max_concurrent_requests_per_client: None,
+            max_batch_total_tokens: None,
+            image_dir: None,
+            image_base_url: None,
+            image_retention_secs: default_image_retention_secs(),
+            sse_keep_alive_secs: default_sse_keep_alive_secs(),
+            cors_origins: default_cors_origins(),
+            auto_download: false,
+            log_level: default_log_level(),
         }
     }
 }
@@ -124,6 +264,11 @@ impl Default for InferenceConfig {
             temperature: default_temperature(),
             top_p: default_top_p(),
             use_gpu: default_use_gpu(),
+            gpu_id: 0,
+            stream_buffer_size: default_stream_buffer_size(),
+            chat_template: None,
+            dtype: None,
+            context_length: None,
         }
     }
 }
@@ -144,7 +289,13 @@ impl Config {
 
         if config_path.exists() {
             let content = fs::read_to_string(&config_path)?;
-            let config: Config = toml::from_str(&content)?;
+            let config: Config = toml::from_str(&content).with_context(|| {
+                format!(
+                    "Failed to parse {} (fix it with `omg config edit`, or start over with \
+                     `omg config reset`)",
+                    config_path.display()
+                )
+            })?;
             Ok(config)
         } else {
             Ok(Config::default())
@@ -164,6 +315,151 @@ impl Config {
         Ok(())
     }
 
+    /// Check ranges and paths, naming every invalid setting and how to fix
+    /// it. Run after `load` by commands that act on the settings.
+    pub fn validate(&self) -> Result<()> {
+        let problems = self.problems();
+        if problems.is_empty() {
+            return Ok(());
+        }
+
+        let path = Self::config_path()
+            .map(|p| p.display().to_string())
+            .unwrap_or_else(|_| "config.toml".to_string());
+        let mut message = format!("Invalid configuration in {}:", path);
+        for (key, problem) in &problems {
+            message.push_str(&format!("\n  {}: {}", key, problem));
+        }
+        message.push_str(
+            "\nFix it with `omg config <key> <value>` or `omg config edit`, or start over \
+             with `omg config reset`.",
+        );
+        anyhow::bail!(message)
+    }
+
+    /// Invalid settings, as (key, what is wrong) pairs
+    pub fn problems(&self) -> Vec<(&'static str, String)> {
+        let mut problems = Vec::new();
+        let mut check = |key: &'static str, ok: bool, problem: String| {
+            if !ok {
+                problems.push((key, problem));
+            }
+        };
+
+        let daemon = &self.daemon;
+        check(
+            "daemon.port",
+            daemon.port != 0,
+            "must be between 1 and 65535".to_string(),
+        );
+        check(
+            "daemon.host",
+            !daemon.host.trim().is_empty(),
+            "must not be empty (e.g. 127.0.0.1)".to_string(),
+        );
+        check(
+            "daemon.transcript_max_mb",
+            daemon.transcript_max_mb > 0,
+            "must be at least 1".to_string(),
+        );
+        check(
+            "daemon.max_tokens_per_second",
+            daemon
+                .max_tokens_per_second
+                .is_none_or(|rate| rate > 0.0 && rate.is_finite()),
+            "must be positive (or unset for unlimited)".to_string(),
+        );
+        check(
+            "daemon.max_concurrent_requests_per_client",
+            daemon.max_concurrent_requests_per_client != Some(0),
+            "must be at least 1 (or unset for unlimited)".to_string(),
+        );
+        check(
+            "daemon.max_batch_total_tokens",
+            daemon.max_batch_total_tokens != Some(0),
+            "must be at least 1 (or unset for unlimited)".to_string(),
+        );
+        check(
+            "daemon.image_base_url",
+            daemon
+                .image_base_url
+                .as_deref()
+                .is_none_or(|url| url.starts_with("http://") || url.starts_with("https://")),
+            "must start with http:// or https://".to_string(),
+        );
+        check(
+            "daemon.image_retention_secs",
+            daemon.image_retention_secs > 0,
+            "must be at least 1".to_string(),
+        );
+        for origin in &daemon.cors_origins {
+            check(
+                "daemon.cors_origins",
+                origin == "*" || origin.starts_with("http://") || origin.starts_with("https://"),
+                format!(
+                    "{:?} must be \"*\" or start with http:// or https://",
+                    origin
+                ),
+            );
+        }
+        check(
+            "daemon.log_level",
+            daemon.log_level.parse::<tracing::Level>().is_ok(),
+            "must be error, warn, info, debug or trace".to_string(),
+        );
+        if let Some(path) = &daemon.transcript_path {
+            if let Err(problem) = check_parent_dir(path) {
+                check("daemon.transcript_path", false, problem);
+            }
+        }
+        if let Some(dir) = &daemon.image_dir {
+            if let Err(problem) = check_dir(dir) {
+                check("daemon.image_dir", false, problem);
+            }
+        }
+
+        if let Err(problem) = check_dir(&self.models.storage_path) {
+            check("models.storage_path", false, problem);
+        }
+
+        let inference = &self.inference;
+        check(
+            "inference.max_tokens",
+            inference.max_tokens > 0,
+            "must be at least 1".to_string(),
+        );
+        check(
+            "inference.temperature",
+            (0.0..=2.0).contains(&inference.temperature),
+            format!("must be between 0 and 2 (got {})", inference.temperature),
+        );
+        check(
+            "inference.top_p",
+            inference.top_p > 0.0 && inference.top_p <= 1.0,
+            format!("must be above 0 and at most 1 (got {})", inference.top_p),
+        );
+        check(
+            "inference.stream_buffer_size",
+            inference.stream_buffer_size > 0,
+            "must be at least 1".to_string(),
+        );
+        check(
+            "inference.dtype",
+            matches!(
+                inference.dtype.as_deref(),
+                None | Some("f16" | "bf16" | "f32")
+            ),
+            "must be f16, bf16 or f32 (or unset for auto)".to_string(),
+        );
+        check(
+            "inference.context_length",
+            inference.context_length != Some(0),
+            "must be at least 1 (or unset for the model's maximum)".to_string(),
+        );
+
+        problems
+    }
+
     /// Get the config file path: ~/.config/ohmygpu/config.toml
     pub fn config_path() -> Result<PathBuf> {
         Ok(Self::base_dir()?.join("config.toml"))
@@ -189,3 +485,37 @@ impl Config {
         Ok(Self::base_dir()?.join("cache"))
     }
 }
+
+/// A directory setting: created on first use, so it may be missing, but it
+/// can't be a file or sit under one
+fn check_dir(dir: &Path) -> std::result::Result<(), String> {
+    if dir.as_os_str().is_empty() {
+        return Err("must not be empty".to_string());
+    }
+    if dir.starts_with("~") {
+        return Err(format!(
+            "\"~\" is not expanded; use the full path instead of {}",
+            dir.display()
+        ));
+    }
+    if dir.exists() && !dir.is_dir() {
+        return Err(format!("{} is not a directory", dir.display()));
+    }
+    check_parent_dir(dir)
+}
+
+/// A path whose closest existing ancestor must be a directory, so the
+/// missing part can be created
+fn check_parent_dir(path: &Path) -> std::result::Result<(), String> {
+    if path.as_os_str().is_empty() {
+        return Err("must not be empty".to_string());
+    }
+    match path.ancestors().skip(1).find(|a| a.exists()) {
+        Some(ancestor) if !ancestor.is_dir() => Err(format!(
+            "{} can't be created: {} is not a directory",
+            path.display(),
+            ancestor.display()
+        )),
+        _ => Ok(()),
+    }
+}
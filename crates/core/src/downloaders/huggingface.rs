@@ -3,13 +3,14 @@ use async_trait::async_trait;
 use futures_util::StreamExt;
 use indicatif::{ProgressBar, ProgressStyle};
 use reqwest::Client;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::fs::{self, File};
 use std::io::Write;
 use std::path::PathBuf;
+use std::sync::Arc;
 
 use super::Downloader;
-use crate::models::{ModelInfo, ModelSource, ModelType};
+use crate::models::{ModelInfo, ModelSource, ModelType, SamplingParams};
 use crate::registry::ModelRegistry;
 
 const HF_API_BASE: &str = "https://huggingface.co/api";
@@ -17,6 +18,24 @@ const HF_CDN_BASE: &str = "https://huggingface.co";
 
 pub struct HuggingFaceDownloader {
     client: Client,
+    progress: Option<ProgressCallback>,
+}
+
+/// Called after every chunk of a download
+pub type ProgressCallback = Arc<dyn Fn(&DownloadProgress) + Send + Sync>;
+
+/// How far a model download has got
+#[derive(Debug, Clone, Serialize)]
+pub struct DownloadProgress {
+    /// File being downloaded
+    pub file: String,
+    /// Files finished before this one
+    pub files_done: usize,
+    pub file_count: usize,
+    /// Bytes downloaded so far, over all files
+    pub downloaded_bytes: u64,
+    /// Size of all files, when HuggingFace reports every one
+    pub total_bytes: Option<u64>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -41,6 +60,47 @@ pub struct HfSibling {
     pub size: Option<u64>,
 }
 
+impl HfModelInfo {
+    /// Whether this is a diffusers pipeline: tagged as image generation,
+    /// or laid out with a `model_index.json`
+    pub fn is_diffusion(&self) -> bool {
+        matches!(
+            self.pipeline_tag.as_deref(),
+            Some("text-to-image" | "image-to-image")
+        ) || self
+            .siblings
+            .iter()
+            .any(|s| s.rfilename == "model_index.json")
+    }
+
+    /// Combined size of `files`, if HuggingFace reported all of their sizes
+    pub fn download_size(&self, files: &[String]) -> Option<u64> {
+        files
+            .iter()
+            .map(|file| {
+                self.siblings
+                    .iter()
+                    .find(|s| &s.rfilename == file)
+                    .and_then(|s| s.size)
+            })
+            .sum()
+    }
+
+    /// Registry type from the `pipeline_tag`, or from the file layout when
+    /// the repo is untagged
+    pub fn model_type(&self) -> ModelType {
+        match self
+            .pipeline_tag
+            .as_deref()
+            .map(ModelType::from_pipeline_tag)
+        {
+            Some(model_type) if model_type != ModelType::Unknown => model_type,
+            _ if self.is_diffusion() => ModelType::ImageGeneration,
+            _ => ModelType::Unknown,
+        }
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct HfSearchResult {
     #[serde(rename = "modelId")]
@@ -49,6 +109,8 @@ pub struct HfSearchResult {
     pub downloads: u64,
     #[serde(default)]
     pub likes: i64,
+    #[serde(default)]
+    pub pipeline_tag: Option<String>,
 }
 
 impl HuggingFaceDownloader {
@@ -58,9 +120,16 @@ impl HuggingFaceDownloader {
                 .user_agent("ohmygpu/0.1.0")
                 .build()
                 .expect("Failed to create HTTP client"),
+            progress: None,
         }
     }
 
+    /// Report download progress to `callback` instead of a progress bar
+    pub fn with_progress(mut self, callback: ProgressCallback) -> Self {
+        self.progress = Some(callback);
+        self
+    }
+
     pub async fn search(&self, query: &str) -> Result<Vec<HfSearchResult>> {
         let url = format!(
             "{}/models?search={}&sort=downloads&direction=-1&limit=20",
@@ -79,7 +148,8 @@ impl HuggingFaceDownloader {
     }
 
     pub async fn get_model_info(&self, repo_id: &str) -> Result<HfModelInfo> {
-        let url = format!("{}/models/{}", HF_API_BASE, repo_id);
+        // `blobs` adds the size of every file
+        let url = format!("{}/models/{}?blobs=true", HF_API_BASE, repo_id);
 
         let response = self
             .client
@@ -96,51 +166,66 @@ impl HuggingFaceDownloader {
         Ok(info)
     }
 
-    fn select_files(&self, model_info: &HfModelInfo, requested_file: Option<&str>) -> Vec<String> {
-        if let Some(file) = requested_file {
-            return vec![file.to_string()];
-        }
+    /// Download the files of a model whose info was already fetched, e.g.
+    /// to check its size first. `file` picks a single file, as in
+    /// [`Downloader::download`].
+    pub async fn download_with_info(
+        &self,
+        hf_info: &HfModelInfo,
+        file: Option<&str>,
+    ) -> Result<ModelInfo> {
+        let model_id = hf_info.id.as_str();
+        let model_type = hf_info.model_type();
 
-        // Prioritize GGUF files for LLMs (quantized, smaller)
-        let gguf_files: Vec<_> = model_info
-            .siblings
-            .iter()
-            .filter(|s| s.rfilename.ends_with(".gguf"))
-            .map(|s| s.rfilename.clone())
-            .collect();
-
-        if !gguf_files.is_empty() {
-            // Prefer Q4_K_M quantization as a good balance
-            if let Some(q4) = gguf_files.iter().find(|f| f.contains("Q4_K_M")) {
-                return vec![q4.clone()];
-            }
-            // Otherwise take the first GGUF
-            return vec![gguf_files[0].clone()];
+        // Create model directory
+        let models_dir = ModelRegistry::models_dir()?;
+        let model_name = model_id.replace('/', "--");
+        let model_dir = models_dir.join(&model_name);
+        fs::create_dir_all(&model_dir)?;
+
+        // Select and download files
+        let files_to_download = select_files(hf_info, file);
+
+        if files_to_download.is_empty() {
+            anyhow::bail!("No suitable files found for model '{}'", model_id);
         }
 
-        // For other model types, get safetensors or pytorch files
-        let model_files: Vec<_> = model_info
-            .siblings
-            .iter()
-            .filter(|s| {
-                s.rfilename.ends_with(".safetensors")
-                    || s.rfilename.ends_with(".bin")
-                    || s.rfilename.ends_with(".pt")
-                    || s.rfilename == "config.json"
-                    || s.rfilename == "tokenizer.json"
-                    || s.rfilename == "tokenizer_config.json"
-            })
-            .map(|s| s.rfilename.clone())
-            .collect();
+        let mut progress = DownloadProgress {
+            file: String::new(),
+            files_done: 0,
+            file_count: files_to_download.len(),
+            downloaded_bytes: 0,
+            total_bytes: hf_info.download_size(&files_to_download),
+        };
+        for filename in &files_to_download {
+            progress.file = filename.clone();
+            self.download_file(model_id, filename, &model_dir, &mut progress)
+                .await?;
+            progress.files_done += 1;
+        }
 
-        model_files
+        Ok(ModelInfo {
+            name: model_name,
+            source: ModelSource::HuggingFace {
+                repo_id: model_id.to_string(),
+            },
+            model_type,
+            path: model_dir,
+            size_bytes: progress.downloaded_bytes,
+            files: files_to_download,
+            downloaded_at: chrono::Utc::now(),
+            sampling: SamplingParams::default(),
+            pinned: false,
+        })
     }
 
+    /// Download one file, adding its bytes to `progress`
     async fn download_file(
         &self,
         repo_id: &str,
         filename: &str,
         dest_dir: &PathBuf,
+        progress: &mut DownloadProgress,
     ) -> Result<u64> {
         let url = format!("{}/{}/resolve/main/{}", HF_CDN_BASE, repo_id, filename);
 
@@ -157,7 +242,11 @@ impl HuggingFaceDownloader {
 
         let total_size = response.content_length().unwrap_or(0);
 
-        let pb = ProgressBar::new(total_size);
+        let pb = if self.progress.is_some() {
+            ProgressBar::hidden()
+        } else {
+            ProgressBar::new(total_size)
+        };
         pb.set_style(
             ProgressStyle::default_bar()
                 .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta})")?
@@ -171,22 +260,148 @@ impl HuggingFaceDownloader {
             fs::create_dir_all(parent)?;
         }
 
-        let mut file = File::create(&dest_path)?;
-        let mut downloaded: u64 = 0;
-        let mut stream = response.bytes_stream();
-
-        while let Some(chunk) = stream.next().await {
-            let chunk = chunk.context("Error downloading chunk")?;
-            file.write_all(&chunk)?;
-            downloaded += chunk.len() as u64;
-            pb.set_position(downloaded);
+        // Written under a temporary name so a failed download never leaves
+        // a truncated file that looks complete
+        let part_path = dest_path.with_file_name(format!(
+            "{}.part",
+            dest_path.file_name().unwrap_or_default().to_string_lossy()
+        ));
+        let written: Result<u64> = async {
+            let mut file = File::create(&part_path)?;
+            let mut downloaded: u64 = 0;
+            let mut stream = response.bytes_stream();
+
+            while let Some(chunk) = stream.next().await {
+                let chunk = chunk.context("Error downloading chunk")?;
+                file.write_all(&chunk)?;
+                downloaded += chunk.len() as u64;
+                pb.set_position(downloaded);
+                progress.downloaded_bytes += chunk.len() as u64;
+                if let Some(callback) = &self.progress {
+                    callback(progress);
+                }
+            }
+            Ok(downloaded)
         }
+        .await;
+        let downloaded = match written {
+            Ok(downloaded) => downloaded,
+            Err(e) => {
+                let _ = fs::remove_file(&part_path);
+                return Err(e);
+            }
+        };
+        fs::rename(&part_path, &dest_path)?;
 
         pb.finish_with_message(format!("Downloaded {}", filename));
         Ok(downloaded)
     }
 }
 
+/// Files to download for a model: `requested_file` alone if given, the
+/// nested component set for diffusion pipelines, otherwise a single GGUF
+/// (preferring Q4_K_M) or the flat safetensors/pytorch set
+pub fn select_files(model_info: &HfModelInfo, requested_file: Option<&str>) -> Vec<String> {
+    if let Some(file) = requested_file {
+        return vec![file.to_string()];
+    }
+
+    if model_info.is_diffusion() {
+        return select_diffusion_files(&model_info.siblings);
+    }
+
+    // Prioritize GGUF files for LLMs (quantized, smaller)
+    let gguf_files: Vec<_> = model_info
+        .siblings
+        .iter()
+        .filter(|s| s.rfilename.ends_with(".gguf"))
+        .map(|s| s.rfilename.clone())
+        .collect();
+
+    if !gguf_files.is_empty() {
+        // Prefer Q4_K_M quantization as a good balance
+        if let Some(q4) = gguf_files.iter().find(|f| f.contains("Q4_K_M")) {
+            return vec![q4.clone()];
+        }
+        // Otherwise take the first GGUF
+        return vec![gguf_files[0].clone()];
+    }
+
+    // For other model types, get safetensors or pytorch files
+    let model_files: Vec<_> = model_info
+        .siblings
+        .iter()
+        .filter(|s| {
+            s.rfilename.ends_with(".safetensors")
+                || s.rfilename.ends_with(".bin")
+                || s.rfilename.ends_with(".pt")
+                || s.rfilename == "config.json"
+                || s.rfilename == "tokenizer.json"
+                || s.rfilename == "tokenizer_config.json"
+        })
+        .map(|s| s.rfilename.clone())
+        .collect();
+
+    model_files
+}
+
+const WEIGHT_EXTENSIONS: [&str; 5] = [".safetensors", ".bin", ".pt", ".pth", ".ckpt"];
+const COMPONENT_METADATA_EXTENSIONS: [&str; 4] = [".json", ".txt", ".model", ".tiktoken"];
+
+/// The diffusers layout: `model_index.json` plus every component directory
+/// (configs, tokenizer files and weights). Within a component, safetensors
+/// win over pickle weights and full-precision files over their `.fp16` /
+/// `.bf16` variants. Root-level weights are single-file checkpoints that
+/// duplicate the components, so they are only taken when there is no
+/// `model_index.json` (e.g. FLUX-style repos).
+fn select_diffusion_files(siblings: &[HfSibling]) -> Vec<String> {
+    let names: Vec<&str> = siblings.iter().map(|s| s.rfilename.as_str()).collect();
+    let has_index = names.contains(&"model_index.json");
+    let is_weight = |file: &str| WEIGHT_EXTENSIONS.iter().any(|ext| file.ends_with(ext));
+    let has_safetensors_in = |dir: &str| {
+        names
+            .iter()
+            .any(|name| split_dir(name).0 == dir && name.ends_with(".safetensors"))
+    };
+
+    names
+        .iter()
+        .filter(|name| {
+            let (dir, file) = split_dir(name);
+            if dir.is_empty() {
+                return file == "model_index.json"
+                    || (!has_index && file.ends_with(".safetensors"));
+            }
+            if is_weight(file) {
+                if !file.ends_with(".safetensors") && has_safetensors_in(dir) {
+                    return false;
+                }
+                return match full_precision_name(file) {
+                    Some(full) => !names.contains(&format!("{}/{}", dir, full).as_str()),
+                    None => true,
+                };
+            }
+            COMPONENT_METADATA_EXTENSIONS
+                .iter()
+                .any(|ext| file.ends_with(ext))
+        })
+        .map(|name| name.to_string())
+        .collect()
+}
+
+/// `"vae/config.json"` -> `("vae", "config.json")`; root files have an
+/// empty directory
+fn split_dir(name: &str) -> (&str, &str) {
+    name.rsplit_once('/').unwrap_or(("", name))
+}
+
+/// `"model.fp16.safetensors"` -> `Some("model.safetensors")`
+fn full_precision_name(file: &str) -> Option<String> {
+    let (stem, ext) = file.rsplit_once('.')?;
+    let (base, variant) = stem.rsplit_once('.')?;
+    matches!(variant, "fp16" | "bf16").then(|| format!("{}.{}", base, ext))
+}
+
 impl Default for HuggingFaceDownloader {
     fn default() -> Self {
         Self::new()
@@ -198,47 +413,7 @@ impl Downloader for HuggingFaceDownloader {
     async fn download(&self, model_id: &str, file: Option<&str>) -> Result<ModelInfo> {
         println!("Fetching model info from HuggingFace...");
         let hf_info = self.get_model_info(model_id).await?;
-
-        let model_type = hf_info
-            .pipeline_tag
-            .as_ref()
-            .map(|t| ModelType::from_pipeline_tag(t))
-            .unwrap_or(ModelType::Unknown);
-
-        // Create model directory
-        let models_dir = ModelRegistry::models_dir()?;
-        let model_name = model_id.replace('/', "--");
-        let model_dir = models_dir.join(&model_name);
-        fs::create_dir_all(&model_dir)?;
-
-        // Select and download files
-        let files_to_download = self.select_files(&hf_info, file);
-
-        if files_to_download.is_empty() {
-            anyhow::bail!("No suitable files found for model '{}'", model_id);
-        }
-
-        println!(
-            "Downloading {} file(s) to {:?}",
-            files_to_download.len(),
-            model_dir
-        );
-
-        let mut total_size = 0u64;
-        for filename in &files_to_download {
-            total_size += self.download_file(model_id, filename, &model_dir).await?;
-        }
-
-        Ok(ModelInfo {
-            name: model_name,
-            source: ModelSource::HuggingFace {
-                repo_id: model_id.to_string(),
-            },
-            model_type,
-            path: model_dir,
-            size_bytes: total_size,
-            files: files_to_download,
-            downloaded_at: chrono::Utc::now(),
-        })
+        println!("Downloading {} file(s)", select_files(&hf_info, file).len());
+        self.download_with_info(&hf_info, file).await
     }
 }
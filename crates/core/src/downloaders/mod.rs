@@ -10,4 +10,4 @@ pub trait Downloader: Send + Sync {
     async fn download(&self, model_id: &str, file: Option<&str>) -> Result<ModelInfo>;
 }
 
-pub use huggingface::HuggingFaceDownloader;
+pub use huggingface::{select_files, HuggingFaceDownloader};
@@ -5,12 +5,16 @@
 //! - Local model repository and registry
 //! - Model metadata and types
 //! - Configuration management
+//! - Embedding similarity helpers
 
 pub mod config;
+pub mod disk;
 pub mod downloaders;
 pub mod models;
+pub mod random;
 pub mod registry;
+pub mod similarity;
 
 pub use config::Config;
-pub use models::{ModelInfo, ModelSource, ModelType};
+pub use models::{ModelInfo, ModelSource, ModelType, SamplingParams};
 pub use registry::ModelRegistry;
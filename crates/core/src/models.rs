@@ -1,5 +1,6 @@
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ModelInfo {
@@ -10,6 +11,28 @@ pub struct ModelInfo {
     pub size_bytes: u64,
     pub files: Vec<String>,
     pub downloaded_at: chrono::DateTime<chrono::Utc>,
+    /// Used when a request omits them, in place of the `[inference]` defaults
+    #[serde(default, skip_serializing_if = "SamplingParams::is_empty")]
+    pub sampling: SamplingParams,
+    /// Set with `omg model pin`; pinned models are never deleted by
+    /// `omg model rm` or `omg model gc`
+    #[serde(default)]
+    pub pinned: bool,
+}
+
+/// Per-model sampling defaults, set with `omg model set-params`
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct SamplingParams {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+}
+
+impl SamplingParams {
+    pub fn is_empty(&self) -> bool {
+        self.temperature.is_none() && self.top_p.is_none()
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -54,4 +77,99 @@ impl ModelType {
             _ => ModelType::Unknown,
         }
     }
+
+    /// Guess the type of a model directory from its files: the
+    /// `pipeline_tag` in a HuggingFace README, a diffusers pipeline layout,
+    /// sentence-transformers metadata, or the architecture in config.json.
+    pub fn detect(model_dir: &Path) -> Self {
+        if let Some(tag) = readme_pipeline_tag(model_dir) {
+            let model_type = Self::from_pipeline_tag(&tag);
+            if model_type != ModelType::Unknown {
+                return model_type;
+            }
+        }
+
+        if model_dir.join("model_index.json").exists()
+            || model_dir.join("transformer").join("config.json").exists()
+        {
+            return ModelType::ImageGeneration;
+        }
+
+        if model_dir.join("modules.json").exists()
+            || model_dir.join("config_sentence_transformers.json").exists()
+        {
+            return ModelType::Embedding;
+        }
+
+        let config: serde_json::Value = std::fs::read_to_string(model_dir.join("config.json"))
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default();
+        let architectures: Vec<String> =
+            serde_json::from_value(config["architectures"].clone()).unwrap_or_default();
+
+        for arch in &architectures {
+            if arch.ends_with("ForCausalLM") || arch.ends_with("LMHeadModel") {
+                return ModelType::LLM;
+            }
+            if arch.starts_with("Whisper") {
+                return ModelType::AudioTranscription;
+            }
+            if arch.ends_with("ForImageClassification") || arch.ends_with("ForObjectDetection") {
+                return ModelType::ImageClassification;
+            }
+            if arch.ends_with("Model") {
+                // Bare encoders (BertModel, XLMRobertaModel, ...) are
+                // typically used for embeddings
+                return ModelType::Embedding;
+            }
+        }
+
+        // Older configs only name the model family
+        match config["model_type"].as_str() {
+            Some("llama" | "mistral" | "mixtral" | "phi" | "phi3" | "qwen2" | "gemma" | "gpt2") => {
+                ModelType::LLM
+            }
+            Some("bert" | "roberta" | "xlm-roberta" | "nomic_bert") => ModelType::Embedding,
+            _ => ModelType::Unknown,
+        }
+    }
+}
+
+/// Accepts the names shown by `as_str` (case-insensitive, `-` or space) as
+/// well as HuggingFace pipeline tags
+impl FromStr for ModelType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let normalized = s.trim().to_lowercase().replace([' ', '_'], "-");
+        let model_type = match normalized.as_str() {
+            "llm" => ModelType::LLM,
+            "embedding" => ModelType::Embedding,
+            "image-gen" | "image-generation" => ModelType::ImageGeneration,
+            "image-class" | "image-classification" => ModelType::ImageClassification,
+            "audio-stt" | "audio-transcription" => ModelType::AudioTranscription,
+            "audio-gen" | "audio-generation" => ModelType::AudioGeneration,
+            "unknown" => ModelType::Unknown,
+            tag => match ModelType::from_pipeline_tag(tag) {
+                ModelType::Unknown => anyhow::bail!(
+                    "Unknown model type '{}' (expected llm, embedding, image-gen, \
+                     image-class, audio-stt, audio-gen or a HuggingFace pipeline tag)",
+                    s
+                ),
+                model_type => model_type,
+            },
+        };
+        Ok(model_type)
+    }
+}
+
+/// `pipeline_tag` from the YAML front matter of a HuggingFace model card
+fn readme_pipeline_tag(model_dir: &Path) -> Option<String> {
+    let readme = std::fs::read_to_string(model_dir.join("README.md")).ok()?;
+    let front_matter = readme.strip_prefix("---")?.split("\n---").next()?;
+    front_matter.lines().find_map(|line| {
+        let value = line.trim().strip_prefix("pipeline_tag:")?;
+        Some(value.trim().trim_matches(['"', '\'']).to_string())
+    })
 }
@@ -0,0 +1,18 @@
+//! Unpredictable values for seeds and ids, without an RNG dependency
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A random `u64`: the current time hashed with a randomly keyed hasher.
+/// Fine for seeds and unguessable ids, not for cryptography.
+pub fn random_u64() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u128(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos(),
+    );
+    hasher.finish()
+}
@@ -2,7 +2,7 @@ use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use crate::config::Config;
 use crate::models::ModelInfo;
@@ -16,10 +16,15 @@ pub struct ModelRegistry {
 
 impl ModelRegistry {
     pub fn load() -> Result<Self> {
-        let base_dir = Config::base_dir()?;
-        fs::create_dir_all(&base_dir)?;
+        Self::load_from(Config::registry_path()?)
+    }
 
-        let registry_path = Config::registry_path()?;
+    /// Load the registry file at `registry_path` instead of the one in the
+    /// config directory
+    pub fn load_from(registry_path: PathBuf) -> Result<Self> {
+        if let Some(dir) = registry_path.parent() {
+            fs::create_dir_all(dir)?;
+        }
 
         let mut registry = if registry_path.exists() {
             let content = fs::read_to_string(&registry_path)?;
@@ -32,6 +37,11 @@ impl ModelRegistry {
         Ok(registry)
     }
 
+    /// The file this registry was loaded from and saves to
+    pub fn path(&self) -> &Path {
+        &self.registry_path
+    }
+
     pub fn save(&self) -> Result<()> {
         let content = serde_json::to_string_pretty(&self)?;
         fs::write(&self.registry_path, content)?;
@@ -54,6 +64,10 @@ impl ModelRegistry {
         self.models.get(name)
     }
 
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut ModelInfo> {
+        self.models.get_mut(name)
+    }
+
     pub fn list(&self) -> Vec<&ModelInfo> {
         self.models.values().collect()
     }
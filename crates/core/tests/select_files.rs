@@ -0,0 +1,186 @@
+//! File selection for `omg model pull` over representative HuggingFace
+//! `siblings` lists.
+
+use ohmygpu_core::downloaders::huggingface::{HfModelInfo, HfSibling};
+use ohmygpu_core::downloaders::select_files;
+use ohmygpu_core::ModelType;
+
+fn model(pipeline_tag: Option<&str>, files: &[&str]) -> HfModelInfo {
+    HfModelInfo {
+        id: "org/model".to_string(),
+        pipeline_tag: pipeline_tag.map(str::to_string),
+        downloads: 0,
+        likes: 0,
+        siblings: files
+            .iter()
+            .map(|f| HfSibling {
+                rfilename: f.to_string(),
+                size: None,
+            })
+            .collect(),
+    }
+}
+
+#[test]
+fn diffusers_repo_gets_nested_components() {
+    let info = model(
+        Some("text-to-image"),
+        &[
+            ".gitattributes",
+            "README.md",
+            "assets/showcase.jpg",
+            "model_index.json",
+            "scheduler/scheduler_config.json",
+            "text_encoder/config.json",
+            "text_encoder/model-00001-of-00002.safetensors",
+            "text_encoder/model-00002-of-00002.safetensors",
+            "text_encoder/model.safetensors.index.json",
+            "tokenizer/tokenizer.json",
+            "tokenizer/tokenizer_config.json",
+            "tokenizer/merges.txt",
+            "transformer/config.json",
+            "transformer/diffusion_pytorch_model.safetensors",
+            "vae/config.json",
+            "vae/diffusion_pytorch_model.safetensors",
+        ],
+    );
+
+    assert_eq!(
+        select_files(&info, None),
+        vec![
+            "model_index.json",
+            "scheduler/scheduler_config.json",
+            "text_encoder/config.json",
+            "text_encoder/model-00001-of-00002.safetensors",
+            "text_encoder/model-00002-of-00002.safetensors",
+            "text_encoder/model.safetensors.index.json",
+            "tokenizer/tokenizer.json",
+            "tokenizer/tokenizer_config.json",
+            "tokenizer/merges.txt",
+            "transformer/config.json",
+            "transformer/diffusion_pytorch_model.safetensors",
+            "vae/config.json",
+            "vae/diffusion_pytorch_model.safetensors",
+        ]
+    );
+    assert_eq!(info.model_type(), ModelType::ImageGeneration);
+}
+
+#[test]
+fn diffusers_repo_skips_duplicate_weights() {
+    let info = model(
+        Some("text-to-image"),
+        &[
+            "model_index.json",
+            "sd_xl_base_1.0.safetensors",
+            "unet/config.json",
+            "unet/diffusion_pytorch_model.bin",
+            "unet/diffusion_pytorch_model.fp16.safetensors",
+            "unet/diffusion_pytorch_model.safetensors",
+            "vae/config.json",
+            "vae/diffusion_pytorch_model.fp16.safetensors",
+        ],
+    );
+
+    assert_eq!(
+        select_files(&info, None),
+        vec![
+            "model_index.json",
+            "unet/config.json",
+            "unet/diffusion_pytorch_model.safetensors",
+            "vae/config.json",
+            // Only variant available, so it is kept
+            "vae/diffusion_pytorch_model.fp16.safetensors",
+        ]
+    );
+}
+
+#[test]
+fn single_file_diffusion_repo_keeps_root_weights() {
+    let info = model(
+        Some("text-to-image"),
+        &[
+            "README.md",
+            "ae.safetensors",
+            "flux1-dev.safetensors",
+            "flux1-dev.gguf",
+        ],
+    );
+
+    assert_eq!(
+        select_files(&info, None),
+        vec!["ae.safetensors", "flux1-dev.safetensors"]
+    );
+}
+
+#[test]
+fn untagged_diffusers_repo_is_detected_from_layout() {
+    let info = model(
+        None,
+        &[
+            "model_index.json",
+            "vae/config.json",
+            "vae/diffusion_pytorch_model.safetensors",
+        ],
+    );
+
+    assert!(info.is_diffusion());
+    assert_eq!(info.model_type(), ModelType::ImageGeneration);
+    assert_eq!(select_files(&info, None).len(), 3);
+}
+
+#[test]
+fn llm_repo_prefers_q4_k_m_gguf() {
+    let info = model(
+        Some("text-generation"),
+        &[
+            "README.md",
+            "config.json",
+            "model-Q8_0.gguf",
+            "model-Q4_K_M.gguf",
+            "model.safetensors",
+        ],
+    );
+
+    assert_eq!(select_files(&info, None), vec!["model-Q4_K_M.gguf"]);
+    assert_eq!(info.model_type(), ModelType::LLM);
+}
+
+#[test]
+fn llm_repo_without_gguf_gets_flat_safetensors_set() {
+    let info = model(
+        Some("text-generation"),
+        &[
+            ".gitattributes",
+            "README.md",
+            "config.json",
+            "generation_config.json",
+            "model.safetensors",
+            "tokenizer.json",
+            "tokenizer_config.json",
+        ],
+    );
+
+    assert_eq!(
+        select_files(&info, None),
+        vec![
+            "config.json",
+            "model.safetensors",
+            "tokenizer.json",
+            "tokenizer_config.json",
+        ]
+    );
+}
+
+#[test]
+fn requested_file_overrides_selection() {
+    let info = model(
+        Some("text-to-image"),
+        &["model_index.json", "vae/config.json"],
+    );
+
+    assert_eq!(
+        select_files(&info, Some("vae/config.json")),
+        vec!["vae/config.json"]
+    );
+}
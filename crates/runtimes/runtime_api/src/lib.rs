@@ -5,7 +5,9 @@
 
 use anyhow::Result;
 use async_trait::async_trait;
+use ohmygpu_core::ModelType;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::path::PathBuf;
 
 /// Capabilities that a runtime can provide
@@ -19,6 +21,33 @@ pub struct RuntimeCaps {
     pub streaming: bool,
 }
 
+impl RuntimeCaps {
+    /// Capabilities a model of the given type offers once loaded
+    pub fn for_model_type(model_type: &ModelType) -> Self {
+        match model_type {
+            ModelType::LLM => Self {
+                chat: true,
+                completions: true,
+                streaming: true,
+                ..Default::default()
+            },
+            ModelType::Embedding => Self {
+                embeddings: true,
+                ..Default::default()
+            },
+            ModelType::ImageGeneration => Self {
+                images: true,
+                ..Default::default()
+            },
+            ModelType::AudioTranscription | ModelType::AudioGeneration => Self {
+                audio: true,
+                ..Default::default()
+            },
+            ModelType::ImageClassification | ModelType::Unknown => Self::default(),
+        }
+    }
+}
+
 /// Runtime status
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum RuntimeStatus {
@@ -35,6 +64,44 @@ pub struct RuntimeConfig {
     pub gpu_id: Option<u32>,
     pub vram_budget_mb: Option<u64>,
     pub cpu_threads: Option<u32>,
+    /// Capacity of the token channel returned by `chat_stream`
+    #[serde(default)]
+    pub stream_buffer_size: Option<usize>,
+    /// Chat template overriding the runtime's built-in prompt format
+    /// (a template string or a path to a template file)
+    #[serde(default)]
+    pub chat_template: Option<String>,
+    /// Registry type of the model, which decides how it is loaded
+    /// (e.g. `Embedding` loads an encoder instead of a chat model).
+    /// `None` loads a chat model.
+    #[serde(default)]
+    pub model_type: Option<ModelType>,
+    /// Weight dtype ("f16", "bf16", "f32"); `None` lets the runtime choose
+    #[serde(default)]
+    pub dtype: Option<String>,
+    /// Name the model is registered under, reported by `model_info`;
+    /// `None` uses the name of the model directory
+    #[serde(default)]
+    pub model_name: Option<String>,
+    /// Maximum sequence length (prompt + completion), below the model's own
+    /// to save memory; `None` uses the length from the model config
+    #[serde(default)]
+    pub context_length: Option<usize>,
+}
+
+/// Metadata about the model a runtime has loaded
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoadedModelInfo {
+    pub name: String,
+    /// Architecture from the model config (e.g. "llama", "phi", "bert")
+    pub architecture: String,
+    /// Weight dtype (e.g. "bf16")
+    pub dtype: String,
+    /// Device the model runs on (e.g. "cuda:0", "metal:0", "cpu")
+    pub device: String,
+    /// Maximum sequence length in tokens
+    pub context_length: usize,
+    pub caps: RuntimeCaps,
 }
 
 /// Chat message for inference
@@ -52,6 +119,30 @@ pub struct ChatRequest {
     pub max_tokens: u32,
     #[serde(default = "default_temperature")]
     pub temperature: f32,
+    #[serde(default = "default_top_p")]
+    pub top_p: f32,
+    /// Sample from the `top_k` most likely tokens only; 0 disables
+    #[serde(default)]
+    pub top_k: u32,
+    /// Drop tokens less likely than `min_p` times the most likely one;
+    /// 0 disables
+    #[serde(default)]
+    pub min_p: f32,
+    /// Penalizes tokens already generated multiplicatively; 1 disables
+    #[serde(default = "default_repetition_penalty")]
+    pub repetition_penalty: f32,
+    /// Subtracted from a token's logit for every time it was generated
+    #[serde(default)]
+    pub frequency_penalty: f32,
+    /// Subtracted once from the logit of every token already generated
+    #[serde(default)]
+    pub presence_penalty: f32,
+    /// Added to the logits of individual token ids
+    #[serde(default)]
+    pub logit_bias: HashMap<u32, f32>,
+    /// Makes sampling reproducible; a random seed is used when unset
+    #[serde(default)]
+    pub seed: Option<u64>,
     #[serde(default)]
     pub stream: bool,
 }
@@ -64,19 +155,120 @@ fn default_temperature() -> f32 {
     0.7
 }
 
+fn default_top_p() -> f32 {
+    0.9
+}
+
+fn default_repetition_penalty() -> f32 {
+    1.0
+}
+
+/// Errors a runtime reports for requests it cannot serve, so callers can
+/// tell them apart from internal failures
+#[derive(Debug, thiserror::Error)]
+pub enum RuntimeError {
+    #[error("Prompt is {prompt_tokens} tokens, which does not fit the model's context length of {context_length}")]
+    PromptTooLong {
+        prompt_tokens: usize,
+        context_length: usize,
+    },
+    #[error("The loaded model is not a chat model ({0})")]
+    NotAChatModel(&'static str),
+    #[error("The loaded model is not an embedding model")]
+    NotAnEmbeddingModel,
+    #[error("Out of GPU memory. Try a smaller or quantized model, a shorter prompt or fewer max_tokens, or free memory used by other processes")]
+    OutOfMemory,
+}
+
+impl RuntimeError {
+    /// Whether a backend error (e.g. from candle or CUDA) is an allocation
+    /// failure on the device
+    pub fn is_out_of_memory(error: &anyhow::Error) -> bool {
+        if matches!(
+            error.downcast_ref::<RuntimeError>(),
+            Some(RuntimeError::OutOfMemory)
+        ) {
+            return true;
+        }
+        let message = format!("{:#}", error).to_lowercase();
+        [
+            "out of memory",
+            "out_of_memory",
+            "outofmemory",
+            "failed to allocate",
+        ]
+        .iter()
+        .any(|pattern| message.contains(pattern))
+    }
+
+    /// Turn a backend out-of-memory error into [`RuntimeError::OutOfMemory`]
+    /// (keeping the original error as its source); other errors pass through
+    pub fn map_out_of_memory(error: anyhow::Error) -> anyhow::Error {
+        if Self::is_out_of_memory(&error) && error.downcast_ref::<RuntimeError>().is_none() {
+            tracing::warn!("Out of memory: {:#}", error);
+            error.context(RuntimeError::OutOfMemory)
+        } else {
+            error
+        }
+    }
+}
+
 /// Response from chat completion
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChatResponse {
     pub content: String,
     pub tokens_used: u32,
     pub finish_reason: String,
+    /// Time spent processing the prompt, in milliseconds
+    #[serde(default)]
+    pub prefill_ms: u64,
+    /// Total generation time including prefill, in milliseconds
+    #[serde(default)]
+    pub generation_ms: u64,
+}
+
+impl ChatResponse {
+    /// Decode throughput, excluding prompt processing
+    pub fn tokens_per_second(&self) -> Option<f64> {
+        let decode_ms = self.generation_ms.saturating_sub(self.prefill_ms);
+        if decode_ms == 0 {
+            return None;
+        }
+        Some(self.tokens_used as f64 * 1000.0 / decode_ms as f64)
+    }
+}
+
+/// Response from an embeddings request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbeddingResponse {
+    /// One vector per input, in input order
+    pub embeddings: Vec<Vec<f32>>,
+    pub tokens_used: u32,
 }
 
 /// A single token from streaming response
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChatToken {
     pub content: String,
+    /// Ids of the tokens `content` was decoded from
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub token_ids: Vec<u32>,
     pub finish_reason: Option<String>,
+    /// Why generation failed partway; the last token of such a stream
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl ChatToken {
+    /// The final token of a stream whose generation failed
+    pub fn failed(error: &anyhow::Error) -> Self {
+        Self {
+            content: String::new(),
+            token_ids: Vec::new(),
+            finish_reason: Some("error".to_string()),
+            error: Some(error.to_string()),
+        }
+    }
 }
 
 /// The main Runtime trait that all backends must implement
@@ -88,12 +280,21 @@ pub trait Runtime: Send + Sync {
     /// Get current status
     fn status(&self) -> RuntimeStatus;
 
+    /// Metadata about the loaded model, or `None` when nothing is loaded
+    fn model_info(&self) -> Option<LoadedModelInfo>;
+
     /// Load a model
     async fn load(&mut self, config: RuntimeConfig) -> Result<()>;
 
     /// Unload the current model
     async fn unload(&mut self) -> Result<()>;
 
+    /// Render messages into the exact prompt string fed to the model
+    fn render_prompt(&self, messages: &[ChatMessage]) -> String;
+
+    /// Number of tokens in the rendered prompt for `messages`
+    async fn count_prompt_tokens(&self, messages: &[ChatMessage]) -> Result<usize>;
+
     /// Run chat completion (non-streaming)
     async fn chat(&self, request: ChatRequest) -> Result<ChatResponse>;
 
@@ -103,4 +304,7 @@ pub trait Runtime: Send + Sync {
         &self,
         request: ChatRequest,
     ) -> Result<tokio::sync::mpsc::Receiver<ChatToken>>;
+
+    /// Embed each input into a vector (embedding models only)
+    async fn embed(&self, inputs: Vec<String>) -> Result<EmbeddingResponse>;
 }
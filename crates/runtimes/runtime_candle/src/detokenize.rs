@@ -0,0 +1,79 @@
+//! Incremental decoding of a growing token sequence into text deltas
+
+use anyhow::Result;
+use tokenizers::Tokenizer;
+
+/// Turns the tokens generated so far into the text added by the newest
+/// ones, decoding a few tokens per step instead of the whole sequence.
+///
+/// Tokens can't be decoded one by one: SentencePiece decoders drop the
+/// leading space of the first token they are given, and a character may be
+/// split over several byte tokens. Each step decodes a window that starts at
+/// the tokens of the previous delta and keeps only the text past them. A
+/// window ending in U+FFFD holds an incomplete character, so it waits for
+/// the next token.
+#[derive(Debug, Default)]
+pub struct Detokenizer {
+    /// Start of the window, which gives the new tokens their context
+    prefix_offset: usize,
+    /// First token whose text has not been emitted yet
+    read_offset: usize,
+}
+
+impl Detokenizer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The text added by `tokens` since the last delta, with the ids it was
+    /// decoded from, or `None` while the new tokens don't form complete
+    /// characters yet
+    pub fn step<'t>(
+        &mut self,
+        tokenizer: &Tokenizer,
+        tokens: &'t [u32],
+    ) -> Result<Option<(String, &'t [u32])>> {
+        self.advance(tokenizer, tokens, false)
+    }
+
+    /// The text of the tokens not emitted yet, incomplete characters
+    /// included, once no more tokens will follow
+    pub fn flush<'t>(
+        &mut self,
+        tokenizer: &Tokenizer,
+        tokens: &'t [u32],
+    ) -> Result<Option<(String, &'t [u32])>> {
+        self.advance(tokenizer, tokens, true)
+    }
+
+    fn advance<'t>(
+        &mut self,
+        tokenizer: &Tokenizer,
+        tokens: &'t [u32],
+        partial: bool,
+    ) -> Result<Option<(String, &'t [u32])>> {
+        if self.read_offset >= tokens.len() {
+            return Ok(None);
+        }
+        let prefix_text = decode(tokenizer, &tokens[self.prefix_offset..self.read_offset])?;
+        let new_text = decode(tokenizer, &tokens[self.prefix_offset..])?;
+        if !partial && new_text.ends_with('\u{FFFD}') {
+            return Ok(None);
+        }
+        let delta = match new_text.get(prefix_text.len()..) {
+            Some(delta) if !delta.is_empty() => delta.to_string(),
+            _ => return Ok(None),
+        };
+
+        let token_ids = &tokens[self.read_offset..];
+        self.prefix_offset = self.read_offset;
+        self.read_offset = tokens.len();
+        Ok(Some((delta, token_ids)))
+    }
+}
+
+fn decode(tokenizer: &Tokenizer, tokens: &[u32]) -> Result<String> {
+    tokenizer
+        .decode(tokens, true)
+        .map_err(|e| anyhow::anyhow!("Decode error: {}", e))
+}
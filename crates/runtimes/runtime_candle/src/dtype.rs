@@ -0,0 +1,120 @@
+//! Choosing the weight dtype for a chat model
+//!
+//! Without a configured dtype, each architecture lists the reduced-precision
+//! dtypes it is known to run correctly in, best first. The first one the
+//! device supports is used, and F32 otherwise.
+
+use candle_core::{DType, Device};
+
+/// The kind of device a model is loaded on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceKind {
+    Cpu,
+    Cuda,
+    Metal,
+}
+
+impl DeviceKind {
+    pub fn of(device: &Device) -> Self {
+        match device {
+            Device::Cpu => Self::Cpu,
+            Device::Cuda(_) => Self::Cuda,
+            Device::Metal(_) => Self::Metal,
+        }
+    }
+
+    /// Reduced-precision dtypes with fast kernels on this device. CPU
+    /// matmuls are fastest in F32, and Metal has no BF16 kernels.
+    fn supports(self, dtype: DType) -> bool {
+        match self {
+            Self::Cpu => false,
+            Self::Cuda => matches!(dtype, DType::BF16 | DType::F16),
+            Self::Metal => dtype == DType::F16,
+        }
+    }
+}
+
+impl std::fmt::Display for DeviceKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Cpu => write!(f, "CPU"),
+            Self::Cuda => write!(f, "CUDA"),
+            Self::Metal => write!(f, "Metal"),
+        }
+    }
+}
+
+/// Reduced-precision dtypes an architecture runs correctly in, best first
+struct Preference {
+    architectures: &'static [&'static str],
+    dtypes: &'static [DType],
+}
+
+const PREFERENCES: &[Preference] = &[
+    Preference {
+        architectures: &["llama", "mistral"],
+        dtypes: &[DType::BF16, DType::F16],
+    },
+    // Phi's attention scores overflow F16's range
+    Preference {
+        architectures: &["phi", "phi-msft", "phi2"],
+        dtypes: &[DType::BF16],
+    },
+];
+
+/// Architectures not listed above: BF16 has F32's range, so it is safe
+/// wherever it is supported, while F16 is unverified
+const DEFAULT_DTYPES: &[DType] = &[DType::BF16];
+
+/// The dtype to load a model in, and why
+#[derive(Debug, Clone, PartialEq)]
+pub struct DtypeChoice {
+    pub dtype: DType,
+    /// Shown in the load log
+    pub reason: String,
+    /// The dtype was picked automatically and may still produce non-finite
+    /// logits, so the loader checks a forward pass and falls back to F32
+    pub verify: bool,
+}
+
+/// Pick the weight dtype for an `architecture` (config.json `model_type`)
+/// on `device`; a `configured` dtype always wins
+pub fn resolve_dtype(
+    device: DeviceKind,
+    architecture: &str,
+    configured: Option<DType>,
+) -> DtypeChoice {
+    if let Some(dtype) = configured {
+        return DtypeChoice {
+            dtype,
+            reason: "set by inference.dtype (or --dtype)".to_string(),
+            verify: false,
+        };
+    }
+
+    let preferred = PREFERENCES
+        .iter()
+        .find(|p| p.architectures.contains(&architecture))
+        .map_or(DEFAULT_DTYPES, |p| p.dtypes);
+    match preferred.iter().find(|&&dtype| device.supports(dtype)) {
+        Some(&dtype) => DtypeChoice {
+            dtype,
+            reason: format!("preferred for '{}' models on {}", architecture, device),
+            verify: dtype == DType::F16,
+        },
+        None if device == DeviceKind::Cpu => DtypeChoice {
+            dtype: DType::F32,
+            reason: "CPU runs F32 fastest".to_string(),
+            verify: false,
+        },
+        None => DtypeChoice {
+            dtype: DType::F32,
+            reason: format!(
+                "no reduced precision verified for '{}' models on {} \
+                 (set inference.dtype to override)",
+                architecture, device
+            ),
+            verify: false,
+        },
+    }
+}
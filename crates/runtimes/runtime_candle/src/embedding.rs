@@ -0,0 +1,100 @@
+//! Sentence embedding models (BERT-style encoders)
+
+use anyhow::Result;
+use candle_core::{DType, Device, Tensor};
+use candle_nn::VarBuilder;
+use candle_transformers::models::bert::{BertModel, Config, DTYPE};
+use std::path::Path;
+use tokenizers::Tokenizer;
+
+use crate::model::{find_file, find_weights};
+
+pub struct EmbeddingModel {
+    model: BertModel,
+    tokenizer: Tokenizer,
+    device: Device,
+    max_tokens: usize,
+    /// `model_type` from config.json
+    architecture: String,
+}
+
+impl EmbeddingModel {
+    pub fn load(model_path: &Path, device: &Device) -> Result<Self> {
+        tracing::info!("Loading embedding model from {:?}", model_path);
+
+        let config_path = find_file(model_path, "config.json")?;
+        let tokenizer_path = find_file(model_path, "tokenizer.json")?;
+        let weights_path = find_weights(model_path)?;
+
+        let config_str = std::fs::read_to_string(&config_path)?;
+        let config: Config = serde_json::from_str(&config_str)?;
+        let config_json: serde_json::Value = serde_json::from_str(&config_str)?;
+        let max_tokens = config_json
+            .get("max_position_embeddings")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(512) as usize;
+        let architecture = config_json
+            .get("model_type")
+            .and_then(|v| v.as_str())
+            .unwrap_or("bert")
+            .to_string();
+
+        let tokenizer = Tokenizer::from_file(&tokenizer_path)
+            .map_err(|e| anyhow::anyhow!("Failed to load tokenizer: {}", e))?;
+
+        let vb = unsafe { VarBuilder::from_mmaped_safetensors(&[weights_path], DTYPE, device)? };
+        let model = BertModel::load(vb, &config)?;
+
+        Ok(Self {
+            model,
+            tokenizer,
+            device: device.clone(),
+            max_tokens,
+            architecture,
+        })
+    }
+
+    pub fn architecture(&self) -> &str {
+        &self.architecture
+    }
+
+    pub fn dtype(&self) -> DType {
+        DTYPE
+    }
+
+    /// Longest input in tokens; longer inputs are truncated
+    pub fn max_tokens(&self) -> usize {
+        self.max_tokens
+    }
+
+    /// Embed each input as the mean of its token vectors, L2-normalized.
+    ///
+    /// Returns the vectors and the total number of input tokens.
+    pub fn embed(&self, inputs: &[String]) -> Result<(Vec<Vec<f32>>, usize)> {
+        let mut embeddings = Vec::with_capacity(inputs.len());
+        let mut total_tokens = 0;
+
+        // One input at a time, so no padding or attention masking is needed
+        for input in inputs {
+            let encoding = self
+                .tokenizer
+                .encode(input.as_str(), true)
+                .map_err(|e| anyhow::anyhow!("Tokenization error: {}", e))?;
+            let ids = encoding.get_ids();
+            let ids = &ids[..ids.len().min(self.max_tokens)];
+            total_tokens += ids.len();
+
+            let input_ids = Tensor::new(ids, &self.device)?.unsqueeze(0)?;
+            let token_type_ids = input_ids.zeros_like()?;
+            let hidden = self.model.forward(&input_ids, &token_type_ids, None)?;
+
+            // (1, seq, hidden) -> (hidden)
+            let pooled = hidden.mean(1)?.squeeze(0)?;
+            let norm = pooled.sqr()?.sum_all()?.sqrt()?;
+            let normalized = pooled.broadcast_div(&norm)?;
+            embeddings.push(normalized.to_vec1::<f32>()?);
+        }
+
+        Ok((embeddings, total_tokens))
+    }
+}
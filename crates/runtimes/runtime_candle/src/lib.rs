@@ -1,25 +1,48 @@
 //! ohmygpu_runtime_candle - Candle-based inference runtime
 //!
-//! This crate provides LLM inference using HuggingFace's candle library.
+//! This crate provides LLM inference and text embeddings using HuggingFace's
+//! candle library.
 //! Supports Metal (macOS) and CUDA (Linux/Windows) acceleration.
 
+mod detokenize;
+mod dtype;
+mod embedding;
 mod model;
 mod sampling;
+mod template;
 
 use anyhow::Result;
 use async_trait::async_trait;
+use ohmygpu_core::ModelType;
 use ohmygpu_runtime_api::{
-    ChatRequest, ChatResponse, ChatToken, Runtime, RuntimeCaps, RuntimeConfig, RuntimeStatus,
+    ChatMessage, ChatRequest, ChatResponse, ChatToken, EmbeddingResponse, LoadedModelInfo, Runtime,
+    RuntimeCaps, RuntimeConfig, RuntimeError, RuntimeStatus,
 };
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
-use model::LoadedModel;
+pub use detokenize::Detokenizer;
+pub use dtype::{resolve_dtype, DeviceKind, DtypeChoice};
+pub use model::{ChannelSink, GenerationStats, GenerationTimer, TokenSink};
+pub use sampling::{Sampler, SamplerConfig};
+pub use template::PromptFormat;
+
+use embedding::EmbeddingModel;
+use model::{GenerationParams, LoadedModel};
+use template::ChatTemplate;
+
+const DEFAULT_STREAM_BUFFER_SIZE: usize = 100;
 
 pub struct CandleRuntime {
     status: RuntimeStatus,
     config: Option<RuntimeConfig>,
     model: Arc<RwLock<Option<LoadedModel>>>,
+    embedder: Arc<RwLock<Option<EmbeddingModel>>>,
+    chat_template: Option<ChatTemplate>,
+    /// Built-in format detected for the loaded model
+    prompt_format: PromptFormat,
+    /// Details of the loaded model, filled in once loading finishes
+    model_info: Option<LoadedModelInfo>,
 }
 
 impl CandleRuntime {
@@ -28,28 +51,63 @@ impl CandleRuntime {
             status: RuntimeStatus::Unloaded,
             config: None,
             model: Arc::new(RwLock::new(None)),
+            embedder: Arc::new(RwLock::new(None)),
+            chat_template: None,
+            prompt_format: PromptFormat::default(),
+            model_info: None,
         }
     }
 
-    fn get_device() -> Result<candle_core::Device> {
+    /// Type of the loaded model; chat models unless configured otherwise
+    fn model_type(&self) -> ModelType {
+        self.config
+            .as_ref()
+            .and_then(|c| c.model_type.clone())
+            .unwrap_or(ModelType::LLM)
+    }
+
+    /// Fail unless a chat model is loaded
+    fn check_chat_ready(&self) -> Result<()> {
+        if self.status != RuntimeStatus::Ready {
+            anyhow::bail!("Model not loaded");
+        }
+        match self.model_type() {
+            ModelType::Embedding => {
+                Err(RuntimeError::NotAChatModel(ModelType::Embedding.as_str()).into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn get_device(gpu_id: usize) -> Result<candle_core::Device> {
         #[cfg(feature = "metal")]
         {
-            tracing::info!("Using Metal device");
-            Ok(candle_core::Device::new_metal(0)?)
+            tracing::info!("Using Metal device {}", gpu_id);
+            Ok(candle_core::Device::new_metal(gpu_id)?)
         }
         #[cfg(feature = "cuda")]
         {
-            tracing::info!("Using CUDA device");
-            Ok(candle_core::Device::new_cuda(0)?)
+            tracing::info!("Using CUDA device {}", gpu_id);
+            Ok(candle_core::Device::new_cuda(gpu_id)?)
         }
         #[cfg(not(any(feature = "metal", feature = "cuda")))]
         {
+            let _ = gpu_id;
             tracing::info!("Using CPU device (no GPU features enabled)");
             Ok(candle_core::Device::Cpu)
         }
     }
 }
 
+/// Short device name such as "cuda:0" or "cpu"
+fn device_name(device: &candle_core::Device) -> String {
+    match device.location() {
+        candle_core::DeviceLocation::Cpu => "cpu".to_string(),
+        candle_core::DeviceLocation::Cuda { gpu_id } => format!("cuda:{}", gpu_id),
+        candle_core::DeviceLocation::Metal { gpu_id } => format!("metal:{}", gpu_id),
+    }
+}
+
 impl Default for CandleRuntime {
     fn default() -> Self {
         Self::new()
@@ -59,13 +117,16 @@ impl Default for CandleRuntime {
 #[async_trait]
 impl Runtime for CandleRuntime {
     fn caps(&self) -> RuntimeCaps {
-        RuntimeCaps {
-            chat: true,
-            completions: true,
-            embeddings: false,
-            images: false,
-            audio: false,
-            streaming: true,
+        match self.model_type() {
+            ModelType::Embedding => RuntimeCaps::for_model_type(&ModelType::Embedding),
+            _ => RuntimeCaps {
+                chat: true,
+                completions: true,
+                embeddings: false,
+                images: false,
+                audio: false,
+                streaming: true,
+            },
         }
     }
 
@@ -73,22 +134,83 @@ impl Runtime for CandleRuntime {
         self.status
     }
 
+    fn model_info(&self) -> Option<LoadedModelInfo> {
+        self.model_info.clone()
+    }
+
     async fn load(&mut self, config: RuntimeConfig) -> Result<()> {
+        let chat_template = config
+            .chat_template
+            .as_deref()
+            .map(ChatTemplate::from_config_value)
+            .transpose()?;
+        let dtype = config
+            .dtype
+            .as_deref()
+            .map(|d| {
+                d.parse::<candle_core::DType>()
+                    .map_err(|_| anyhow::anyhow!("Unsupported dtype '{}'", d))
+            })
+            .transpose()?;
+
         self.status = RuntimeStatus::Loading;
         tracing::info!("Loading model from {:?}", config.model_path);
 
-        let device = Self::get_device()?;
+        let device = Self::get_device(config.gpu_id.unwrap_or(0) as usize)?;
         tracing::info!("Device: {:?}", device);
+        let device_name = device_name(&device);
 
         // Load the model
         let model_path = config.model_path.clone();
-        let loaded = tokio::task::spawn_blocking(move || {
-            LoadedModel::load(&model_path, &device)
-        })
-        .await??;
+        let context_length = config.context_length;
+        let is_embedding = config.model_type == Some(ModelType::Embedding);
+        let (architecture, dtype, context_length) = if is_embedding {
+            let loaded =
+                tokio::task::spawn_blocking(move || EmbeddingModel::load(&model_path, &device))
+                    .await?
+                    .map_err(RuntimeError::map_out_of_memory)?;
+            let details = (
+                loaded.architecture().to_string(),
+                loaded.dtype(),
+                loaded.max_tokens(),
+            );
+            *self.embedder.write().await = Some(loaded);
+            details
+        } else {
+            let loaded = tokio::task::spawn_blocking(move || {
+                LoadedModel::load(&model_path, &device, dtype, context_length)
+            })
+            .await?
+            .map_err(RuntimeError::map_out_of_memory)?;
+            self.prompt_format = loaded.prompt_format();
+            tracing::info!("Prompt format: {:?}", self.prompt_format);
+            let details = (
+                loaded.architecture().to_string(),
+                loaded.dtype(),
+                loaded.context_length(),
+            );
+            *self.model.write().await = Some(loaded);
+            details
+        };
 
-        *self.model.write().await = Some(loaded);
+        let name = config.model_name.clone().unwrap_or_else(|| {
+            config
+                .model_path
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        });
+
+        self.chat_template = chat_template;
         self.config = Some(config);
+        self.model_info = Some(LoadedModelInfo {
+            name,
+            architecture,
+            dtype: dtype.as_str().to_string(),
+            device: device_name,
+            context_length,
+            caps: self.caps(),
+        });
         self.status = RuntimeStatus::Ready;
 
         tracing::info!("Model loaded successfully");
@@ -98,36 +220,58 @@ impl Runtime for CandleRuntime {
     async fn unload(&mut self) -> Result<()> {
         tracing::info!("Unloading model");
         *self.model.write().await = None;
+        *self.embedder.write().await = None;
+        self.chat_template = None;
+        self.prompt_format = PromptFormat::default();
+        self.model_info = None;
         self.config = None;
         self.status = RuntimeStatus::Unloaded;
         Ok(())
     }
 
-    async fn chat(&self, request: ChatRequest) -> Result<ChatResponse> {
-        if self.status != RuntimeStatus::Ready {
-            anyhow::bail!("Model not loaded");
+    fn render_prompt(&self, messages: &[ChatMessage]) -> String {
+        match &self.chat_template {
+            Some(template) => template.render(messages),
+            None => self.prompt_format.render(messages),
         }
+    }
 
-        let model_guard = self.model.read().await;
-        let model = model_guard
+    async fn count_prompt_tokens(&self, messages: &[ChatMessage]) -> Result<usize> {
+        self.check_chat_ready()?;
+        let prompt = self.render_prompt(messages);
+        self.model
+            .read()
+            .await
             .as_ref()
-            .ok_or_else(|| anyhow::anyhow!("Model not loaded"))?;
+            .ok_or_else(|| anyhow::anyhow!("Model not loaded"))?
+            .count_tokens(&prompt)
+    }
+
+    async fn chat(&self, request: ChatRequest) -> Result<ChatResponse> {
+        self.check_chat_ready()?;
 
         // Build prompt from messages
-        let prompt = build_chat_prompt(&request.messages);
+        let prompt = self.render_prompt(&request.messages);
         tracing::debug!("Prompt: {}", prompt);
+        let params = generation_params(&request);
 
-        // Generate response
-        let response = model.generate(
-            &prompt,
-            request.max_tokens as usize,
-            request.temperature,
-        )?;
+        // Generate on a blocking thread; the guard keeps the model alive
+        let model_guard = self.model.clone().read_owned().await;
+        let response = tokio::task::spawn_blocking(move || {
+            let model = model_guard
+                .as_ref()
+                .ok_or_else(|| anyhow::anyhow!("Model not loaded"))?;
+            model.generate(&prompt, &params)
+        })
+        .await?
+        .map_err(RuntimeError::map_out_of_memory)?;
 
         Ok(ChatResponse {
             content: response.text,
-            tokens_used: response.tokens_generated as u32,
-            finish_reason: response.finish_reason,
+            tokens_used: response.stats.tokens_generated as u32,
+            finish_reason: response.stats.finish_reason,
+            prefill_ms: response.stats.prefill_ms,
+            generation_ms: response.stats.generation_ms,
         })
     }
 
@@ -135,49 +279,83 @@ impl Runtime for CandleRuntime {
         &self,
         request: ChatRequest,
     ) -> Result<tokio::sync::mpsc::Receiver<ChatToken>> {
-        if self.status != RuntimeStatus::Ready {
-            anyhow::bail!("Model not loaded");
-        }
+        self.check_chat_ready()?;
 
-        let (tx, rx) = tokio::sync::mpsc::channel(100);
-        let model = self.model.clone();
-        let prompt = build_chat_prompt(&request.messages);
-        let max_tokens = request.max_tokens as usize;
-        let temperature = request.temperature;
+        // Generation blocks on a full channel, so a slow client throttles it
+        // instead of losing tokens
+        let buffer_size = self
+            .config
+            .as_ref()
+            .and_then(|c| c.stream_buffer_size)
+            .unwrap_or(DEFAULT_STREAM_BUFFER_SIZE)
+            .max(1);
+        let (tx, rx) = tokio::sync::mpsc::channel(buffer_size);
+        let prompt = self.render_prompt(&request.messages);
+        let params = generation_params(&request);
+
+        let model_guard = self.model.clone().read_owned().await;
+
+        // Check the prompt up front so callers get the error before streaming
+        let prompt_tokens = model_guard
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Model not loaded"))?
+            .encode_prompt(&prompt)?;
 
-        tokio::spawn(async move {
-            let model_guard = model.read().await;
+        tokio::task::spawn_blocking(move || {
             if let Some(loaded_model) = model_guard.as_ref() {
-                if let Err(e) = loaded_model.generate_stream(&prompt, max_tokens, temperature, tx).await {
+                // The stream's last token reports a failure to the client
+                let error_tx = tx.clone();
+                if let Err(e) = loaded_model.generate_stream(&prompt_tokens, &params, tx) {
+                    let e = RuntimeError::map_out_of_memory(e);
                     tracing::error!("Generation error: {}", e);
+                    let _ = error_tx.blocking_send(ChatToken::failed(&e));
                 }
             }
         });
 
         Ok(rx)
     }
-}
-
-fn build_chat_prompt(messages: &[ohmygpu_runtime_api::ChatMessage]) -> String {
-    // Simple chat template (Llama-style)
-    let mut prompt = String::new();
 
-    for msg in messages {
-        match msg.role.as_str() {
-            "system" => {
-                prompt.push_str(&format!("<<SYS>>\n{}\n<</SYS>>\n\n", msg.content));
-            }
-            "user" => {
-                prompt.push_str(&format!("[INST] {} [/INST]", msg.content));
-            }
-            "assistant" => {
-                prompt.push_str(&format!(" {} ", msg.content));
-            }
-            _ => {
-                prompt.push_str(&msg.content);
-            }
+    async fn embed(&self, inputs: Vec<String>) -> Result<EmbeddingResponse> {
+        if self.status != RuntimeStatus::Ready {
+            anyhow::bail!("Model not loaded");
+        }
+        if self.model_type() != ModelType::Embedding {
+            return Err(RuntimeError::NotAnEmbeddingModel.into());
         }
+
+        let embedder_guard = self.embedder.clone().read_owned().await;
+        let (embeddings, tokens) = tokio::task::spawn_blocking(move || {
+            let embedder = embedder_guard
+                .as_ref()
+                .ok_or_else(|| anyhow::anyhow!("Model not loaded"))?;
+            embedder.embed(&inputs)
+        })
+        .await?
+        .map_err(RuntimeError::map_out_of_memory)?;
+
+        Ok(EmbeddingResponse {
+            embeddings,
+            tokens_used: tokens as u32,
+        })
     }
+}
 
-    prompt
+fn generation_params(request: &ChatRequest) -> GenerationParams {
+    GenerationParams {
+        max_tokens: request.max_tokens as usize,
+        sampling: SamplerConfig {
+            temperature: request.temperature,
+            top_p: request.top_p,
+            top_k: request.top_k as usize,
+            min_p: request.min_p,
+            repetition_penalty: request.repetition_penalty,
+            frequency_penalty: request.frequency_penalty,
+            presence_penalty: request.presence_penalty,
+            logit_bias: request.logit_bias.clone(),
+            seed: request
+                .seed
+                .unwrap_or_else(ohmygpu_core::random::random_u64),
+        },
+    }
 }
@@ -5,46 +5,214 @@ use candle_core::{DType, Device, Tensor};
 use candle_nn::VarBuilder;
 use candle_transformers::models::llama as llama_model;
 use candle_transformers::models::phi as phi_model;
-use ohmygpu_runtime_api::ChatToken;
+use ohmygpu_runtime_api::{ChatToken, RuntimeError};
 use std::path::Path;
 use std::sync::Mutex;
+use std::time::Instant;
 use tokenizers::Tokenizer;
 
-use crate::sampling::Sampler;
+use crate::detokenize::Detokenizer;
+use crate::dtype::{resolve_dtype, DeviceKind};
+use crate::sampling::{Sampler, SamplerConfig};
+use crate::template::PromptFormat;
 
 pub struct GenerationResult {
     pub text: String,
+    pub stats: GenerationStats,
+}
+
+/// What the generation loop reports besides the text itself
+#[derive(Debug, Clone)]
+pub struct GenerationStats {
     pub tokens_generated: usize,
     pub finish_reason: String,
+    /// Time spent on the prompt forward pass, in milliseconds
+    pub prefill_ms: u64,
+    /// Total wall time of the generation, including prefill, in milliseconds
+    pub generation_ms: u64,
+}
+
+/// Wall-clock timing of one generation, measured from its start
+#[derive(Debug, Clone)]
+pub struct GenerationTimer {
+    start: Instant,
+    prefill_ms: u64,
+    prefill_done: bool,
+}
+
+impl GenerationTimer {
+    pub fn start() -> Self {
+        Self {
+            start: Instant::now(),
+            prefill_ms: 0,
+            prefill_done: false,
+        }
+    }
+
+    /// Mark the end of the prompt forward pass; later calls are ignored
+    pub fn prefill_done(&mut self) {
+        if !self.prefill_done {
+            self.prefill_ms = self.elapsed_ms();
+            self.prefill_done = true;
+        }
+    }
+
+    pub fn elapsed_ms(&self) -> u64 {
+        self.start.elapsed().as_millis() as u64
+    }
+
+    /// Stats for a generation ending now
+    pub fn finish(&self, tokens_generated: usize, finish_reason: &str) -> GenerationStats {
+        GenerationStats {
+            tokens_generated,
+            finish_reason: finish_reason.to_string(),
+            prefill_ms: self.prefill_ms,
+            generation_ms: self.elapsed_ms(),
+        }
+    }
+}
+
+/// Per-request generation parameters
+#[derive(Debug, Clone)]
+pub struct GenerationParams {
+    pub max_tokens: usize,
+    pub sampling: SamplerConfig,
+}
+
+/// Receives decoded text as the generation loop produces it
+pub trait TokenSink {
+    /// Called with each new piece of decoded text and the ids of the tokens
+    /// it was decoded from (several when a character spans tokens).
+    /// Returning `false` stops generation (e.g. the client went away).
+    fn on_text(&mut self, delta: &str, token_ids: &[u32]) -> bool;
+
+    /// Called once when generation ends normally
+    fn on_finish(&mut self, finish_reason: &str);
+}
+
+impl<T: TokenSink + ?Sized> TokenSink for &mut T {
+    fn on_text(&mut self, delta: &str, token_ids: &[u32]) -> bool {
+        (**self).on_text(delta, token_ids)
+    }
+
+    fn on_finish(&mut self, finish_reason: &str) {
+        (**self).on_finish(finish_reason)
+    }
+}
+
+/// Collects the whole completion into a buffer (non-streaming path)
+#[derive(Default)]
+struct BufferSink {
+    text: String,
+}
+
+impl TokenSink for BufferSink {
+    fn on_text(&mut self, delta: &str, _token_ids: &[u32]) -> bool {
+        self.text.push_str(delta);
+        true
+    }
+
+    fn on_finish(&mut self, _finish_reason: &str) {}
+}
+
+/// Forwards text deltas to a channel (streaming path).
+///
+/// Must be driven from a blocking thread: sends wait for channel capacity,
+/// so a slow consumer holds generation back rather than losing tokens.
+pub struct ChannelSink {
+    tx: tokio::sync::mpsc::Sender<ChatToken>,
+}
+
+impl ChannelSink {
+    pub fn new(tx: tokio::sync::mpsc::Sender<ChatToken>) -> Self {
+        Self { tx }
+    }
+}
+
+impl TokenSink for ChannelSink {
+    fn on_text(&mut self, delta: &str, token_ids: &[u32]) -> bool {
+        self.tx
+            .blocking_send(ChatToken {
+                content: delta.to_string(),
+                token_ids: token_ids.to_vec(),
+                finish_reason: None,
+                error: None,
+            })
+            .is_ok()
+    }
+
+    fn on_finish(&mut self, finish_reason: &str) {
+        let _ = self.tx.blocking_send(ChatToken {
+            content: String::new(),
+            token_ids: Vec::new(),
+            finish_reason: Some(finish_reason.to_string()),
+            error: None,
+        });
+    }
 }
 
 pub struct LoadedModel {
     model: ModelType,
     tokenizer: Tokenizer,
     device: Device,
-    #[allow(dead_code)]
     dtype: DType,
     eos_token_id: Option<u32>,
+    /// Maximum sequence length (prompt + completion)
+    context_length: usize,
+    /// `model_type` from config.json
+    architecture: String,
 }
 
 enum ModelType {
     Llama {
         model: llama_model::Llama,
+        config: llama_model::Config,
         cache: Mutex<llama_model::Cache>,
     },
     Phi(Mutex<phi_model::Model>),
 }
 
 impl LoadedModel {
-    pub fn load(model_path: &Path, device: &Device) -> Result<Self> {
-        tracing::info!("Loading model from {:?}", model_path);
+    /// Load a model, using `dtype` if given or choosing one for the device
+    /// and architecture (see [`resolve_dtype`]).
+    ///
+    /// An automatically chosen F16 is checked with a test forward pass; if
+    /// loading or the check fails, the model is reloaded in F32.
+    ///
+    /// `context_length` shrinks the context window (and the KV cache) below
+    /// the model's maximum; it is an error for it to exceed that maximum.
+    pub fn load(
+        model_path: &Path,
+        device: &Device,
+        dtype: Option<DType>,
+        context_length: Option<usize>,
+    ) -> Result<Self> {
+        let load = |dtype| Self::load_with_dtype(model_path, device, dtype, context_length);
+        let architecture = model_architecture(model_path)?;
+        let choice = resolve_dtype(DeviceKind::of(device), &architecture, dtype);
+        tracing::info!("Using {:?} weights: {}", choice.dtype, choice.reason);
+        if !choice.verify {
+            return load(choice.dtype);
+        }
 
-        // Determine dtype based on device
-        let dtype = match device {
-            Device::Metal(_) => DType::F32, // Metal works best with F32
-            Device::Cuda(_) => DType::BF16, // CUDA can use BF16
-            Device::Cpu => DType::F32,
-        };
+        let attempt = load(choice.dtype).and_then(|model| model.check_logits().map(|_| model));
+        attempt.or_else(|e| {
+            tracing::warn!(
+                "{:?} failed ({}); falling back to F32 weights, which need twice the memory",
+                choice.dtype,
+                e
+            );
+            load(DType::F32)
+        })
+    }
+
+    fn load_with_dtype(
+        model_path: &Path,
+        device: &Device,
+        dtype: DType,
+        context_override: Option<usize>,
+    ) -> Result<Self> {
+        tracing::info!("Loading model from {:?}", model_path);
 
         // Find model files
         let config_path = find_file(model_path, "config.json")?;
@@ -66,6 +234,29 @@ impl LoadedModel {
 
         tracing::info!("Model type: {}", model_type_str);
 
+        let model_context_length = context_length(&config_json);
+        let context_length = match context_override {
+            Some(0) => anyhow::bail!("Context length must be at least 1"),
+            Some(n) if n > model_context_length => anyhow::bail!(
+                "Context length {} exceeds the model's maximum of {}; lower \
+                 inference.context_length (or --ctx)",
+                n,
+                model_context_length
+            ),
+            Some(n) => {
+                tracing::info!(
+                    "Context length: {} (model maximum {})",
+                    n,
+                    model_context_length
+                );
+                n
+            }
+            None => {
+                tracing::info!("Context length: {}", model_context_length);
+                model_context_length
+            }
+        };
+
         // Load tokenizer
         let tokenizer = Tokenizer::from_file(&tokenizer_path)
             .map_err(|e| anyhow::anyhow!("Failed to load tokenizer: {}", e))?;
@@ -95,11 +286,13 @@ impl LoadedModel {
                 // Default to Llama for llama, mistral, etc.
                 tracing::info!("Loading Llama-style model");
                 let config: llama_model::LlamaConfig = serde_json::from_str(&config_str)?;
-                let config = config.into_config(false); // use_flash_attn = false
+                let mut config = config.into_config(false); // use_flash_attn = false
+                config.max_position_embeddings = context_length; // sizes the cache
                 let model = llama_model::Llama::load(vb, &config)?;
                 let cache = llama_model::Cache::new(true, dtype, &config, device)?;
                 ModelType::Llama {
                     model,
+                    config,
                     cache: Mutex::new(cache),
                 }
             }
@@ -111,148 +304,205 @@ impl LoadedModel {
             device: device.clone(),
             dtype,
             eos_token_id,
+            context_length,
+            architecture: model_type_str.to_string(),
         })
     }
 
-    pub fn generate(
-        &self,
-        prompt: &str,
-        max_tokens: usize,
-        temperature: f32,
-    ) -> Result<GenerationResult> {
-        let tokens = self
-            .tokenizer
-            .encode(prompt, true)
-            .map_err(|e| anyhow::anyhow!("Tokenization error: {}", e))?;
-
-        let input_ids = tokens.get_ids();
-        let mut all_tokens = input_ids.to_vec();
-
-        let mut sampler = Sampler::new(temperature, 0.9, 42);
-
-        let mut generated = 0;
-        let mut finish_reason = "length".to_string();
+    pub fn architecture(&self) -> &str {
+        &self.architecture
+    }
 
-        for _ in 0..max_tokens {
-            let input = Tensor::new(&all_tokens[..], &self.device)?
-                .unsqueeze(0)?;
+    pub fn dtype(&self) -> DType {
+        self.dtype
+    }
 
-            let logits = self.forward(&input, all_tokens.len())?;
-            let logits = logits.squeeze(0)?;
-            let last_logits = logits.get(logits.dim(0)? - 1)?;
+    pub fn context_length(&self) -> usize {
+        self.context_length
+    }
 
-            let next_token = sampler.sample(&last_logits)?;
+    /// Built-in prompt format for this model, detected from its tokenizer:
+    /// Llama 3 vocabularies have dedicated role header tokens
+    pub fn prompt_format(&self) -> PromptFormat {
+        if self.tokenizer.token_to_id("<|start_header_id|>").is_some() {
+            PromptFormat::Llama3
+        } else {
+            PromptFormat::Llama2
+        }
+    }
 
-            if Some(next_token) == self.eos_token_id {
-                finish_reason = "stop".to_string();
-                break;
+    /// Tokenize a prompt, rejecting it if it leaves no room to generate
+    pub fn encode_prompt(&self, prompt: &str) -> Result<Vec<u32>> {
+        let prompt_tokens = self.tokenize(prompt)?;
+        if prompt_tokens.len() >= self.context_length {
+            return Err(RuntimeError::PromptTooLong {
+                prompt_tokens: prompt_tokens.len(),
+                context_length: self.context_length,
             }
-
-            all_tokens.push(next_token);
-            generated += 1;
+            .into());
         }
+        Ok(prompt_tokens)
+    }
 
-        // Decode only the generated tokens
-        let generated_tokens = &all_tokens[input_ids.len()..];
-        let text = self
-            .tokenizer
-            .decode(generated_tokens, true)
-            .map_err(|e| anyhow::anyhow!("Decode error: {}", e))?;
+    pub fn count_tokens(&self, text: &str) -> Result<usize> {
+        Ok(self.tokenize(text)?.len())
+    }
+
+    pub fn generate(&self, prompt: &str, params: &GenerationParams) -> Result<GenerationResult> {
+        let prompt_tokens = self.encode_prompt(prompt)?;
+        let mut sink = BufferSink::default();
+        let stats = self.run_generation(&prompt_tokens, params, &mut sink)?;
 
         Ok(GenerationResult {
-            text,
-            tokens_generated: generated,
-            finish_reason,
+            text: sink.text,
+            stats,
         })
     }
 
-    pub async fn generate_stream(
+    /// Stream generated text to `tx`. Blocks until generation finishes, so
+    /// call it from a blocking task.
+    pub fn generate_stream(
         &self,
-        prompt: &str,
-        max_tokens: usize,
-        temperature: f32,
+        prompt_tokens: &[u32],
+        params: &GenerationParams,
         tx: tokio::sync::mpsc::Sender<ChatToken>,
     ) -> Result<()> {
-        let tokens = self
-            .tokenizer
-            .encode(prompt, true)
-            .map_err(|e| anyhow::anyhow!("Tokenization error: {}", e))?;
-
-        let input_ids = tokens.get_ids();
-        let mut all_tokens = input_ids.to_vec();
-
-        let mut sampler = Sampler::new(temperature, 0.9, 42);
+        self.run_generation(prompt_tokens, params, ChannelSink::new(tx))?;
+        Ok(())
+    }
 
-        let mut prev_text_len = 0;
+    /// The generation core shared by the streaming and non-streaming paths.
+    pub fn run_generation(
+        &self,
+        prompt_tokens: &[u32],
+        params: &GenerationParams,
+        mut sink: impl TokenSink,
+    ) -> Result<GenerationStats> {
+        let mut timer = GenerationTimer::start();
+
+        // Never generate past the context window
+        let available = self.context_length.saturating_sub(prompt_tokens.len());
+        if available == 0 {
+            return Err(RuntimeError::PromptTooLong {
+                prompt_tokens: prompt_tokens.len(),
+                context_length: self.context_length,
+            }
+            .into());
+        }
+        let max_tokens = params.max_tokens.min(available);
+        if max_tokens < params.max_tokens {
+            tracing::info!(
+                "Clamping max_tokens from {} to {} ({} prompt tokens, context length {})",
+                params.max_tokens,
+                max_tokens,
+                prompt_tokens.len(),
+                self.context_length
+            );
+        }
 
-        for i in 0..max_tokens {
-            let input = Tensor::new(&all_tokens[..], &self.device)?
-                .unsqueeze(0)?;
+        self.clear_cache()?;
+        let mut sampler = Sampler::from_config(params.sampling.clone());
 
-            let logits = self.forward(&input, all_tokens.len())?;
-            let logits = logits.squeeze(0)?;
-            let last_logits = logits.get(logits.dim(0)? - 1)?;
+        let mut detokenizer = Detokenizer::new();
 
-            let next_token = sampler.sample(&last_logits)?;
+        for _ in 0..max_tokens {
+            let next_token = self.next_token(prompt_tokens, &mut sampler)?;
+            // The first step runs the whole prompt through the model
+            timer.prefill_done();
 
             if Some(next_token) == self.eos_token_id {
-                let _ = tx
-                    .send(ChatToken {
-                        content: String::new(),
-                        finish_reason: Some("stop".to_string()),
-                    })
-                    .await;
-                return Ok(());
+                // The EOS token itself is not part of the completion
+                let history = sampler.history();
+                let completion = &history[..history.len() - 1];
+                if let Some((delta, token_ids)) = detokenizer.flush(&self.tokenizer, completion)? {
+                    sink.on_text(&delta, token_ids);
+                }
+                sink.on_finish("stop");
+                return Ok(timer.finish(completion.len(), "stop"));
             }
 
-            all_tokens.push(next_token);
-
-            // Decode current text and send delta
-            let generated_tokens = &all_tokens[input_ids.len()..];
-            let current_text = self
-                .tokenizer
-                .decode(generated_tokens, true)
-                .map_err(|e| anyhow::anyhow!("Decode error: {}", e))?;
-
-            if current_text.len() > prev_text_len {
-                let delta = current_text[prev_text_len..].to_string();
-                prev_text_len = current_text.len();
-
-                if tx
-                    .send(ChatToken {
-                        content: delta,
-                        finish_reason: None,
-                    })
-                    .await
-                    .is_err()
-                {
-                    // Receiver dropped
-                    return Ok(());
+            // Decode only the new tokens and emit their text
+            if let Some((delta, token_ids)) =
+                detokenizer.step(&self.tokenizer, sampler.history())?
+            {
+                if !sink.on_text(&delta, token_ids) {
+                    // Receiver went away; nobody is listening for the rest
+                    return Ok(timer.finish(sampler.history().len(), "cancelled"));
                 }
             }
+        }
 
-            // Small yield to allow other tasks
-            if i % 10 == 0 {
-                tokio::task::yield_now().await;
-            }
+        if let Some((delta, token_ids)) = detokenizer.flush(&self.tokenizer, sampler.history())? {
+            sink.on_text(&delta, token_ids);
         }
+        sink.on_finish("length");
+        Ok(timer.finish(sampler.history().len(), "length"))
+    }
 
-        // Send final token indicating we hit length limit
-        let _ = tx
-            .send(ChatToken {
-                content: String::new(),
-                finish_reason: Some("length".to_string()),
-            })
-            .await;
+    /// Run a short prompt through the model and make sure the logits are
+    /// finite, which reduced-precision weights don't always guarantee
+    fn check_logits(&self) -> Result<()> {
+        let tokens = self.tokenize("Hello")?;
+        let input = Tensor::new(tokens.as_slice(), &self.device)?.unsqueeze(0)?;
+        let logits = self.forward(&input, 0)?;
+        self.clear_cache()?;
+
+        let values = logits
+            .to_dtype(DType::F32)?
+            .flatten_all()?
+            .to_vec1::<f32>()?;
+        if values.iter().any(|v| !v.is_finite()) {
+            anyhow::bail!("test forward pass produced non-finite logits");
+        }
+        Ok(())
+    }
 
+    fn tokenize(&self, prompt: &str) -> Result<Vec<u32>> {
+        let tokens = self
+            .tokenizer
+            .encode(prompt, true)
+            .map_err(|e| anyhow::anyhow!("Tokenization error: {}", e))?;
+        Ok(tokens.get_ids().to_vec())
+    }
+
+    /// Run one decoding step and sample the next token.
+    ///
+    /// The first step feeds the whole prompt; later steps only feed the last
+    /// sampled token since earlier positions are already in the KV cache.
+    fn next_token(&self, prompt_tokens: &[u32], sampler: &mut Sampler) -> Result<u32> {
+        let history = sampler.history();
+        let (input, index_pos) = match history.last() {
+            None => (Tensor::new(prompt_tokens, &self.device)?, 0),
+            Some(&last) => (
+                Tensor::new(&[last], &self.device)?,
+                prompt_tokens.len() + history.len() - 1,
+            ),
+        };
+
+        let logits = self.forward(&input.unsqueeze(0)?, index_pos)?;
+        let logits = logits.squeeze(0)?;
+
+        sampler.sample(&logits)
+    }
+
+    fn clear_cache(&self) -> Result<()> {
+        match &self.model {
+            ModelType::Llama { config, cache, .. } => {
+                let mut cache_guard = cache.lock().unwrap();
+                *cache_guard = llama_model::Cache::new(true, self.dtype, config, &self.device)?;
+            }
+            ModelType::Phi(m) => {
+                m.lock().unwrap().clear_kv_cache();
+            }
+        }
         Ok(())
     }
 
-    fn forward(&self, input: &Tensor, seq_len: usize) -> Result<Tensor> {
+    fn forward(&self, input: &Tensor, index_pos: usize) -> Result<Tensor> {
         match &self.model {
-            ModelType::Llama { model, cache } => {
+            ModelType::Llama { model, cache, .. } => {
                 let mut cache_guard = cache.lock().unwrap();
-                Ok(model.forward(input, seq_len - 1, &mut cache_guard)?)
+                Ok(model.forward(input, index_pos, &mut cache_guard)?)
             }
             ModelType::Phi(m) => {
                 let mut model_guard = m.lock().unwrap();
@@ -263,17 +513,40 @@ impl LoadedModel {
 
 }
 
+/// The `model_type` declared in config.json (defaults to "llama")
+fn model_architecture(model_path: &Path) -> Result<String> {
+    let config_path = find_file(model_path, "config.json")?;
+    let config_json: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(config_path)?)?;
+    Ok(config_json
+        .get("model_type")
+        .and_then(|v| v.as_str())
+        .unwrap_or("llama")
+        .to_string())
+}
+
+/// Context length from config.json, falling back to a conservative default
+fn context_length(config_json: &serde_json::Value) -> usize {
+    ["max_position_embeddings", "n_positions", "max_seq_len"]
+        .iter()
+        .find_map(|key| config_json.get(key).and_then(|v| v.as_u64()))
+        .map(|n| n as usize)
+        .unwrap_or(2048)
+}
+
 fn get_eos_token_id(tokenizer: &Tokenizer) -> Option<u32> {
     let vocab = tokenizer.get_vocab(true);
+    // Llama 3 ends chat turns with <|eot_id|> rather than end-of-text
     vocab
-        .get("</s>")
+        .get("<|eot_id|>")
+        .or_else(|| vocab.get("</s>"))
         .or_else(|| vocab.get("<|endoftext|>"))
         .or_else(|| vocab.get("<eos>"))
         .or_else(|| vocab.get("<|end|>"))
         .copied()
 }
 
-fn find_file(model_path: &Path, filename: &str) -> Result<std::path::PathBuf> {
+pub(crate) fn find_file(model_path: &Path, filename: &str) -> Result<std::path::PathBuf> {
     let direct = model_path.join(filename);
     if direct.exists() {
         return Ok(direct);
@@ -291,7 +564,7 @@ fn find_file(model_path: &Path, filename: &str) -> Result<std::path::PathBuf> {
     anyhow::bail!("Could not find {} in {:?}", filename, model_path)
 }
 
-fn find_weights(model_path: &Path) -> Result<std::path::PathBuf> {
+pub(crate) fn find_weights(model_path: &Path) -> Result<std::path::PathBuf> {
     // Look for safetensors files
     let patterns = [
         "model.safetensors",
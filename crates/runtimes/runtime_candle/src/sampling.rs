@@ -2,33 +2,123 @@
 
 use anyhow::Result;
 use candle_core::Tensor;
+use std::collections::HashMap;
+
+/// Sampling parameters for one request.
+///
+/// The defaults match a request that sets nothing: temperature and top-p
+/// from the API defaults and every other filter and penalty off. The seed is
+/// fixed; the runtime replaces it with a random one for requests without a
+/// `seed`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SamplerConfig {
+    pub temperature: f32,
+    /// Sample from the most likely tokens covering this much probability
+    /// mass; 1 disables
+    pub top_p: f32,
+    /// Sample from the `top_k` most likely tokens only; 0 disables
+    pub top_k: usize,
+    /// Drop tokens less likely than `min_p` times the most likely one;
+    /// 0 disables
+    pub min_p: f32,
+    /// Divides the positive logits (and multiplies the negative ones) of
+    /// tokens already sampled; 1 disables
+    pub repetition_penalty: f32,
+    /// Subtracted from a token's logit for every time it was sampled
+    pub frequency_penalty: f32,
+    /// Subtracted once from the logit of every token already sampled
+    pub presence_penalty: f32,
+    /// Added to the logits of individual token ids
+    pub logit_bias: HashMap<u32, f32>,
+    pub seed: u64,
+}
+
+impl Default for SamplerConfig {
+    fn default() -> Self {
+        Self {
+            temperature: 0.7,
+            top_p: 0.9,
+            top_k: 0,
+            min_p: 0.0,
+            repetition_penalty: 1.0,
+            frequency_penalty: 0.0,
+            presence_penalty: 0.0,
+            logit_bias: HashMap::new(),
+            seed: 42,
+        }
+    }
+}
+
+impl SamplerConfig {
+    fn has_penalties(&self) -> bool {
+        self.repetition_penalty != 1.0
+            || self.frequency_penalty != 0.0
+            || self.presence_penalty != 0.0
+    }
 
+    fn has_filters(&self) -> bool {
+        self.top_p < 1.0 || self.top_k > 0 || self.min_p > 0.0
+    }
+}
+
+/// Per-request sampling state.
+///
+/// A sampler is constructed for each request and owns everything that has to
+/// start fresh with it: the RNG state and the tokens sampled so far (needed by
+/// history-dependent penalties).
 pub struct Sampler {
-    temperature: f32,
-    top_p: f32,
-    #[allow(dead_code)]
-    rng_seed: u64,
+    config: SamplerConfig,
     rng_state: u64,
+    history: Vec<u32>,
 }
 
 impl Sampler {
-    pub fn new(temperature: f32, top_p: f32, seed: u64) -> Self {
+    pub fn from_config(mut config: SamplerConfig) -> Self {
+        config.temperature = config.temperature.max(0.001); // Avoid division by zero
         Self {
-            temperature: temperature.max(0.001), // Avoid division by zero
-            top_p,
-            rng_seed: seed,
-            rng_state: seed,
+            // xorshift never leaves an all-zero state, and clients do send
+            // `seed: 0`
+            rng_state: if config.seed == 0 {
+                0x9E37_79B9_7F4A_7C15
+            } else {
+                config.seed
+            },
+            config,
+            history: Vec::new(),
         }
     }
 
+    /// Temperature and top-p sampling with everything else at its default
+    pub fn new(temperature: f32, top_p: f32, seed: u64) -> Self {
+        Self::from_config(SamplerConfig {
+            temperature,
+            top_p,
+            seed,
+            ..Default::default()
+        })
+    }
+
+    /// Tokens sampled so far in this request
+    pub fn history(&self) -> &[u32] {
+        &self.history
+    }
+
     pub fn sample(&mut self, logits: &Tensor) -> Result<u32> {
         let logits = logits.to_dtype(candle_core::DType::F32)?;
-        let logits = logits.to_vec1::<f32>()?;
+        let mut logits = logits.to_vec1::<f32>()?;
+        self.apply_bias_and_penalties(&mut logits);
 
-        // Apply temperature
+        // Apply temperature; NaN logits (from a numerically broken forward
+        // pass) get zero probability instead of poisoning the softmax
         let scaled: Vec<f32> = logits
             .iter()
-            .map(|&x| x / self.temperature)
+            .map(|&x| {
+                if x.is_nan() {
+                    f32::NEG_INFINITY
+                } else {
+                    x / self.config.temperature
+                }
+            })
             .collect();
 
         // Softmax
@@ -37,27 +127,68 @@ impl Sampler {
         let sum: f32 = exp.iter().sum();
         let probs: Vec<f32> = exp.iter().map(|&x| x / sum).collect();
 
-        // Top-p (nucleus) sampling
-        let token = if self.top_p < 1.0 {
-            self.sample_top_p(&probs)
+        let token = if self.config.has_filters() {
+            self.sample_filtered(&probs)
         } else {
             self.sample_multinomial(&probs)
         };
 
+        self.history.push(token);
         Ok(token)
     }
 
-    fn sample_top_p(&mut self, probs: &[f32]) -> u32 {
-        // Sort by probability descending
+    /// `logit_bias`, then the penalties for the tokens sampled so far
+    fn apply_bias_and_penalties(&self, logits: &mut [f32]) {
+        for (&token, &bias) in &self.config.logit_bias {
+            if let Some(logit) = logits.get_mut(token as usize) {
+                *logit += bias;
+            }
+        }
+        if !self.config.has_penalties() {
+            return;
+        }
+
+        let mut counts: HashMap<u32, u32> = HashMap::new();
+        for &token in &self.history {
+            *counts.entry(token).or_default() += 1;
+        }
+        for (token, count) in counts {
+            let Some(logit) = logits.get_mut(token as usize) else {
+                continue;
+            };
+            if *logit > 0.0 {
+                *logit /= self.config.repetition_penalty;
+            } else {
+                *logit *= self.config.repetition_penalty;
+            }
+            *logit -= count as f32 * self.config.frequency_penalty + self.config.presence_penalty;
+        }
+    }
+
+    /// Sample from the candidates left by top-k, min-p and top-p, in that
+    /// order. Top-p counts probability mass in the full distribution.
+    fn sample_filtered(&mut self, probs: &[f32]) -> u32 {
+        // Sort by probability descending. The order is total so identical
+        // inputs always give the same candidates: NaN sorts last and equal
+        // probabilities keep token index order.
         let mut indexed: Vec<(usize, f32)> = probs.iter().copied().enumerate().collect();
-        indexed.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+        let key = |p: f32| if p.is_nan() { f32::NEG_INFINITY } else { p };
+        indexed.sort_unstable_by(|a, b| key(b.1).total_cmp(&key(a.1)).then(a.0.cmp(&b.0)));
+
+        if self.config.top_k > 0 {
+            indexed.truncate(self.config.top_k);
+        }
+        if self.config.min_p > 0.0 {
+            let threshold = indexed.first().map_or(0.0, |(_, p)| p * self.config.min_p);
+            indexed.retain(|(_, p)| *p >= threshold);
+        }
 
         // Find cutoff for top-p
         let mut cumsum = 0.0;
         let mut cutoff_idx = indexed.len();
         for (i, (_, p)) in indexed.iter().enumerate() {
             cumsum += p;
-            if cumsum >= self.top_p {
+            if cumsum >= self.config.top_p {
                 cutoff_idx = i + 1;
                 break;
             }
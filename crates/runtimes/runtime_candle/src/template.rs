@@ -0,0 +1,182 @@
+//! Chat prompt formats: the built-in ones and user-supplied templates
+//!
+//! A template is a plain format string with `{system}`, `{user}` and
+//! `{assistant}` placeholders, e.g. for ChatML:
+//!
+//! ```text
+//! <|im_start|>system
+//! {system}<|im_end|>
+//! <|im_start|>user
+//! {user}<|im_end|>
+//! <|im_start|>assistant
+//! {assistant}<|im_end|>
+//! ```
+//!
+//! Each placeholder defines one turn, which spans from the end of the
+//! previous turn through the end of the line containing the placeholder.
+//! Messages are rendered turn by turn in order, and the prompt ends with the
+//! assistant turn's prefix so the model continues as the assistant.
+
+use anyhow::Result;
+use ohmygpu_runtime_api::ChatMessage;
+use std::path::Path;
+
+const ROLES: [&str; 3] = ["system", "user", "assistant"];
+
+/// Built-in prompt format, used when no template is configured
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PromptFormat {
+    /// `[INST] ... [/INST]` with a `<<SYS>>` block (Llama 2 and similar)
+    #[default]
+    Llama2,
+    /// `<|start_header_id|>role<|end_header_id|>` headers with turns ending
+    /// in `<|eot_id|>` (Llama 3)
+    Llama3,
+}
+
+impl PromptFormat {
+    pub fn render(self, messages: &[ChatMessage]) -> String {
+        match self {
+            PromptFormat::Llama2 => render_llama2(messages),
+            PromptFormat::Llama3 => render_llama3(messages),
+        }
+    }
+}
+
+fn render_llama2(messages: &[ChatMessage]) -> String {
+    let mut prompt = String::new();
+
+    for msg in messages {
+        match msg.role.as_str() {
+            "system" => {
+                prompt.push_str(&format!("<<SYS>>\n{}\n<</SYS>>\n\n", msg.content));
+            }
+            "user" => {
+                prompt.push_str(&format!("[INST] {} [/INST]", msg.content));
+            }
+            "assistant" => {
+                prompt.push_str(&format!(" {} ", msg.content));
+            }
+            _ => {
+                prompt.push_str(&msg.content);
+            }
+        }
+    }
+
+    prompt
+}
+
+/// The leading `<|begin_of_text|>` is left out: the Llama 3 tokenizer adds it
+/// when encoding.
+fn render_llama3(messages: &[ChatMessage]) -> String {
+    let mut prompt = String::new();
+
+    for msg in messages {
+        prompt.push_str(&format!(
+            "<|start_header_id|>{}<|end_header_id|>\n\n{}<|eot_id|>",
+            msg.role,
+            msg.content.trim()
+        ));
+    }
+
+    prompt.push_str("<|start_header_id|>assistant<|end_header_id|>\n\n");
+    prompt
+}
+
+/// The literal text around one placeholder
+#[derive(Debug, Clone, Default)]
+struct Turn {
+    prefix: String,
+    suffix: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct ChatTemplate {
+    system: Option<Turn>,
+    user: Turn,
+    assistant: Turn,
+}
+
+impl ChatTemplate {
+    /// Load a template from a config value, which is either a path to a
+    /// template file or the template itself.
+    pub fn from_config_value(value: &str) -> Result<Self> {
+        let path = Path::new(value);
+        if path.is_file() {
+            let content = std::fs::read_to_string(path)?;
+            return Self::parse(&content);
+        }
+        Self::parse(value)
+    }
+
+    pub fn parse(template: &str) -> Result<Self> {
+        // Locate placeholders in template order
+        let mut found: Vec<(usize, &str)> = ROLES
+            .iter()
+            .filter_map(|role| {
+                template
+                    .find(&format!("{{{}}}", role))
+                    .map(|pos| (pos, *role))
+            })
+            .collect();
+        found.sort_by_key(|(pos, _)| *pos);
+
+        let mut system = None;
+        let mut user = None;
+        let mut assistant = None;
+        let mut turn_start = 0;
+
+        for (i, (pos, role)) in found.iter().enumerate() {
+            let placeholder_end = pos + role.len() + 2;
+            let next_pos = found.get(i + 1).map(|(p, _)| *p).unwrap_or(template.len());
+
+            // The turn ends at the first newline after the placeholder, but
+            // never runs into the next placeholder.
+            let turn_end = template[placeholder_end..next_pos]
+                .find('\n')
+                .map(|n| placeholder_end + n + 1)
+                .unwrap_or(next_pos);
+
+            let turn = Turn {
+                prefix: template[turn_start..*pos].to_string(),
+                suffix: template[placeholder_end..turn_end].to_string(),
+            };
+            turn_start = turn_end;
+
+            match *role {
+                "system" => system = Some(turn),
+                "user" => user = Some(turn),
+                _ => assistant = Some(turn),
+            }
+        }
+
+        let user = user.ok_or_else(|| anyhow::anyhow!("Chat template is missing {{user}}"))?;
+        let assistant =
+            assistant.ok_or_else(|| anyhow::anyhow!("Chat template is missing {{assistant}}"))?;
+
+        Ok(Self {
+            system,
+            user,
+            assistant,
+        })
+    }
+
+    pub fn render(&self, messages: &[ChatMessage]) -> String {
+        let mut prompt = String::new();
+
+        for msg in messages {
+            let turn = match msg.role.as_str() {
+                // Templates without a system turn fold it into a user turn
+                "system" => self.system.as_ref().unwrap_or(&self.user),
+                "assistant" => &self.assistant,
+                _ => &self.user,
+            };
+            prompt.push_str(&turn.prefix);
+            prompt.push_str(&msg.content);
+            prompt.push_str(&turn.suffix);
+        }
+
+        prompt.push_str(&self.assistant.prefix);
+        prompt
+    }
+}
@@ -0,0 +1,98 @@
+//! Streamed text deltas add up to decoding the whole completion at once,
+//! even when characters are split over byte tokens.
+
+use ohmygpu_runtime_candle::Detokenizer;
+use std::str::FromStr;
+use tokenizers::Tokenizer;
+
+/// A SentencePiece-style tokenizer with byte fallback and Llama's decoder,
+/// which drops the leading space of the text it decodes
+fn tokenizer() -> Tokenizer {
+    let json = serde_json::json!({
+        "version": "1.0",
+        "truncation": null,
+        "padding": null,
+        "added_tokens": [],
+        "normalizer": null,
+        "pre_tokenizer": null,
+        "post_processor": null,
+        "decoder": {
+            "type": "Sequence",
+            "decoders": [
+                { "type": "Replace", "pattern": { "String": "▁" }, "content": " " },
+                { "type": "ByteFallback" },
+                { "type": "Fuse" },
+                { "type": "Strip", "content": " ", "start": 1, "stop": 0 }
+            ]
+        },
+        "model": {
+            "type": "BPE",
+            "dropout": null,
+            "unk_token": "<unk>",
+            "continuing_subword_prefix": null,
+            "end_of_word_suffix": null,
+            "fuse_unk": true,
+            "byte_fallback": true,
+            "vocab": {
+                "<unk>": 0, "<0xA0>": 1, "<0xA5>": 2, "<0xBD>": 3, "<0xE4>": 4,
+                "<0xE5>": 5, "▁hello": 6, "▁world": 7, "!": 8, "<0xC3>": 9, "<0xA9>": 10
+            },
+            "merges": []
+        }
+    });
+    Tokenizer::from_str(&json.to_string()).unwrap()
+}
+
+/// Feed `tokens` one at a time like the generation loop, then flush
+fn stream(tokenizer: &Tokenizer, tokens: &[u32]) -> Vec<(String, Vec<u32>)> {
+    let mut detokenizer = Detokenizer::new();
+    let mut deltas = Vec::new();
+    for end in 1..=tokens.len() {
+        if let Some((text, ids)) = detokenizer.step(tokenizer, &tokens[..end]).unwrap() {
+            deltas.push((text, ids.to_vec()));
+        }
+    }
+    if let Some((text, ids)) = detokenizer.flush(tokenizer, tokens).unwrap() {
+        deltas.push((text, ids.to_vec()));
+    }
+    deltas
+}
+
+fn check(tokens: &[u32]) -> Vec<(String, Vec<u32>)> {
+    let tokenizer = tokenizer();
+    let deltas = stream(&tokenizer, tokens);
+
+    let text: String = deltas.iter().map(|(text, _)| text.as_str()).collect();
+    assert_eq!(text, tokenizer.decode(tokens, true).unwrap());
+    let ids: Vec<u32> = deltas.iter().flat_map(|(_, ids)| ids.clone()).collect();
+    assert_eq!(ids, tokens);
+    deltas
+}
+
+#[test]
+fn deltas_match_a_full_decode() {
+    // "▁hello" 你 (E4 BD A0) 好 (E5 A5 BD) "▁world" é (C3 A9) "!"
+    let tokens = [6, 4, 3, 1, 5, 2, 3, 7, 9, 10, 8];
+    let deltas = check(&tokens);
+
+    assert_eq!(deltas[0].0, "hello");
+    // Split characters come out whole, with all of their byte tokens
+    assert!(deltas.contains(&("你".to_string(), vec![4, 3, 1])));
+    assert!(deltas.contains(&("好".to_string(), vec![5, 2, 3])));
+    assert!(deltas.contains(&(" world".to_string(), vec![7])));
+    assert!(deltas.iter().all(|(text, _)| !text.contains('\u{FFFD}')));
+}
+
+#[test]
+fn leading_space_token_keeps_its_space_mid_stream() {
+    let deltas = check(&[6, 7, 7]);
+    let texts: Vec<&str> = deltas.iter().map(|(text, _)| text.as_str()).collect();
+    assert_eq!(texts, ["hello", " world", " world"]);
+}
+
+#[test]
+fn flush_emits_an_incomplete_character() {
+    // Generation stopped in the middle of 你
+    let deltas = check(&[6, 4, 3]);
+    assert_eq!(deltas.last().unwrap().1, [4, 3]);
+}
@@ -0,0 +1,49 @@
+//! The dtype resolver picks weights per device and architecture.
+
+use candle_core::DType;
+use ohmygpu_runtime_candle::{resolve_dtype, DeviceKind};
+
+fn dtype(device: DeviceKind, architecture: &str) -> DType {
+    resolve_dtype(device, architecture, None).dtype
+}
+
+#[test]
+fn cpu_always_uses_f32() {
+    for architecture in ["llama", "mistral", "phi", "gemma"] {
+        assert_eq!(dtype(DeviceKind::Cpu, architecture), DType::F32);
+    }
+}
+
+#[test]
+fn cuda_uses_bf16() {
+    for architecture in ["llama", "mistral", "phi2", "gemma"] {
+        assert_eq!(dtype(DeviceKind::Cuda, architecture), DType::BF16);
+    }
+}
+
+#[test]
+fn metal_uses_f16_only_where_it_is_known_to_work() {
+    assert_eq!(dtype(DeviceKind::Metal, "llama"), DType::F16);
+    assert_eq!(dtype(DeviceKind::Metal, "mistral"), DType::F16);
+    // No BF16 on Metal, and F16 overflows in Phi
+    assert_eq!(dtype(DeviceKind::Metal, "phi"), DType::F32);
+    assert_eq!(dtype(DeviceKind::Metal, "gemma"), DType::F32);
+}
+
+#[test]
+fn automatic_f16_is_verified() {
+    assert!(resolve_dtype(DeviceKind::Metal, "llama", None).verify);
+    assert!(!resolve_dtype(DeviceKind::Cuda, "llama", None).verify);
+    assert!(!resolve_dtype(DeviceKind::Metal, "phi", None).verify);
+}
+
+#[test]
+fn configured_dtype_wins() {
+    let choice = resolve_dtype(DeviceKind::Metal, "phi", Some(DType::F16));
+    assert_eq!(choice.dtype, DType::F16);
+    assert!(!choice.verify);
+    assert_eq!(
+        resolve_dtype(DeviceKind::Cpu, "llama", Some(DType::BF16)).dtype,
+        DType::BF16
+    );
+}
@@ -0,0 +1,125 @@
+//! End-to-end generation with a tiny model.
+//!
+//! Ignored by default because it downloads a checkpoint. Run with:
+//!
+//! ```text
+//! cargo test -p ohmygpu_runtime_candle --test generation -- --ignored
+//! ```
+//!
+//! Set `OHMYGPU_TEST_MODEL` to a local model directory to skip the download,
+//! or `OHMYGPU_TEST_MODEL_REPO` to fetch a different HuggingFace repo. The
+//! model needs `config.json`, `tokenizer.json` and `model.safetensors`.
+
+use anyhow::Result;
+use ohmygpu_runtime_api::{ChatMessage, ChatRequest, Runtime, RuntimeConfig};
+use ohmygpu_runtime_candle::CandleRuntime;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+const DEFAULT_REPO: &str = "hf-internal-testing/tiny-random-LlamaForCausalLM";
+const MAX_TOKENS: u32 = 16;
+
+fn model_dir() -> Result<PathBuf> {
+    if let Ok(dir) = std::env::var("OHMYGPU_TEST_MODEL") {
+        return Ok(PathBuf::from(dir));
+    }
+
+    let repo_id = std::env::var("OHMYGPU_TEST_MODEL_REPO").unwrap_or(DEFAULT_REPO.to_string());
+    let repo = hf_hub::api::sync::Api::new()?.model(repo_id);
+    let mut dir = None;
+    for file in ["config.json", "tokenizer.json", "model.safetensors"] {
+        dir = repo.get(file)?.parent().map(PathBuf::from);
+    }
+    dir.ok_or_else(|| anyhow::anyhow!("Download returned no path"))
+}
+
+async fn load_runtime() -> Result<CandleRuntime> {
+    let model_path = tokio::task::spawn_blocking(model_dir).await??;
+
+    let mut runtime = CandleRuntime::new();
+    runtime
+        .load(RuntimeConfig {
+            model_path,
+            gpu_id: None,
+            vram_budget_mb: None,
+            cpu_threads: None,
+            stream_buffer_size: None,
+            chat_template: None,
+            model_type: None,
+            dtype: Some("f32".to_string()),
+            model_name: None,
+            context_length: None,
+        })
+        .await?;
+    Ok(runtime)
+}
+
+fn request(stream: bool) -> ChatRequest {
+    ChatRequest {
+        messages: vec![ChatMessage {
+            role: "user".to_string(),
+            content: "Tell me a story".to_string(),
+        }],
+        max_tokens: MAX_TOKENS,
+        temperature: 0.7,
+        top_p: 0.9,
+        top_k: 0,
+        min_p: 0.0,
+        repetition_penalty: 1.0,
+        frequency_penalty: 0.0,
+        presence_penalty: 0.0,
+        logit_bias: HashMap::new(),
+        seed: Some(42),
+        stream,
+    }
+}
+
+#[tokio::test]
+#[ignore = "downloads a model; run with --ignored"]
+async fn generation_is_deterministic() -> Result<()> {
+    let runtime = load_runtime().await?;
+
+    let first = runtime.chat(request(false)).await?;
+    let second = runtime.chat(request(false)).await?;
+
+    assert!(!first.content.is_empty(), "generated no text");
+    assert_eq!(
+        first.content, second.content,
+        "same request, different output"
+    );
+    assert_eq!(first.tokens_used, second.tokens_used);
+
+    assert!(first.tokens_used > 0);
+    assert!(first.tokens_used <= MAX_TOKENS);
+    match first.finish_reason.as_str() {
+        "length" => assert_eq!(first.tokens_used, MAX_TOKENS),
+        "stop" => {}
+        other => panic!("unexpected finish reason {:?}", other),
+    }
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "downloads a model; run with --ignored"]
+async fn streaming_matches_non_streaming() -> Result<()> {
+    let runtime = load_runtime().await?;
+
+    let expected = runtime.chat(request(false)).await?;
+
+    let mut rx = runtime.chat_stream(request(true)).await?;
+    let mut content = String::new();
+    let mut finish_reason = None;
+    while let Some(token) = rx.recv().await {
+        content.push_str(&token.content);
+        if token.finish_reason.is_some() {
+            finish_reason = token.finish_reason;
+        }
+    }
+
+    assert_eq!(content, expected.content);
+    assert_eq!(
+        finish_reason.as_deref(),
+        Some(expected.finish_reason.as_str())
+    );
+    Ok(())
+}
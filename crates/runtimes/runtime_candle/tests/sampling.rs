@@ -0,0 +1,96 @@
+//! Top-p sampling must pick the same token for the same inputs, and the
+//! other `SamplerConfig` knobs must narrow or reshape the choice.
+
+use candle_core::{Device, Tensor};
+use ohmygpu_runtime_candle::{Sampler, SamplerConfig};
+
+fn sample(logits: &[f32], seed: u64) -> u32 {
+    let logits = Tensor::new(logits, &Device::Cpu).unwrap();
+    Sampler::new(1.0, 0.5, seed).sample(&logits).unwrap()
+}
+
+#[test]
+fn equal_probabilities_give_a_stable_choice() {
+    let logits = [1.0; 8];
+
+    for seed in [1, 42, 1234, u64::MAX / 3] {
+        let token = sample(&logits, seed);
+        // Ties keep token order, so the nucleus is the first four tokens
+        assert!(token < 4, "seed {} picked token {}", seed, token);
+        for _ in 0..10 {
+            assert_eq!(sample(&logits, seed), token);
+        }
+    }
+}
+
+#[test]
+fn nan_logits_are_never_sampled() {
+    let logits = [f32::NAN, 0.5, f32::NAN, 2.0, 2.0];
+
+    for seed in [1, 42, 1234] {
+        let token = sample(&logits, seed);
+        assert!(
+            token == 3 || token == 4,
+            "seed {} picked token {}",
+            seed,
+            token
+        );
+        assert_eq!(sample(&logits, seed), token);
+    }
+}
+
+fn sample_with(config: SamplerConfig, logits: &[f32]) -> u32 {
+    let logits = Tensor::new(logits, &Device::Cpu).unwrap();
+    Sampler::from_config(config).sample(&logits).unwrap()
+}
+
+#[test]
+fn top_k_and_min_p_narrow_the_candidates() {
+    let logits = [1.0, 3.0, 0.5, 2.9, 2.0];
+
+    for seed in [1, 42, 1234] {
+        let greedy = SamplerConfig {
+            top_k: 1,
+            seed,
+            ..SamplerConfig::default()
+        };
+        assert_eq!(sample_with(greedy, &logits), 1);
+
+        // Only tokens 1 and 3 are within 0.8x of the most likely one
+        let min_p = SamplerConfig {
+            temperature: 1.0,
+            top_p: 1.0,
+            min_p: 0.8,
+            seed,
+            ..SamplerConfig::default()
+        };
+        let token = sample_with(min_p, &logits);
+        assert!(
+            token == 1 || token == 3,
+            "seed {} picked token {}",
+            seed,
+            token
+        );
+    }
+}
+
+#[test]
+fn logit_bias_and_penalties_reshape_the_distribution() {
+    let logits = [5.0, 0.0, 0.0];
+    let config = SamplerConfig {
+        top_k: 1,
+        logit_bias: [(2, 10.0)].into_iter().collect(),
+        ..SamplerConfig::default()
+    };
+    assert_eq!(sample_with(config, &logits), 2);
+
+    // Once token 0 has been sampled, the penalty makes token 1 the top choice
+    let mut sampler = Sampler::from_config(SamplerConfig {
+        top_k: 1,
+        presence_penalty: 6.0,
+        ..SamplerConfig::default()
+    });
+    let logits = Tensor::new(&[5.0f32, 0.5, 0.0][..], &Device::Cpu).unwrap();
+    assert_eq!(sampler.sample(&logits).unwrap(), 0);
+    assert_eq!(sampler.sample(&logits).unwrap(), 1);
+}
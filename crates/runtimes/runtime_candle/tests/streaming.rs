@@ -0,0 +1,62 @@
+//! A slow stream consumer holds generation back instead of losing tokens.
+
+use ohmygpu_runtime_candle::{ChannelSink, TokenSink};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A small `inference.stream_buffer_size`
+const BUFFER: usize = 2;
+const TOKENS: usize = 20;
+
+#[tokio::test]
+async fn slow_consumer_throttles_generation() {
+    let (tx, mut rx) = tokio::sync::mpsc::channel(BUFFER);
+    let produced = Arc::new(AtomicUsize::new(0));
+    let generation = tokio::task::spawn_blocking({
+        let produced = produced.clone();
+        move || {
+            let mut sink = ChannelSink::new(tx);
+            for i in 0..TOKENS {
+                if !sink.on_text(&format!("t{} ", i), &[i as u32]) {
+                    return false;
+                }
+                produced.fetch_add(1, Ordering::SeqCst);
+            }
+            sink.on_finish("stop");
+            true
+        }
+    });
+
+    let mut received = Vec::new();
+    let mut finish_reason = None;
+    while let Some(token) = rx.recv().await {
+        if token.finish_reason.is_some() {
+            finish_reason = token.finish_reason;
+            continue;
+        }
+        received.push(token);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        // Generation is never more than the channel's capacity ahead
+        let ahead = produced.load(Ordering::SeqCst) - received.len();
+        assert!(ahead <= BUFFER, "generation ran {} tokens ahead", ahead);
+    }
+
+    assert!(generation.await.unwrap(), "the sink stopped generation");
+    assert_eq!(received.len(), TOKENS);
+    for (i, token) in received.iter().enumerate() {
+        assert_eq!(token.content, format!("t{} ", i));
+        assert_eq!(token.token_ids, [i as u32]);
+    }
+    assert_eq!(finish_reason.as_deref(), Some("stop"));
+}
+
+#[tokio::test]
+async fn dropped_consumer_stops_generation() {
+    let (tx, rx) = tokio::sync::mpsc::channel(BUFFER);
+    drop(rx);
+    let stopped = tokio::task::spawn_blocking(move || !ChannelSink::new(tx).on_text("t", &[0]))
+        .await
+        .unwrap();
+    assert!(stopped);
+}
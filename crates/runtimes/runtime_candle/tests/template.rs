@@ -0,0 +1,45 @@
+//! The built-in Llama 3 format renders conversations exactly as Meta's
+//! reference prompt format does.
+
+use ohmygpu_runtime_api::ChatMessage;
+use ohmygpu_runtime_candle::PromptFormat;
+
+/// A system prompt, one exchange and a follow-up question, as given in the
+/// Llama 3 model card
+const LLAMA3_REFERENCE: &str = "<|begin_of_text|>\
+<|start_header_id|>system<|end_header_id|>\n\nYou are a helpful assistant.<|eot_id|>\
+<|start_header_id|>user<|end_header_id|>\n\nWhat is the capital of France?<|eot_id|>\
+<|start_header_id|>assistant<|end_header_id|>\n\nParis.<|eot_id|>\
+<|start_header_id|>user<|end_header_id|>\n\nAnd of Italy?<|eot_id|>\
+<|start_header_id|>assistant<|end_header_id|>\n\n";
+
+fn message(role: &str, content: &str) -> ChatMessage {
+    ChatMessage {
+        role: role.to_string(),
+        content: content.to_string(),
+    }
+}
+
+#[test]
+fn llama3_matches_the_reference_format() {
+    let messages = [
+        message("system", "You are a helpful assistant."),
+        message("user", "What is the capital of France?"),
+        message("assistant", "Paris."),
+        message("user", "And of Italy?"),
+    ];
+
+    // The tokenizer adds `<|begin_of_text|>` itself
+    let expected = LLAMA3_REFERENCE.strip_prefix("<|begin_of_text|>").unwrap();
+    assert_eq!(PromptFormat::Llama3.render(&messages), expected);
+}
+
+#[test]
+fn llama3_trims_message_whitespace() {
+    let messages = [message("user", "  Hi there \n")];
+    assert_eq!(
+        PromptFormat::Llama3.render(&messages),
+        "<|start_header_id|>user<|end_header_id|>\n\nHi there<|eot_id|>\
+         <|start_header_id|>assistant<|end_header_id|>\n\n"
+    );
+}
@@ -0,0 +1,58 @@
+//! Generation timing is plausible and monotonic.
+
+use ohmygpu_runtime_candle::GenerationTimer;
+use std::thread::sleep;
+use std::time::Duration;
+
+const PREFILL: Duration = Duration::from_millis(30);
+const STEP: Duration = Duration::from_millis(10);
+const STEPS: usize = 5;
+
+/// A generation loop whose forward passes are sleeps
+fn stub_generation() -> (ohmygpu_runtime_candle::GenerationStats, Vec<u64>) {
+    let mut timer = GenerationTimer::start();
+    let mut elapsed = Vec::new();
+    for step in 0..STEPS {
+        sleep(if step == 0 { PREFILL } else { STEP });
+        timer.prefill_done();
+        elapsed.push(timer.elapsed_ms());
+    }
+    (timer.finish(STEPS, "length"), elapsed)
+}
+
+#[test]
+fn timing_is_plausible() {
+    let (stats, _) = stub_generation();
+    let minimum = (PREFILL + STEP * (STEPS as u32 - 1)).as_millis() as u64;
+
+    assert_eq!(stats.tokens_generated, STEPS);
+    assert!(
+        stats.prefill_ms >= PREFILL.as_millis() as u64,
+        "{:?}",
+        stats
+    );
+    assert!(stats.generation_ms >= minimum, "{:?}", stats);
+    // Generous bounds: the stub never takes seconds
+    assert!(stats.prefill_ms < 5_000, "{:?}", stats);
+    assert!(stats.generation_ms < 5_000, "{:?}", stats);
+}
+
+#[test]
+fn timing_is_monotonic() {
+    let (stats, elapsed) = stub_generation();
+
+    assert!(
+        elapsed.windows(2).all(|pair| pair[0] <= pair[1]),
+        "{:?}",
+        elapsed
+    );
+    // Prefill is the first step only, and part of the total
+    assert!(stats.prefill_ms <= elapsed[0], "{:?} {:?}", stats, elapsed);
+    assert!(stats.prefill_ms < stats.generation_ms, "{:?}", stats);
+    assert!(
+        elapsed[STEPS - 1] <= stats.generation_ms,
+        "{:?} {:?}",
+        stats,
+        elapsed
+    );
+}
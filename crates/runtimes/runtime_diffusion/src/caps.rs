@@ -0,0 +1,111 @@
+//! The `steps` and `guidance_scale` ranges a model gives good images in
+//!
+//! Distilled (turbo/schnell) models are trained for a handful of steps and
+//! little or no guidance; far outside that they produce artifacts instead
+//! of failing, so requests are checked against the model's ranges up front.
+
+use std::fmt;
+use std::ops::RangeInclusive;
+
+use crate::ImageGenRequest;
+
+/// Recommended parameter ranges of a model, from [`crate::DiffusionModel::caps`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiffusionCaps {
+    pub steps: RangeInclusive<u32>,
+    pub guidance_scale: RangeInclusive<f32>,
+}
+
+impl DiffusionCaps {
+    /// No limits, for models without known ranges
+    pub const UNRESTRICTED: Self = Self {
+        steps: 1..=u32::MAX,
+        guidance_scale: 0.0..=f32::MAX,
+    };
+
+    /// Z-Image-Turbo is distilled for ~8 steps
+    pub const Z_IMAGE_TURBO: Self = Self {
+        steps: 1..=16,
+        guidance_scale: 0.0..=6.0,
+    };
+
+    /// The parameters of `request` outside the ranges, including a NaN
+    /// guidance scale
+    pub fn check(&self, request: &ImageGenRequest) -> Vec<OutOfRange> {
+        let mut out_of_range = Vec::new();
+        if !self.steps.contains(&request.steps) {
+            out_of_range.push(OutOfRange::Steps {
+                requested: request.steps,
+                range: self.steps.clone(),
+            });
+        }
+        if !self.guidance_scale.contains(&request.guidance_scale) {
+            out_of_range.push(OutOfRange::GuidanceScale {
+                requested: request.guidance_scale,
+                range: self.guidance_scale.clone(),
+            });
+        }
+        out_of_range
+    }
+
+    /// Move out-of-range parameters of `request` to the nearest end of their
+    /// range and return what was changed. A NaN guidance scale has no
+    /// nearest end and becomes the lowest guidance.
+    pub fn clamp(&self, request: &mut ImageGenRequest) -> Vec<OutOfRange> {
+        let out_of_range = self.check(request);
+        for param in &out_of_range {
+            match param {
+                OutOfRange::Steps { requested, range } => {
+                    request.steps = (*requested).clamp(*range.start(), *range.end());
+                }
+                OutOfRange::GuidanceScale { requested, range } if requested.is_nan() => {
+                    request.guidance_scale = *range.start();
+                }
+                OutOfRange::GuidanceScale { requested, range } => {
+                    request.guidance_scale = requested.clamp(*range.start(), *range.end());
+                }
+            }
+        }
+        out_of_range
+    }
+}
+
+impl Default for DiffusionCaps {
+    fn default() -> Self {
+        Self::UNRESTRICTED
+    }
+}
+
+/// A request parameter outside the model's recommended range
+#[derive(Debug, Clone, PartialEq)]
+pub enum OutOfRange {
+    Steps {
+        requested: u32,
+        range: RangeInclusive<u32>,
+    },
+    GuidanceScale {
+        requested: f32,
+        range: RangeInclusive<f32>,
+    },
+}
+
+impl fmt::Display for OutOfRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Steps { requested, range } => write!(
+                f,
+                "steps {} is outside the recommended {}-{}",
+                requested,
+                range.start(),
+                range.end()
+            ),
+            Self::GuidanceScale { requested, range } => write!(
+                f,
+                "guidance scale {} is outside the recommended {}-{}",
+                requested,
+                range.start(),
+                range.end()
+            ),
+        }
+    }
+}
@@ -0,0 +1,70 @@
+//! Reuse of prompt embeddings across the images of a batch
+//!
+//! The images of a batch (or a grid of seeds) share their prompt and
+//! negative prompt and differ only in their initial noise, so the text
+//! encoder needs to run once for all of them.
+
+use std::sync::Mutex;
+
+use crate::{ImageGenRequest, LongPrompt};
+
+/// The request fields the text embeddings depend on
+#[derive(Debug, Clone, PartialEq)]
+struct TextKey {
+    prompt: String,
+    negative_prompt: Option<String>,
+    prompt_weighting: bool,
+    long_prompt: LongPrompt,
+    /// The negative prompt is only encoded when CFG is on
+    cfg: bool,
+}
+
+impl TextKey {
+    fn new(request: &ImageGenRequest) -> Self {
+        Self {
+            prompt: request.prompt.clone(),
+            negative_prompt: request.negative_prompt.clone(),
+            prompt_weighting: request.prompt_weighting,
+            long_prompt: request.long_prompt,
+            cfg: request.guidance_scale > 1.0,
+        }
+    }
+}
+
+/// Holds the embeddings of the most recent prompt. `E` is whatever a
+/// pipeline computes from the text, e.g. conditional and unconditional
+/// embeddings with their masks.
+pub struct TextEmbeddingCache<E> {
+    entry: Mutex<Option<(TextKey, E)>>,
+}
+
+impl<E> Default for TextEmbeddingCache<E> {
+    fn default() -> Self {
+        Self {
+            entry: Mutex::new(None),
+        }
+    }
+}
+
+impl<E: Clone> TextEmbeddingCache<E> {
+    /// The embeddings for `request`'s text, calling `encode` only when the
+    /// text differs from the previous request's. Seed, size and steps do
+    /// not matter.
+    pub fn get_or_encode<Err>(
+        &self,
+        request: &ImageGenRequest,
+        encode: impl FnOnce() -> Result<E, Err>,
+    ) -> Result<E, Err> {
+        let key = TextKey::new(request);
+        let mut entry = self.entry.lock().unwrap();
+        if let Some((cached_key, embeddings)) = entry.as_ref() {
+            if *cached_key == key {
+                return Ok(embeddings.clone());
+            }
+        }
+
+        let embeddings = encode()?;
+        *entry = Some((key, embeddings.clone()));
+        Ok(embeddings)
+    }
+}
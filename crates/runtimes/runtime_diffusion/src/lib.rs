@@ -3,14 +3,93 @@
 //! This crate provides image generation using diffusion models.
 //! Supports FLUX and Z-Image (S3-DiT) architectures.
 
+mod caps;
+mod embedding_cache;
+mod prompt_weighting;
 mod zimage;
 
 use anyhow::Result;
-use candle_core::Device;
-use std::path::Path;
+use candle_core::{DType, Device};
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
 
+pub use caps::{DiffusionCaps, OutOfRange};
+pub use embedding_cache::TextEmbeddingCache;
+pub use prompt_weighting::WeightedPrompt;
+pub use tokio_util::sync::CancellationToken;
 pub use zimage::ZImagePipeline;
 
+/// Errors from the diffusion pipelines. Through `anyhow` (e.g. from
+/// [`DiffusionModel`]) they can be recovered with `downcast_ref`, except
+/// [`DiffusionError::Backend`], which is passed through as is.
+#[derive(Debug, thiserror::Error)]
+pub enum DiffusionError {
+    /// A file the pipeline needs is not in the model directory
+    #[error("{component} not found at {path:?}; the model download may be incomplete")]
+    MissingComponent {
+        component: &'static str,
+        path: PathBuf,
+    },
+    #[error("Image dimensions must be multiples of {multiple_of}, got {width}x{height}")]
+    InvalidDimensions {
+        width: u32,
+        height: u32,
+        multiple_of: u32,
+    },
+    #[error("Failed to tokenize the prompt: {0}")]
+    TokenizeFailed(String),
+    /// The generation was stopped through its [`CancellationToken`]
+    #[error("Image generation cancelled")]
+    Cancelled,
+    /// Anything else: I/O, invalid configs, tensor backend failures
+    /// (including [`ohmygpu_runtime_api::RuntimeError::OutOfMemory`])
+    #[error(transparent)]
+    Backend(anyhow::Error),
+}
+
+impl DiffusionError {
+    /// Convert for `anyhow` callers. Backend errors are unwrapped so their
+    /// `RuntimeError` context stays downcastable.
+    pub fn into_anyhow(self) -> anyhow::Error {
+        match self {
+            Self::Backend(e) => e,
+            other => other.into(),
+        }
+    }
+}
+
+impl From<anyhow::Error> for DiffusionError {
+    fn from(e: anyhow::Error) -> Self {
+        Self::Backend(e)
+    }
+}
+
+impl From<candle_core::Error> for DiffusionError {
+    fn from(e: candle_core::Error) -> Self {
+        Self::Backend(e.into())
+    }
+}
+
+impl From<std::io::Error> for DiffusionError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Backend(e.into())
+    }
+}
+
+impl From<serde_json::Error> for DiffusionError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Backend(e.into())
+    }
+}
+
+/// Reported after each denoising step of a generation
+#[derive(Debug, Clone, Copy)]
+pub struct StepProgress {
+    /// Steps completed so far, from 1 to `total_steps`
+    pub step: u32,
+    pub total_steps: u32,
+}
+
 /// Image generation request
 #[derive(Debug, Clone)]
 pub struct ImageGenRequest {
@@ -20,7 +99,40 @@ pub struct ImageGenRequest {
     pub height: u32,
     pub steps: u32,
     pub guidance_scale: f32,
+    /// Base seed of the batch; see [`ImageGenRequest::seeds`]
     pub seed: Option<u64>,
+    /// Number of images to generate
+    pub num_images: u32,
+    /// Interpret `(text:weight)` emphasis in the prompts; see
+    /// [`WeightedPrompt`] for the supported syntax
+    pub prompt_weighting: bool,
+    /// What to do with prompts longer than the text encoder accepts
+    pub long_prompt: LongPrompt,
+}
+
+/// Handling of prompts with more tokens than the pipeline's text encoder
+/// takes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LongPrompt {
+    /// Drop the tokens past the limit, with a warning
+    #[default]
+    Truncate,
+    /// Encode the prompt in windows of the maximum length and average their
+    /// embeddings, so every part of the prompt has an effect (at some cost
+    /// in precision)
+    Chunk,
+}
+
+impl std::str::FromStr for LongPrompt {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "truncate" => Ok(Self::Truncate),
+            "chunk" => Ok(Self::Chunk),
+            _ => anyhow::bail!("Unknown long prompt mode '{}' (use truncate or chunk)", s),
+        }
+    }
 }
 
 impl Default for ImageGenRequest {
@@ -33,10 +145,51 @@ impl Default for ImageGenRequest {
             steps: 9,
             guidance_scale: 5.0,
             seed: None,
+            num_images: 1,
+            prompt_weighting: false,
+            long_prompt: LongPrompt::default(),
         }
     }
 }
 
+impl ImageGenRequest {
+    /// Pick a random base seed if none was given and return the base seed.
+    ///
+    /// Call this before generating so the seed can be reported: passing it
+    /// back as `seed` reproduces the whole batch.
+    pub fn resolve_seed(&mut self) -> u64 {
+        *self.seed.get_or_insert_with(random_seed)
+    }
+
+    /// The seed of every image in the batch: image `i` uses `seed + i`
+    /// (wrapping). The same `seed` and `num_images` always give the same
+    /// images, and image `i` alone can be regenerated with seed `seed + i`.
+    ///
+    /// Empty while no seed has been chosen (see [`Self::resolve_seed`]).
+    pub fn seeds(&self) -> Vec<u64> {
+        match self.seed {
+            Some(base) => (0..self.num_images as u64)
+                .map(|i| base.wrapping_add(i))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// The single-image request for image `index` of the batch
+    pub fn for_image(&self, index: u32) -> Self {
+        Self {
+            seed: self.seed.map(|base| base.wrapping_add(index as u64)),
+            num_images: 1,
+            ..self.clone()
+        }
+    }
+}
+
+/// A random base seed for requests that don't set one
+pub fn random_seed() -> u64 {
+    ohmygpu_core::random::random_u64()
+}
+
 /// Image generation response
 pub struct ImageGenResponse {
     /// Raw pixel data (RGB, u8)
@@ -45,13 +198,89 @@ pub struct ImageGenResponse {
     pub height: u32,
 }
 
+impl ImageGenResponse {
+    /// Downscale the image to fit within `max_size` x `max_size` (keeping the
+    /// aspect ratio) and encode it as PNG, for quick previews in terminals
+    /// and TUIs without writing the full image to disk.
+    pub fn thumbnail_png(&self, max_size: u32) -> Result<Vec<u8>> {
+        let img = image::ImageBuffer::<image::Rgb<u8>, &[u8]>::from_raw(
+            self.width,
+            self.height,
+            &self.pixels,
+        )
+        .ok_or_else(|| anyhow::anyhow!("Pixel buffer does not match image size"))?;
+
+        // Never upscale, and keep both sides at least one pixel
+        let scale = (max_size as f64 / self.width.max(self.height) as f64).min(1.0);
+        let width = ((self.width as f64 * scale).round() as u32).max(1);
+        let height = ((self.height as f64 * scale).round() as u32).max(1);
+
+        let thumbnail = image::imageops::thumbnail(&img, width, height);
+
+        let mut bytes = Vec::new();
+        thumbnail.write_to(&mut Cursor::new(&mut bytes), image::ImageFormat::Png)?;
+        Ok(bytes)
+    }
+
+    /// Encode the full image as PNG
+    pub fn png(&self) -> Result<Vec<u8>> {
+        let img = image::RgbImage::from_raw(self.width, self.height, self.pixels.clone())
+            .ok_or_else(|| anyhow::anyhow!("Pixel buffer does not match image size"))?;
+        let mut bytes = Vec::new();
+        img.write_to(&mut Cursor::new(&mut bytes), image::ImageFormat::Png)?;
+        Ok(bytes)
+    }
+}
+
 /// Trait for diffusion model backends
 pub trait DiffusionModel: Send + Sync {
     /// Generate an image from a text prompt
     fn generate(&self, request: &ImageGenRequest) -> Result<ImageGenResponse>;
 
+    /// Like [`Self::generate`], but fails with [`DiffusionError::Cancelled`] once `token` is
+    /// cancelled. Backends that can stop mid-generation override this; the
+    /// default only checks the token before starting.
+    fn generate_cancellable(
+        &self,
+        request: &ImageGenRequest,
+        token: CancellationToken,
+    ) -> Result<ImageGenResponse> {
+        if token.is_cancelled() {
+            return Err(DiffusionError::Cancelled.into());
+        }
+        self.generate(request)
+    }
+
+    /// Like [`Self::generate_cancellable`], calling `on_step` after every
+    /// denoising step. The default never reports a step.
+    fn generate_with_progress(
+        &self,
+        request: &ImageGenRequest,
+        token: CancellationToken,
+        on_step: &mut dyn FnMut(StepProgress),
+    ) -> Result<ImageGenResponse> {
+        let _ = on_step;
+        self.generate_cancellable(request, token)
+    }
+
+    /// Generate `num_images` images following the seed contract of
+    /// [`ImageGenRequest::seeds`], picking a random base seed if needed
+    fn generate_batch(&self, request: &ImageGenRequest) -> Result<Vec<ImageGenResponse>> {
+        let mut request = request.clone();
+        request.resolve_seed();
+        (0..request.num_images)
+            .map(|i| self.generate(&request.for_image(i)))
+            .collect()
+    }
+
     /// Get the model name
     fn name(&self) -> &str;
+
+    /// The `steps` and `guidance_scale` this model gives good images with.
+    /// The default has no limits.
+    fn caps(&self) -> DiffusionCaps {
+        DiffusionCaps::UNRESTRICTED
+    }
 }
 
 /// Supported diffusion model types
@@ -63,18 +292,35 @@ pub enum DiffusionModelType {
     ZImage,
 }
 
-/// Load a diffusion model from a path
+impl std::str::FromStr for DiffusionModelType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().replace(['-', '_'], "").as_str() {
+            "flux" => Ok(Self::Flux),
+            "zimage" => Ok(Self::ZImage),
+            _ => anyhow::bail!("Unknown diffusion model type '{}' (use zimage or flux)", s),
+        }
+    }
+}
+
+/// Load a diffusion model from a path.
+///
+/// `vae_dtype` overrides the dtype of the VAE only; `None` keeps it in the
+/// pipeline dtype.
 pub fn load_model(
     model_path: &Path,
     model_type: DiffusionModelType,
     device: &Device,
+    vae_dtype: Option<DType>,
 ) -> Result<Box<dyn DiffusionModel>> {
     match model_type {
         DiffusionModelType::Flux => {
             anyhow::bail!("FLUX model loading not yet implemented")
         }
         DiffusionModelType::ZImage => {
-            let pipeline = ZImagePipeline::load(model_path, device)?;
+            let pipeline = ZImagePipeline::load(model_path, device, vae_dtype)
+                .map_err(DiffusionError::into_anyhow)?;
             Ok(Box::new(pipeline))
         }
     }
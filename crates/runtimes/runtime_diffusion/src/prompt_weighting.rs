@@ -0,0 +1,101 @@
+//! Prompt weighting (emphasis) syntax
+//!
+//! Supported subset:
+//!
+//! - `(text:1.3)` scales the embeddings of the tokens of `text` by the
+//!   weight (any non-negative decimal; below 1.0 de-emphasizes)
+//! - `\(` and `\)` are literal parentheses
+//! - `(text)` without a weight is kept as literal text
+//!
+//! Nesting, `[text]` de-emphasis and repeated parentheses (`((text))`) are
+//! not supported. A prompt that does not parse (unbalanced or nested
+//! parentheses, invalid weight) is used literally with weight 1.0.
+
+use std::ops::Range;
+
+/// A prompt with the weighting syntax removed and the weight of each
+/// emphasized span of the remaining text
+#[derive(Debug, Clone, PartialEq)]
+pub struct WeightedPrompt {
+    /// The text fed to the tokenizer
+    pub text: String,
+    /// Byte ranges of `text` with a weight other than the default 1.0
+    spans: Vec<(Range<usize>, f32)>,
+}
+
+impl WeightedPrompt {
+    /// Parse the weighting syntax, falling back to the literal prompt
+    pub fn parse(prompt: &str) -> Self {
+        Self::try_parse(prompt).unwrap_or_else(|| {
+            tracing::warn!("Could not parse prompt weighting; using the prompt as-is");
+            Self::literal(prompt)
+        })
+    }
+
+    /// The prompt as-is, every token at weight 1.0
+    pub fn literal(prompt: &str) -> Self {
+        Self {
+            text: prompt.to_string(),
+            spans: Vec::new(),
+        }
+    }
+
+    /// Weight of the token starting at byte `offset` of `text`
+    pub fn weight_at(&self, offset: usize) -> f32 {
+        self.spans
+            .iter()
+            .find(|(range, _)| range.contains(&offset))
+            .map_or(1.0, |(_, weight)| *weight)
+    }
+
+    /// Whether every token has weight 1.0
+    pub fn is_uniform(&self) -> bool {
+        self.spans.iter().all(|(_, weight)| *weight == 1.0)
+    }
+
+    fn try_parse(prompt: &str) -> Option<Self> {
+        let mut text = String::new();
+        let mut spans = Vec::new();
+        let mut chars = prompt.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' if matches!(chars.peek(), Some('(' | ')')) => text.push(chars.next()?),
+                '(' => {
+                    let mut inner = String::new();
+                    loop {
+                        match chars.next()? {
+                            ')' => break,
+                            '(' => return None,
+                            '\\' if matches!(chars.peek(), Some('(' | ')')) => {
+                                inner.push(chars.next()?)
+                            }
+                            c => inner.push(c),
+                        }
+                    }
+
+                    match inner.rsplit_once(':') {
+                        Some((span_text, weight)) => {
+                            let weight: f32 = weight.trim().parse().ok()?;
+                            if !weight.is_finite() || weight < 0.0 {
+                                return None;
+                            }
+                            let start = text.len();
+                            text.push_str(span_text);
+                            spans.push((start..text.len(), weight));
+                        }
+                        None => {
+                            text.push('(');
+                            text.push_str(&inner);
+                            text.push(')');
+                        }
+                    }
+                }
+                ')' => return None,
+                c => text.push(c),
+            }
+        }
+
+        Some(Self { text, spans })
+    }
+}
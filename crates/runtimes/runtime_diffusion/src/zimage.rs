@@ -10,11 +10,17 @@ use candle_transformers::models::z_image::{
     FlowMatchEulerDiscreteScheduler, SchedulerConfig, TextEncoderConfig, VaeConfig,
     ZImageTextEncoder, ZImageTransformer2DModel,
 };
+use ohmygpu_runtime_api::RuntimeError;
 use std::path::Path;
 use std::sync::Mutex;
 use tokenizers::Tokenizer;
 
-use crate::{DiffusionModel, ImageGenRequest, ImageGenResponse};
+use crate::embedding_cache::TextEmbeddingCache;
+use crate::prompt_weighting::WeightedPrompt;
+use crate::{
+    CancellationToken, DiffusionCaps, DiffusionError, DiffusionModel, ImageGenRequest,
+    ImageGenResponse, LongPrompt, StepProgress,
+};
 
 /// Z-Image scheduler constants
 const BASE_IMAGE_SEQ_LEN: usize = 256;
@@ -22,6 +28,14 @@ const MAX_IMAGE_SEQ_LEN: usize = 4096;
 const BASE_SHIFT: f64 = 0.5;
 const MAX_SHIFT: f64 = 1.15;
 
+/// Qwen3 chat template around the prompt
+const PROMPT_PREFIX: &str = "<|im_start|>user\n";
+const PROMPT_SUFFIX: &str = "<|im_end|>\n<|im_start|>assistant\n";
+
+/// Longest caption (template included) the text encoder is run on, as in
+/// the reference pipeline's `max_sequence_length`
+const MAX_PROMPT_TOKENS: usize = 512;
+
 /// Z-Image generation pipeline
 pub struct ZImagePipeline {
     tokenizer: Tokenizer,
@@ -30,18 +44,51 @@ pub struct ZImagePipeline {
     vae: AutoEncoderKL,
     device: Device,
     dtype: DType,
+    /// dtype the VAE weights are loaded in; latents are converted to it
+    /// before decoding
+    vae_dtype: DType,
     scheduler: Mutex<FlowMatchEulerDiscreteScheduler>,
+    /// Embeddings of the last prompt, reused by the other images of a batch
+    text_embeddings: TextEmbeddingCache<PromptEmbeddings>,
+}
+
+/// Text conditioning for one generation: the prompt's embeddings and mask,
+/// plus the negative prompt's when CFG is on
+#[derive(Clone)]
+struct PromptEmbeddings {
+    cap_feats: Tensor,
+    cap_mask: Tensor,
+    negative: Option<(Tensor, Tensor)>,
 }
 
 impl ZImagePipeline {
-    /// Load Z-Image pipeline from a model directory
-    pub fn load(model_path: &Path, device: &Device) -> Result<Self> {
+    /// Load Z-Image pipeline from a model directory.
+    ///
+    /// `vae_dtype` runs the VAE in a different dtype than the transformer
+    /// (e.g. f16 to save memory); `None` uses the pipeline dtype.
+    pub fn load(
+        model_path: &Path,
+        device: &Device,
+        vae_dtype: Option<DType>,
+    ) -> Result<Self, DiffusionError> {
+        Self::load_internal(model_path, device, vae_dtype).map_err(map_out_of_memory)
+    }
+
+    fn load_internal(
+        model_path: &Path,
+        device: &Device,
+        vae_dtype: Option<DType>,
+    ) -> Result<Self, DiffusionError> {
         let dtype = device.bf16_default_to_f32();
+        let vae_dtype = vae_dtype.unwrap_or(dtype);
 
         // Load tokenizer
         let tokenizer_path = model_path.join("tokenizer").join("tokenizer.json");
         if !tokenizer_path.exists() {
-            anyhow::bail!("Tokenizer not found at {:?}", tokenizer_path);
+            return Err(DiffusionError::MissingComponent {
+                component: "Tokenizer",
+                path: tokenizer_path,
+            });
         }
         let tokenizer = Tokenizer::from_file(&tokenizer_path)
             .map_err(|e| anyhow::anyhow!("Failed to load tokenizer: {}", e))?;
@@ -65,7 +112,10 @@ impl ZImagePipeline {
             .collect();
 
         if text_encoder_files.is_empty() {
-            anyhow::bail!("Text encoder weights not found in {:?}", model_path.join("text_encoder"));
+            return Err(DiffusionError::MissingComponent {
+                component: "Text encoder weights",
+                path: model_path.join("text_encoder"),
+            });
         }
 
         let files: Vec<&str> = text_encoder_files.iter().map(|p| p.to_str().unwrap()).collect();
@@ -92,10 +142,10 @@ impl ZImagePipeline {
             .collect();
 
         if transformer_files.is_empty() {
-            anyhow::bail!(
-                "Transformer weights not found in {:?}",
-                model_path.join("transformer")
-            );
+            return Err(DiffusionError::MissingComponent {
+                component: "Transformer weights",
+                path: model_path.join("transformer"),
+            });
         }
 
         let files: Vec<&str> = transformer_files.iter().map(|p| p.to_str().unwrap()).collect();
@@ -114,11 +164,14 @@ impl ZImagePipeline {
         // Load VAE weights
         let vae_path = model_path.join("vae").join("diffusion_pytorch_model.safetensors");
         if !vae_path.exists() {
-            anyhow::bail!("VAE weights not found at {:?}", vae_path);
+            return Err(DiffusionError::MissingComponent {
+                component: "VAE weights",
+                path: vae_path,
+            });
         }
 
         let vae_weights = unsafe {
-            VarBuilder::from_mmaped_safetensors(&[vae_path.to_str().unwrap()], dtype, device)?
+            VarBuilder::from_mmaped_safetensors(&[vae_path.to_str().unwrap()], vae_dtype, device)?
         };
         let vae = AutoEncoderKL::new(&vae_cfg, vae_weights)?;
 
@@ -133,20 +186,193 @@ impl ZImagePipeline {
             vae,
             device: device.clone(),
             dtype,
+            vae_dtype,
             scheduler: Mutex::new(scheduler),
+            text_embeddings: TextEmbeddingCache::default(),
+        })
+    }
+
+    /// Encode the prompt, and the negative prompt for CFG
+    fn encode_prompts(
+        &self,
+        request: &ImageGenRequest,
+    ) -> Result<PromptEmbeddings, DiffusionError> {
+        let (cap_feats, cap_mask) = self.encode_prompt(
+            &request.prompt,
+            request.prompt_weighting,
+            request.long_prompt,
+        )?;
+
+        let negative = match &request.negative_prompt {
+            Some(neg_prompt) if !neg_prompt.is_empty() && request.guidance_scale > 1.0 => Some(
+                self.encode_prompt(neg_prompt, request.prompt_weighting, request.long_prompt)?,
+            ),
+            _ => None,
+        };
+
+        Ok(PromptEmbeddings {
+            cap_feats,
+            cap_mask,
+            negative,
         })
     }
 
     /// Format prompt for Qwen3 chat template
     fn format_prompt(prompt: &str) -> String {
-        format!(
-            "<|im_start|>user\n{}<|im_end|>\n<|im_start|>assistant\n",
-            prompt
-        )
+        format!("{}{}{}", PROMPT_PREFIX, prompt, PROMPT_SUFFIX)
+    }
+
+    /// Tokenize and encode a prompt into text embeddings and their mask.
+    ///
+    /// With `weighting`, `(text:weight)` spans scale the embeddings of their
+    /// tokens, then the result is rescaled to the original mean so the
+    /// overall magnitude is unchanged.
+    ///
+    /// Prompts over [`MAX_PROMPT_TOKENS`] are truncated or encoded in
+    /// windows, following `long_prompt`.
+    fn encode_prompt(
+        &self,
+        prompt: &str,
+        weighting: bool,
+        long_prompt: LongPrompt,
+    ) -> Result<(Tensor, Tensor), DiffusionError> {
+        let weighted = if weighting {
+            WeightedPrompt::parse(prompt)
+        } else {
+            WeightedPrompt::literal(prompt)
+        };
+
+        let formatted_prompt = Self::format_prompt(&weighted.text);
+        let encoding = self
+            .tokenizer
+            .encode(formatted_prompt.as_str(), true)
+            .map_err(|e| DiffusionError::TokenizeFailed(e.to_string()))?;
+        let tokens = encoding.get_ids();
+
+        // Token offsets are into the formatted prompt; template tokens keep 1.0
+        let text_range = PROMPT_PREFIX.len()..PROMPT_PREFIX.len() + weighted.text.len();
+        let weights: Option<Vec<f32>> = (!weighted.is_uniform()).then(|| {
+            encoding
+                .get_offsets()
+                .iter()
+                .map(|&(start, _)| {
+                    if text_range.contains(&start) {
+                        weighted.weight_at(start - text_range.start)
+                    } else {
+                        1.0
+                    }
+                })
+                .collect()
+        });
+
+        // Each window is the indices of the tokens encoded together
+        let windows = if tokens.len() <= MAX_PROMPT_TOKENS {
+            vec![(0..tokens.len()).collect::<Vec<_>>()]
+        } else {
+            let offsets = encoding.get_offsets();
+            let text_start = offsets
+                .iter()
+                .position(|&(start, _)| start >= text_range.start)
+                .unwrap_or(0);
+            let text_end = offsets
+                .iter()
+                .position(|&(start, _)| start >= text_range.end)
+                .unwrap_or(tokens.len());
+            let template_len = text_start + (tokens.len() - text_end);
+            let window_len = MAX_PROMPT_TOKENS.saturating_sub(template_len).max(1);
+            let text_len = text_end - text_start;
+
+            let starts: Vec<usize> = match long_prompt {
+                LongPrompt::Truncate => {
+                    tracing::warn!(
+                        "Prompt is {} tokens, more than the text encoder's {}; \
+                         the last {} are ignored",
+                        tokens.len(),
+                        MAX_PROMPT_TOKENS,
+                        text_len - window_len
+                    );
+                    vec![text_start]
+                }
+                LongPrompt::Chunk => {
+                    // Equal windows, the last one ending at the end of the text
+                    let count = text_len.div_ceil(window_len);
+                    (0..count)
+                        .map(|i| text_start + (i * window_len).min(text_len - window_len))
+                        .collect()
+                }
+            };
+            starts
+                .into_iter()
+                .map(|start| {
+                    (0..text_start)
+                        .chain(start..start + window_len)
+                        .chain(text_end..tokens.len())
+                        .collect()
+                })
+                .collect()
+        };
+
+        let mut window_feats = Vec::with_capacity(windows.len());
+        for window in &windows {
+            let ids: Vec<u32> = window.iter().map(|&i| tokens[i]).collect();
+            let input_ids = Tensor::from_vec(ids, (1, window.len()), &self.device)?;
+            let feats = self.text_encoder.forward(&input_ids)?;
+            let feats = match &weights {
+                Some(weights) => {
+                    let weights: Vec<f32> = window.iter().map(|&i| weights[i]).collect();
+                    self.apply_weights(feats, weights)?
+                }
+                None => feats,
+            };
+            window_feats.push(feats);
+        }
+
+        let feats = if window_feats.len() == 1 {
+            window_feats.remove(0)
+        } else {
+            Tensor::stack(&window_feats, 0)?.mean(0)?
+        };
+        let mask = Tensor::ones((1, feats.dim(1)?), DType::U8, &self.device)?;
+        Ok((feats, mask))
+    }
+
+    /// Scale each token's embedding by its weight, keeping the overall mean
+    fn apply_weights(&self, feats: Tensor, weights: Vec<f32>) -> Result<Tensor, DiffusionError> {
+        let num_tokens = weights.len();
+        let weights =
+            Tensor::from_vec(weights, (1, num_tokens, 1), &self.device)?.to_dtype(feats.dtype())?;
+
+        let original_mean = feats.to_dtype(DType::F32)?.mean_all()?.to_scalar::<f32>()?;
+        let weighted_feats = feats.broadcast_mul(&weights)?;
+        let weighted_mean = weighted_feats
+            .to_dtype(DType::F32)?
+            .mean_all()?
+            .to_scalar::<f32>()?;
+        Ok(if weighted_mean != 0.0 {
+            (weighted_feats * (original_mean / weighted_mean) as f64)?
+        } else {
+            weighted_feats
+        })
+    }
+
+    /// Generate an image, checking `token` between denoising steps so a
+    /// cancelled generation stops within one step and frees the device
+    pub fn generate_cancellable(
+        &self,
+        request: &ImageGenRequest,
+        token: CancellationToken,
+    ) -> Result<ImageGenResponse, DiffusionError> {
+        self.generate_internal(request, &token, &mut |_| {})
+            .map_err(map_out_of_memory)
     }
 
     /// Generate image from request
-    fn generate_internal(&self, request: &ImageGenRequest) -> Result<ImageGenResponse> {
+    fn generate_internal(
+        &self,
+        request: &ImageGenRequest,
+        token: &CancellationToken,
+        on_step: &mut dyn FnMut(StepProgress),
+    ) -> Result<ImageGenResponse, DiffusionError> {
         let num_steps = request.steps as usize;
 
         // Set seed if provided
@@ -154,41 +380,17 @@ impl ZImagePipeline {
             self.device.set_seed(seed)?;
         }
 
-        // Tokenize prompt
-        let formatted_prompt = Self::format_prompt(&request.prompt);
-        let tokens = self
-            .tokenizer
-            .encode(formatted_prompt.as_str(), true)
-            .map_err(|e| anyhow::anyhow!("Tokenization failed: {}", e))?
-            .get_ids()
-            .to_vec();
-
-        let input_ids = Tensor::from_vec(tokens.clone(), (1, tokens.len()), &self.device)?;
-
-        // Get text embeddings
-        let cap_feats = self.text_encoder.forward(&input_ids)?;
-        let cap_mask = Tensor::ones((1, tokens.len()), DType::U8, &self.device)?;
-
-        // Process negative prompt for CFG
-        let (neg_cap_feats, neg_cap_mask) = if let Some(ref neg_prompt) = request.negative_prompt {
-            if !neg_prompt.is_empty() && request.guidance_scale > 1.0 {
-                let formatted_neg = Self::format_prompt(neg_prompt);
-                let neg_tokens = self
-                    .tokenizer
-                    .encode(formatted_neg.as_str(), true)
-                    .map_err(|e| anyhow::anyhow!("Negative prompt tokenization failed: {}", e))?
-                    .get_ids()
-                    .to_vec();
-                let neg_input_ids =
-                    Tensor::from_vec(neg_tokens.clone(), (1, neg_tokens.len()), &self.device)?;
-                let neg_feats = self.text_encoder.forward(&neg_input_ids)?;
-                let neg_mask = Tensor::ones((1, neg_tokens.len()), DType::U8, &self.device)?;
-                (Some(neg_feats), Some(neg_mask))
-            } else {
-                (None, None)
-            }
-        } else {
-            (None, None)
+        // Get text embeddings, shared by all images with the same text
+        let PromptEmbeddings {
+            cap_feats,
+            cap_mask,
+            negative,
+        } = self
+            .text_embeddings
+            .get_or_encode(request, || self.encode_prompts(request))?;
+        let (neg_cap_feats, neg_cap_mask) = match negative {
+            Some((feats, mask)) => (Some(feats), Some(mask)),
+            None => (None, None),
         };
 
         // Calculate latent dimensions
@@ -199,12 +401,11 @@ impl ZImagePipeline {
         let width = request.width as usize;
 
         if height % vae_align != 0 || width % vae_align != 0 {
-            anyhow::bail!(
-                "Image dimensions must be divisible by {}. Got {}x{}",
-                vae_align,
-                width,
-                height
-            );
+            return Err(DiffusionError::InvalidDimensions {
+                width: request.width,
+                height: request.height,
+                multiple_of: vae_align as u32,
+            });
         }
 
         let latent_h = 2 * (height / vae_align);
@@ -230,7 +431,11 @@ impl ZImagePipeline {
         latents = latents.unsqueeze(2)?; // Add frame dimension
 
         // Denoising loop
-        for _ in 0..num_steps {
+        for step in 1..=num_steps {
+            if token.is_cancelled() {
+                return Err(DiffusionError::Cancelled);
+            }
+
             let t = scheduler.current_timestep_normalized();
             let t_tensor =
                 Tensor::from_vec(vec![t as f32], (1,), &self.device)?.to_dtype(self.dtype)?;
@@ -263,13 +468,22 @@ impl ZImagePipeline {
             let latents_4d = latents.squeeze(2)?;
             let prev_latents = scheduler.step(&noise_pred_4d, &latents_4d)?;
             latents = prev_latents.unsqueeze(2)?;
+
+            on_step(StepProgress {
+                step: step as u32,
+                total_steps: num_steps as u32,
+            });
         }
 
         drop(scheduler); // Release lock
 
-        // VAE decode
-        let latents = latents.squeeze(2)?;
-        let image = self.vae.decode(&latents)?;
+        if token.is_cancelled() {
+            return Err(DiffusionError::Cancelled);
+        }
+
+        // VAE decode, in the VAE's dtype
+        let latents = latents.squeeze(2)?.to_dtype(self.vae_dtype)?;
+        let image = self.vae.decode(&latents)?.to_dtype(self.dtype)?;
 
         // Post-process
         let image = postprocess_image(&image)?;
@@ -294,12 +508,45 @@ impl ZImagePipeline {
 
 impl DiffusionModel for ZImagePipeline {
     fn generate(&self, request: &ImageGenRequest) -> Result<ImageGenResponse> {
-        self.generate_internal(request)
+        ZImagePipeline::generate_cancellable(self, request, CancellationToken::new())
+            .map_err(DiffusionError::into_anyhow)
+    }
+
+    fn generate_cancellable(
+        &self,
+        request: &ImageGenRequest,
+        token: CancellationToken,
+    ) -> Result<ImageGenResponse> {
+        ZImagePipeline::generate_cancellable(self, request, token)
+            .map_err(DiffusionError::into_anyhow)
+    }
+
+    fn generate_with_progress(
+        &self,
+        request: &ImageGenRequest,
+        token: CancellationToken,
+        on_step: &mut dyn FnMut(StepProgress),
+    ) -> Result<ImageGenResponse> {
+        self.generate_internal(request, &token, on_step)
+            .map_err(map_out_of_memory)
+            .map_err(DiffusionError::into_anyhow)
     }
 
     fn name(&self) -> &str {
         "Z-Image-Turbo"
     }
+
+    fn caps(&self) -> DiffusionCaps {
+        DiffusionCaps::Z_IMAGE_TURBO
+    }
+}
+
+/// Mark backend out-of-memory failures as [`RuntimeError::OutOfMemory`]
+fn map_out_of_memory(error: DiffusionError) -> DiffusionError {
+    match error {
+        DiffusionError::Backend(e) => DiffusionError::Backend(RuntimeError::map_out_of_memory(e)),
+        other => other,
+    }
 }
 
 // ZImagePipeline is Send + Sync because:
@@ -0,0 +1,92 @@
+//! Requests are checked against a model's recommended ranges, and clamping
+//! moves only the parameters outside them.
+
+use ohmygpu_runtime_diffusion::{DiffusionCaps, ImageGenRequest, OutOfRange};
+
+fn request(steps: u32, guidance_scale: f32) -> ImageGenRequest {
+    ImageGenRequest {
+        prompt: "a lighthouse at dusk".to_string(),
+        steps,
+        guidance_scale,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn in_range_request_is_left_alone() {
+    let caps = DiffusionCaps::Z_IMAGE_TURBO;
+    let mut request = request(8, 0.0);
+    assert!(caps.check(&request).is_empty());
+    assert!(caps.clamp(&mut request).is_empty());
+    assert_eq!((request.steps, request.guidance_scale), (8, 0.0));
+
+    // Both ends are inside
+    assert!(caps.check(&self::request(16, 6.0)).is_empty());
+    assert!(caps.check(&self::request(1, 0.0)).is_empty());
+}
+
+#[test]
+fn check_reports_each_parameter_out_of_range() {
+    let caps = DiffusionCaps::Z_IMAGE_TURBO;
+    assert_eq!(
+        caps.check(&request(50, 7.5)),
+        [
+            OutOfRange::Steps {
+                requested: 50,
+                range: 1..=16
+            },
+            OutOfRange::GuidanceScale {
+                requested: 7.5,
+                range: 0.0..=6.0
+            },
+        ]
+    );
+    assert_eq!(caps.check(&request(0, 1.0)).len(), 1);
+    assert_eq!(
+        caps.check(&request(8, -1.0))[0].to_string(),
+        "guidance scale -1 is outside the recommended 0-6"
+    );
+}
+
+#[test]
+fn clamp_moves_values_to_the_nearest_end() {
+    let caps = DiffusionCaps::Z_IMAGE_TURBO;
+
+    let mut high = request(50, 7.5);
+    assert_eq!(caps.clamp(&mut high).len(), 2);
+    assert_eq!((high.steps, high.guidance_scale), (16, 6.0));
+
+    let mut low = request(0, -1.0);
+    assert_eq!(caps.clamp(&mut low).len(), 2);
+    assert_eq!((low.steps, low.guidance_scale), (1, 0.0));
+
+    // Only the parameter outside its range changes
+    let mut steps_only = request(40, 3.5);
+    assert_eq!(caps.clamp(&mut steps_only).len(), 1);
+    assert_eq!((steps_only.steps, steps_only.guidance_scale), (16, 3.5));
+}
+
+#[test]
+fn nan_guidance_scale_is_out_of_range_and_clamped() {
+    for caps in [DiffusionCaps::Z_IMAGE_TURBO, DiffusionCaps::UNRESTRICTED] {
+        let mut request = request(8, f32::NAN);
+        let out_of_range = caps.check(&request);
+        assert_eq!(out_of_range.len(), 1);
+        assert!(matches!(
+            out_of_range[0],
+            OutOfRange::GuidanceScale { requested, .. } if requested.is_nan()
+        ));
+
+        caps.clamp(&mut request);
+        assert_eq!(request.guidance_scale, *caps.guidance_scale.start());
+        assert_eq!(request.steps, 8);
+    }
+}
+
+#[test]
+fn unrestricted_accepts_any_finite_request() {
+    let caps = DiffusionCaps::UNRESTRICTED;
+    let mut request = request(1_000, 100.0);
+    assert!(caps.clamp(&mut request).is_empty());
+    assert_eq!((request.steps, request.guidance_scale), (1_000, 100.0));
+}
@@ -0,0 +1,79 @@
+//! Every image of a batch gets its own seed, and a batch re-run with its
+//! reported seed comes out the same.
+
+use anyhow::Result;
+use ohmygpu_runtime_diffusion::{DiffusionModel, ImageGenRequest, ImageGenResponse};
+
+/// A pipeline whose one-pixel "image" is a function of the seed alone
+struct SeedImage;
+
+impl DiffusionModel for SeedImage {
+    fn generate(&self, request: &ImageGenRequest) -> Result<ImageGenResponse> {
+        let seed = request.seed.expect("generated without a seed");
+        Ok(ImageGenResponse {
+            pixels: seed.to_le_bytes()[..3].to_vec(),
+            width: 1,
+            height: 1,
+        })
+    }
+
+    fn name(&self) -> &str {
+        "seed-image"
+    }
+}
+
+fn batch(num_images: u32, seed: Option<u64>) -> ImageGenRequest {
+    ImageGenRequest {
+        prompt: "a lighthouse at dusk".to_string(),
+        num_images,
+        seed,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn each_image_gets_the_next_seed() {
+    for base in [0, 7, u64::MAX - 1] {
+        let seeds = batch(4, Some(base)).seeds();
+        assert_eq!(seeds.len(), 4);
+        for (i, pair) in seeds.windows(2).enumerate() {
+            assert_ne!(pair[0], pair[1], "images {} and {} share a seed", i, i + 1);
+            assert_eq!(pair[1], pair[0].wrapping_add(1));
+        }
+    }
+}
+
+#[test]
+fn resolve_seed_picks_a_seed_once() {
+    let mut request = batch(3, None);
+    assert!(request.seeds().is_empty());
+
+    let seed = request.resolve_seed();
+    assert_eq!(request.resolve_seed(), seed);
+    assert_eq!(request.seeds()[0], seed);
+
+    let mut given = batch(3, Some(42));
+    assert_eq!(given.resolve_seed(), 42);
+}
+
+#[test]
+fn rerunning_a_batch_reproduces_it() -> Result<()> {
+    let mut request = batch(4, None);
+    let seed = request.resolve_seed();
+    let first = SeedImage.generate_batch(&request)?;
+    let again = SeedImage.generate_batch(&batch(4, Some(seed)))?;
+
+    let pixels = |images: &[ImageGenResponse]| -> Vec<Vec<u8>> {
+        images.iter().map(|image| image.pixels.clone()).collect()
+    };
+    assert_eq!(first.len(), 4);
+    assert_eq!(pixels(&first), pixels(&again));
+    // The images differ from each other
+    assert_ne!(first[0].pixels, first[1].pixels);
+
+    // Image i alone, from its own seed
+    let seeds = request.seeds();
+    let third = SeedImage.generate(&batch(1, Some(seeds[2])))?;
+    assert_eq!(third.pixels, first[2].pixels);
+    Ok(())
+}
@@ -0,0 +1,72 @@
+//! The images of a batch share their text embeddings: the text encoder runs
+//! once per prompt, however many seeds are generated.
+
+use ohmygpu_runtime_diffusion::{ImageGenRequest, TextEmbeddingCache};
+use std::cell::Cell;
+use std::convert::Infallible;
+
+fn batch(num_images: u32) -> ImageGenRequest {
+    let mut request = ImageGenRequest {
+        prompt: "a lighthouse at dusk".to_string(),
+        negative_prompt: Some("blurry".to_string()),
+        num_images,
+        seed: Some(7),
+        ..Default::default()
+    };
+    request.resolve_seed();
+    request
+}
+
+/// Encode every image of `request` through `cache`, returning how often the
+/// encoder ran
+fn encode_batch(cache: &TextEmbeddingCache<String>, request: &ImageGenRequest) -> usize {
+    let runs = Cell::new(0);
+    for index in 0..request.num_images {
+        let image = request.for_image(index);
+        let embeddings = cache
+            .get_or_encode(&image, || {
+                runs.set(runs.get() + 1);
+                Ok::<_, Infallible>(image.prompt.clone())
+            })
+            .unwrap();
+        assert_eq!(embeddings, request.prompt);
+    }
+    runs.get()
+}
+
+#[test]
+fn encoder_runs_once_regardless_of_grid_size() {
+    for num_images in [1, 4, 16] {
+        let cache = TextEmbeddingCache::default();
+        assert_eq!(encode_batch(&cache, &batch(num_images)), 1);
+    }
+}
+
+#[test]
+fn size_and_steps_do_not_invalidate() {
+    let cache = TextEmbeddingCache::default();
+    let mut request = batch(4);
+    assert_eq!(encode_batch(&cache, &request), 1);
+
+    request.width = 768;
+    request.steps = 20;
+    request.seed = Some(1234);
+    assert_eq!(encode_batch(&cache, &request), 0);
+}
+
+#[test]
+fn new_text_is_encoded_again() {
+    let cache = TextEmbeddingCache::default();
+    let mut request = batch(2);
+    assert_eq!(encode_batch(&cache, &request), 1);
+
+    request.negative_prompt = Some("low quality".to_string());
+    assert_eq!(encode_batch(&cache, &request), 1);
+
+    // Without CFG the negative prompt is not encoded, so the key changes
+    request.guidance_scale = 1.0;
+    assert_eq!(encode_batch(&cache, &request), 1);
+
+    request.prompt = "a lighthouse at dawn".to_string();
+    assert_eq!(encode_batch(&cache, &request), 1);
+}
@@ -0,0 +1,84 @@
+//! ohmygpu_runtime_video - Video generation runtime
+//!
+//! This crate defines the interface for text-to-video backends, mirroring
+//! the diffusion runtime. No backend is implemented yet, so [`load_model`]
+//! fails with [`VideoError::NoVideoModels`].
+
+use anyhow::Result;
+use candle_core::Device;
+use std::path::Path;
+
+/// Errors from the video pipelines. Through `anyhow` they can be recovered
+/// with `downcast_ref`.
+#[derive(Debug, thiserror::Error)]
+pub enum VideoError {
+    /// No installed model has a video backend
+    #[error("No video models installed; video generation is not supported yet")]
+    NoVideoModels,
+}
+
+/// Video generation request
+#[derive(Debug, Clone)]
+pub struct VideoGenRequest {
+    pub prompt: String,
+    pub negative_prompt: Option<String>,
+    pub width: u32,
+    pub height: u32,
+    /// Number of frames to generate
+    pub num_frames: u32,
+    /// Playback rate of the frames, which backends may condition on
+    pub fps: u32,
+    pub steps: u32,
+    pub guidance_scale: f32,
+    pub seed: Option<u64>,
+}
+
+impl Default for VideoGenRequest {
+    fn default() -> Self {
+        Self {
+            prompt: String::new(),
+            negative_prompt: None,
+            width: 832,
+            height: 480,
+            num_frames: 49,
+            fps: 16,
+            steps: 30,
+            guidance_scale: 5.0,
+            seed: None,
+        }
+    }
+}
+
+impl VideoGenRequest {
+    /// Length of the video in seconds
+    pub fn duration_secs(&self) -> f64 {
+        self.num_frames as f64 / self.fps.max(1) as f64
+    }
+}
+
+/// Video generation response
+pub struct VideoGenResponse {
+    /// Raw pixel data of each frame (RGB, u8), in playback order
+    pub frames: Vec<Vec<u8>>,
+    pub width: u32,
+    pub height: u32,
+    pub fps: u32,
+}
+
+/// Trait for video model backends
+pub trait VideoModel: Send + Sync {
+    /// Generate a video from a text prompt
+    fn generate(&self, request: &VideoGenRequest) -> Result<VideoGenResponse>;
+
+    /// Get the model name
+    fn name(&self) -> &str;
+}
+
+/// Load a video model from a path.
+///
+/// Always fails until a backend exists; backends are matched on the model
+/// directory here, like `ohmygpu_runtime_diffusion::load_model`.
+pub fn load_model(model_path: &Path, device: &Device) -> Result<Box<dyn VideoModel>> {
+    let _ = (model_path, device);
+    Err(VideoError::NoVideoModels.into())
+}
@@ -1,36 +1,90 @@
 use axum::{
     extract::State,
+    http::StatusCode,
     response::{
         sse::{Event, Sse},
         IntoResponse, Response,
     },
     Json,
 };
-use futures_util::stream::Stream;
 use serde::{Deserialize, Serialize};
-use std::{convert::Infallible, sync::Arc};
+use std::{collections::HashMap, convert::Infallible, sync::Arc};
 
+use crate::api::{error_status, keep_alive_event, keep_alive_interval, recv_or_keep_alive};
+use crate::limits::Throttle;
 use crate::state::AppState;
-use ohmygpu_runtime_api::{ChatMessage, ChatRequest, Runtime};
+use ohmygpu_core::config::InferenceConfig;
+use ohmygpu_runtime_api::{ChatMessage, ChatRequest};
 
 #[derive(Debug, Deserialize)]
 pub struct ChatCompletionRequest {
     pub model: String,
     pub messages: Vec<ChatMessageInput>,
-    #[serde(default = "default_max_tokens")]
-    pub max_tokens: u32,
-    #[serde(default = "default_temperature")]
-    pub temperature: f32,
+    /// Defaults to `inference.max_tokens` from the config
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+    /// Defaults to the model's `set-params` value, else `inference.temperature`
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    /// Defaults to the model's `set-params` value, else `inference.top_p`
+    #[serde(default)]
+    pub top_p: Option<f32>,
+    #[serde(default)]
+    pub frequency_penalty: f32,
+    #[serde(default)]
+    pub presence_penalty: f32,
+    /// Token id to bias added to its logit
+    #[serde(default)]
+    pub logit_bias: HashMap<u32, f32>,
+    /// Makes sampling reproducible; random when unset
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// Sampling extensions, as in vLLM and llama.cpp's server
+    #[serde(default)]
+    pub top_k: u32,
+    #[serde(default)]
+    pub min_p: f32,
+    #[serde(default = "default_repetition_penalty")]
+    pub repetition_penalty: f32,
     #[serde(default)]
     pub stream: bool,
+    /// Return the rendered prompt instead of generating (for debugging templates)
+    #[serde(default)]
+    pub debug_prompt: bool,
+    /// Add each delta's token ids to streamed chunks (ohmygpu extension)
+    #[serde(default)]
+    pub return_token_ids: bool,
 }
 
-fn default_max_tokens() -> u32 {
-    2048
+fn default_repetition_penalty() -> f32 {
+    1.0
 }
 
-fn default_temperature() -> f32 {
-    0.7
+impl ChatCompletionRequest {
+    /// The runtime request, with unset parameters taken from `defaults`
+    fn into_chat_request(self, defaults: &InferenceConfig, stream: bool) -> ChatRequest {
+        ChatRequest {
+            messages: self
+                .messages
+                .into_iter()
+                .map(|m| ChatMessage {
+                    role: m.role,
+                    content: m.content,
+                })
+                .collect(),
+            max_tokens: self.max_tokens.unwrap_or(defaults.max_tokens),
+            temperature: self.temperature.unwrap_or(defaults.temperature),
+            top_p: self.top_p.unwrap_or(defaults.top_p),
+            top_k: self.top_k,
+            min_p: self.min_p,
+            repetition_penalty: self.repetition_penalty,
+            frequency_penalty: self.frequency_penalty,
+            presence_penalty: self.presence_penalty,
+            logit_bias: self.logit_bias,
+            seed: self.seed,
+            stream,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -47,6 +101,8 @@ pub struct ChatCompletionResponse {
     pub model: String,
     pub choices: Vec<ChatChoice>,
     pub usage: Usage,
+    /// Non-standard extension; OpenAI clients ignore unknown fields
+    pub timings: Timings,
 }
 
 #[derive(Serialize)]
@@ -69,6 +125,14 @@ pub struct Usage {
     pub total_tokens: u32,
 }
 
+#[derive(Serialize)]
+pub struct Timings {
+    pub prefill_ms: u64,
+    pub generation_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tokens_per_second: Option<f64>,
+}
+
 #[derive(Serialize)]
 pub struct ChatCompletionChunk {
     pub id: String,
@@ -92,6 +156,17 @@ pub struct Delta {
     pub role: Option<&'static str>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub content: Option<String>,
+    /// With `return_token_ids`: the tokens `content` was decoded from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_ids: Option<Vec<u32>>,
+}
+
+/// Returned instead of a completion when `debug_prompt` is set
+#[derive(Serialize)]
+pub struct DebugPromptResponse {
+    pub object: &'static str,
+    pub model: String,
+    pub prompt: String,
 }
 
 #[derive(Serialize)]
@@ -113,17 +188,21 @@ pub async fn chat_completions(
     if let Err(e) = state.load_model(&request.model).await {
         tracing::error!("Failed to load model {}: {}", request.model, e);
         return (
-            axum::http::StatusCode::BAD_REQUEST,
+            error_status(&e),
             Json(ErrorResponse {
                 error: ErrorDetail {
                     message: format!("Failed to load model '{}': {}", request.model, e),
-                    r#type: "invalid_request_error",
+                    r#type: error_type(&e),
                 },
             }),
         )
             .into_response();
     }
 
+    if request.debug_prompt {
+        return debug_prompt(state, request).await.into_response();
+    }
+
     if request.stream {
         chat_completions_stream(state, request).await.into_response()
     } else {
@@ -133,28 +212,51 @@ pub async fn chat_completions(
     }
 }
 
-async fn chat_completions_non_stream(
+async fn debug_prompt(
     state: Arc<AppState>,
     request: ChatCompletionRequest,
-) -> Result<Json<ChatCompletionResponse>, (axum::http::StatusCode, Json<ErrorResponse>)> {
+) -> Json<DebugPromptResponse> {
+    let messages: Vec<ChatMessage> = request
+        .messages
+        .into_iter()
+        .map(|m| ChatMessage {
+            role: m.role,
+            content: m.content,
+        })
+        .collect();
+
     let runtime = state.runtime.read().await;
+    Json(DebugPromptResponse {
+        object: "chat.completion.prompt",
+        model: request.model,
+        prompt: runtime.render_prompt(&messages),
+    })
+}
 
-    let chat_request = ChatRequest {
-        messages: request
-            .messages
-            .into_iter()
-            .map(|m| ChatMessage {
-                role: m.role,
-                content: m.content,
-            })
-            .collect(),
-        max_tokens: request.max_tokens,
-        temperature: request.temperature,
-        stream: false,
-    };
+async fn chat_completions_non_stream(
+    state: Arc<AppState>,
+    request: ChatCompletionRequest,
+) -> Result<Json<ChatCompletionResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let model = request.model.clone();
+    let defaults = state.request_defaults(&model).await;
+    let chat_request = request.into_chat_request(&defaults, false);
+    let _permit = state.reserve_tokens(&chat_request).await.map_err(|e| {
+        tracing::error!("Chat error: {}", e);
+        error_response(&e)
+    })?;
+    let transcript = state.start_transcript("/v1/chat/completions", &model, &chat_request);
 
+    let runtime = state.runtime.read().await;
     match runtime.chat(chat_request).await {
         Ok(response) => {
+            if let Some(transcript) = transcript {
+                transcript.finish(
+                    response.content.clone(),
+                    response.tokens_used,
+                    Some(response.finish_reason.clone()),
+                );
+            }
+            let tokens_per_second = response.tokens_per_second();
             let created = std::time::SystemTime::now()
                 .duration_since(std::time::UNIX_EPOCH)
                 .unwrap()
@@ -163,7 +265,7 @@ async fn chat_completions_non_stream(
                 id: format!("chatcmpl-{}", uuid_simple()),
                 object: "chat.completion",
                 created,
-                model: request.model,
+                model,
                 choices: vec![ChatChoice {
                     index: 0,
                     message: ChatMessageOutput {
@@ -177,37 +279,59 @@ async fn chat_completions_non_stream(
                     completion_tokens: response.tokens_used,
                     total_tokens: response.tokens_used,
                 },
+                timings: Timings {
+                    prefill_ms: response.prefill_ms,
+                    generation_ms: response.generation_ms,
+                    tokens_per_second,
+                },
             }))
         }
         Err(e) => {
             tracing::error!("Chat error: {}", e);
-            Err((
-                axum::http::StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: ErrorDetail {
-                        message: format!("Generation error: {}", e),
-                        r#type: "server_error",
-                    },
-                }),
-            ))
+            Err(error_response(&e))
         }
     }
 }
 
-async fn chat_completions_stream(
-    state: Arc<AppState>,
-    request: ChatCompletionRequest,
-) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+async fn chat_completions_stream(state: Arc<AppState>, request: ChatCompletionRequest) -> Response {
     let id = format!("chatcmpl-{}", uuid_simple());
     let created = std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
         .unwrap()
         .as_secs() as i64;
     let model = request.model.clone();
+    let return_token_ids = request.return_token_ids;
 
-    let runtime = state.runtime.clone();
+    let defaults = state.request_defaults(&request.model).await;
+    let mut throttle = Throttle::new(state.config.read().await.daemon.max_tokens_per_second);
+    let mut keep_alive = keep_alive_interval(&state.config.read().await.daemon);
+    let chat_request = request.into_chat_request(&defaults, true);
+    // Held until the stream ends
+    let permit = match state.reserve_tokens(&chat_request).await {
+        Ok(permit) => permit,
+        Err(e) => {
+            tracing::error!("Stream error: {}", e);
+            return error_response(&e).into_response();
+        }
+    };
+    let transcript = state.start_transcript("/v1/chat/completions", &model, &chat_request);
+
+    // Start generation before responding so request errors get a proper status
+    let rx = {
+        let runtime = state.runtime.read().await;
+        runtime.chat_stream(chat_request).await
+    };
+    let mut rx = match rx {
+        Ok(rx) => rx,
+        Err(e) => {
+            tracing::error!("Stream error: {}", e);
+            return error_response(&e).into_response();
+        }
+    };
 
     let stream = async_stream::stream! {
+        let _permit = permit;
+
         // Send initial chunk with role
         let initial_chunk = ChatCompletionChunk {
             id: id.clone(),
@@ -219,57 +343,101 @@ async fn chat_completions_stream(
                 delta: Delta {
                     role: Some("assistant"),
                     content: None,
+                    token_ids: None,
                 },
                 finish_reason: None,
             }],
         };
-        yield Ok(Event::default().data(serde_json::to_string(&initial_chunk).unwrap()));
+        yield Ok::<_, Infallible>(Event::default().data(serde_json::to_string(&initial_chunk).unwrap()));
 
-        let chat_request = ChatRequest {
-            messages: request
-                .messages
-                .into_iter()
-                .map(|m| ChatMessage {
-                    role: m.role,
-                    content: m.content,
-                })
-                .collect(),
-            max_tokens: request.max_tokens,
-            temperature: request.temperature,
-            stream: true,
-        };
+        let mut output = String::new();
+        let mut tokens = 0;
+        let mut finish_reason = None;
 
-        let runtime_guard = runtime.read().await;
-        match runtime_guard.chat_stream(chat_request).await {
-            Ok(mut rx) => {
-                while let Some(token) = rx.recv().await {
-                    let chunk = ChatCompletionChunk {
-                        id: id.clone(),
-                        object: "chat.completion.chunk",
-                        created,
-                        model: model.clone(),
-                        choices: vec![ChatChoiceDelta {
-                            index: 0,
-                            delta: Delta {
-                                role: None,
-                                content: if token.content.is_empty() { None } else { Some(token.content) },
-                            },
-                            finish_reason: token.finish_reason,
-                        }],
-                    };
-                    yield Ok(Event::default().data(serde_json::to_string(&chunk).unwrap()));
+        loop {
+            let token = match recv_or_keep_alive(&mut rx, keep_alive).await {
+                Ok(Some(token)) => token,
+                Ok(None) => break,
+                Err(_) => {
+                    yield Ok(keep_alive_event());
+                    continue;
                 }
+            };
+            // The prompt has been evaluated; tokens keep the stream alive now
+            keep_alive = None;
+
+            // Generation failed partway: an OpenAI error object ends the stream
+            if let Some(error) = token.error {
+                tracing::error!("Stream error: {}", error);
+                finish_reason = token.finish_reason;
+                let error = ErrorResponse {
+                    error: ErrorDetail {
+                        message: format!("Generation error: {}", error),
+                        r#type: "server_error",
+                    },
+                };
+                yield Ok(Event::default().data(serde_json::to_string(&error).unwrap()));
+                break;
+            }
+
+            if !token.content.is_empty() {
+                throttle.wait().await;
             }
-            Err(e) => {
-                tracing::error!("Stream error: {}", e);
+            if transcript.is_some() {
+                output.push_str(&token.content);
+                tokens += token.token_ids.len() as u32;
+                finish_reason.clone_from(&token.finish_reason);
             }
+            let chunk = ChatCompletionChunk {
+                id: id.clone(),
+                object: "chat.completion.chunk",
+                created,
+                model: model.clone(),
+                choices: vec![ChatChoiceDelta {
+                    index: 0,
+                    delta: Delta {
+                        role: None,
+                        token_ids: (return_token_ids && !token.token_ids.is_empty())
+                            .then_some(token.token_ids),
+                        content: if token.content.is_empty() { None } else { Some(token.content) },
+                    },
+                    finish_reason: token.finish_reason,
+                }],
+            };
+            yield Ok(Event::default().data(serde_json::to_string(&chunk).unwrap()));
+        }
+
+        if let Some(transcript) = transcript {
+            transcript.finish(output, tokens, finish_reason);
         }
 
         // Send [DONE] marker
         yield Ok(Event::default().data("[DONE]"));
     };
 
-    Sse::new(stream)
+    Sse::new(stream).into_response()
+}
+
+/// OpenAI-style error body for a generation error, with its status code
+fn error_response(error: &anyhow::Error) -> (StatusCode, Json<ErrorResponse>) {
+    (
+        error_status(error),
+        Json(ErrorResponse {
+            error: ErrorDetail {
+                message: format!("Generation error: {}", error),
+                r#type: error_type(error),
+            },
+        }),
+    )
+}
+
+/// OpenAI error type for a generation error
+fn error_type(error: &anyhow::Error) -> &'static str {
+    if error_status(error).is_client_error() {
+        "invalid_request_error"
+    } else {
+        "server_error"
+    }
 }
 
 fn uuid_simple() -> String {
@@ -0,0 +1,110 @@
+use axum::{
+    extract::State,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::api::error_status;
+use crate::state::AppState;
+
+#[derive(Debug, Deserialize)]
+pub struct EmbeddingRequest {
+    pub model: String,
+    pub input: EmbeddingInput,
+}
+
+/// OpenAI accepts a single string or a list of strings
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum EmbeddingInput {
+    Single(String),
+    Batch(Vec<String>),
+}
+
+impl EmbeddingInput {
+    fn into_vec(self) -> Vec<String> {
+        match self {
+            EmbeddingInput::Single(input) => vec![input],
+            EmbeddingInput::Batch(inputs) => inputs,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct EmbeddingListResponse {
+    pub object: &'static str,
+    pub data: Vec<EmbeddingObject>,
+    pub model: String,
+    pub usage: EmbeddingUsage,
+}
+
+#[derive(Serialize)]
+pub struct EmbeddingObject {
+    pub object: &'static str,
+    pub index: usize,
+    pub embedding: Vec<f32>,
+}
+
+#[derive(Serialize)]
+pub struct EmbeddingUsage {
+    pub prompt_tokens: u32,
+    pub total_tokens: u32,
+}
+
+pub async fn embeddings(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<EmbeddingRequest>,
+) -> Response {
+    // Auto-load model if not loaded
+    if let Err(e) = state.load_model(&request.model).await {
+        tracing::error!("Failed to load model {}: {}", request.model, e);
+        return error_response(
+            error_status(&e),
+            format!("Failed to load model '{}': {}", request.model, e),
+        );
+    }
+
+    let runtime = state.runtime.read().await;
+    match runtime.embed(request.input.into_vec()).await {
+        Ok(response) => Json(EmbeddingListResponse {
+            object: "list",
+            data: response
+                .embeddings
+                .into_iter()
+                .enumerate()
+                .map(|(index, embedding)| EmbeddingObject {
+                    object: "embedding",
+                    index,
+                    embedding,
+                })
+                .collect(),
+            model: request.model,
+            usage: EmbeddingUsage {
+                prompt_tokens: response.tokens_used,
+                total_tokens: response.tokens_used,
+            },
+        })
+        .into_response(),
+        Err(e) => {
+            tracing::error!("Embedding error: {}", e);
+            error_response(error_status(&e), format!("Embedding error: {}", e))
+        }
+    }
+}
+
+fn error_response(status: axum::http::StatusCode, message: String) -> Response {
+    let r#type = if status.is_client_error() {
+        "invalid_request_error"
+    } else {
+        "server_error"
+    };
+    (
+        status,
+        Json(serde_json::json!({
+            "error": { "message": message, "type": r#type }
+        })),
+    )
+        .into_response()
+}
@@ -0,0 +1,231 @@
+//! `POST /v1/images/generations`: text-to-image through
+//! `ohmygpu_runtime_diffusion`, returning URLs under `/images/` or base64
+
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use base64::Engine;
+use ohmygpu_runtime_diffusion::{CancellationToken, DiffusionError, ImageGenRequest};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::api::error_status;
+use crate::images::ImageStore;
+use crate::state::AppState;
+
+#[derive(Debug, Deserialize)]
+pub struct ImageGenerationRequest {
+    pub model: String,
+    pub prompt: String,
+    /// Images to generate, with consecutive seeds
+    #[serde(default = "default_n")]
+    pub n: u32,
+    /// `<width>x<height>`, defaulting to the pipeline's default size
+    #[serde(default)]
+    pub size: Option<String>,
+    #[serde(default)]
+    pub response_format: ResponseFormat,
+    /// ohmygpu extensions
+    #[serde(default)]
+    pub negative_prompt: Option<String>,
+    #[serde(default)]
+    pub steps: Option<u32>,
+    #[serde(default)]
+    pub guidance_scale: Option<f32>,
+    /// Reproduces an earlier response when passed the `seed` of its first
+    /// image
+    #[serde(default)]
+    pub seed: Option<u64>,
+}
+
+fn default_n() -> u32 {
+    1
+}
+
+/// As in OpenAI's API
+const MAX_IMAGES: u32 = 10;
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResponseFormat {
+    /// A URL under `/images/`, valid for `daemon.image_retention_secs`
+    #[default]
+    Url,
+    B64Json,
+}
+
+#[derive(Serialize)]
+pub struct ImageGenerationResponse {
+    pub created: i64,
+    pub data: Vec<ImageData>,
+}
+
+#[derive(Serialize)]
+pub struct ImageData {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub b64_json: Option<String>,
+    /// ohmygpu extension: the seed that reproduces this image alone
+    pub seed: u64,
+}
+
+pub async fn generate_images(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<ImageGenerationRequest>,
+) -> Response {
+    if !(1..=MAX_IMAGES).contains(&request.n) {
+        return error_response(
+            StatusCode::BAD_REQUEST,
+            format!("n must be between 1 and {}", MAX_IMAGES),
+        );
+    }
+    let mut gen_request = ImageGenRequest {
+        prompt: request.prompt,
+        negative_prompt: request.negative_prompt,
+        seed: request.seed,
+        num_images: request.n,
+        ..Default::default()
+    };
+    if let Some(size) = &request.size {
+        let Some((width, height)) = parse_size(size) else {
+            return error_response(
+                StatusCode::BAD_REQUEST,
+                format!(
+                    "Invalid size '{}'; use <width>x<height>, e.g. 1024x1024",
+                    size
+                ),
+            );
+        };
+        gen_request.width = width;
+        gen_request.height = height;
+    }
+    gen_request.steps = request.steps.unwrap_or(gen_request.steps);
+    gen_request.guidance_scale = request.guidance_scale.unwrap_or(gen_request.guidance_scale);
+
+    let mut slot = state.image_pipeline.clone().lock_owned().await;
+    let pipeline = match state.image_pipeline(&mut slot, &request.model).await {
+        Ok(pipeline) => pipeline,
+        Err(e) => {
+            tracing::error!("Failed to load image model {}: {}", request.model, e);
+            return error_response(
+                error_status(&e),
+                format!("Failed to load model '{}': {}", request.model, e),
+            );
+        }
+    };
+    for param in pipeline.caps().clamp(&mut gen_request) {
+        tracing::warn!("{} for {}; clamped", param, request.model);
+    }
+
+    gen_request.resolve_seed();
+    let seeds = gen_request.seeds();
+    // Dropped with this handler when the client disconnects, which stops
+    // the generation after its current step
+    let token = CancellationToken::new();
+    let _cancel_on_drop = token.clone().drop_guard();
+    let generated = tokio::task::spawn_blocking(move || {
+        // Keeps the pipeline locked until the generation has stopped
+        let _slot = slot;
+        (0..gen_request.num_images)
+            .map(|index| {
+                pipeline.generate_cancellable(&gen_request.for_image(index), token.clone())
+            })
+            .collect::<anyhow::Result<Vec<_>>>()
+    })
+    .await;
+    let images = match generated {
+        Ok(Ok(images)) => images,
+        Ok(Err(e)) if matches!(e.downcast_ref(), Some(DiffusionError::Cancelled)) => {
+            tracing::info!("Image generation cancelled");
+            return error_response(StatusCode::SERVICE_UNAVAILABLE, e.to_string());
+        }
+        Ok(Err(e)) => {
+            tracing::error!("Image generation error: {}", e);
+            return error_response(
+                image_error_status(&e),
+                format!("Image generation error: {}", e),
+            );
+        }
+        Err(e) => {
+            return error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
+        }
+    };
+
+    let mut data = Vec::with_capacity(images.len());
+    for (image, seed) in images.iter().zip(seeds) {
+        match encode(&state, image, seed, request.response_format).await {
+            Ok(image) => data.push(image),
+            Err(e) => {
+                tracing::error!("Failed to store image: {}", e);
+                return error_response(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed to store image: {}", e),
+                );
+            }
+        }
+    }
+    Json(ImageGenerationResponse {
+        created: chrono::Utc::now().timestamp(),
+        data,
+    })
+    .into_response()
+}
+
+/// The image as a URL in the image store, or inline as base64
+async fn encode(
+    state: &AppState,
+    image: &ohmygpu_runtime_diffusion::ImageGenResponse,
+    seed: u64,
+    format: ResponseFormat,
+) -> anyhow::Result<ImageData> {
+    let png = image.png()?;
+    Ok(match format {
+        ResponseFormat::Url => {
+            let id = state.images.save_png(&png)?;
+            ImageData {
+                url: Some(ImageStore::url(&*state.config.read().await, &id)),
+                b64_json: None,
+                seed,
+            }
+        }
+        ResponseFormat::B64Json => ImageData {
+            url: None,
+            b64_json: Some(base64::engine::general_purpose::STANDARD.encode(png)),
+            seed,
+        },
+    })
+}
+
+fn parse_size(size: &str) -> Option<(u32, u32)> {
+    let (width, height) = size.split_once('x')?;
+    Some((width.trim().parse().ok()?, height.trim().parse().ok()?))
+}
+
+/// Requests the pipeline rejects are client errors; the rest as for chat
+fn image_error_status(error: &anyhow::Error) -> StatusCode {
+    match error.downcast_ref::<DiffusionError>() {
+        Some(DiffusionError::InvalidDimensions { .. } | DiffusionError::TokenizeFailed(_)) => {
+            StatusCode::BAD_REQUEST
+        }
+        _ => error_status(error),
+    }
+}
+
+fn error_response(status: StatusCode, message: String) -> Response {
+    let r#type = if status.is_client_error() {
+        "invalid_request_error"
+    } else {
+        "server_error"
+    };
+    (
+        status,
+        Json(serde_json::json!({
+            "error": { "message": message, "type": r#type }
+        })),
+    )
+        .into_response()
+}
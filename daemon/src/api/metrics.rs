@@ -0,0 +1,43 @@
+//! `GET /metrics` in the Prometheus text format
+
+use axum::{extract::State, http::header, response::IntoResponse};
+use std::fmt::Write;
+use std::sync::Arc;
+
+use crate::state::AppState;
+
+pub async fn metrics(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let usage = state.token_budget.usage();
+    let limit = state.config.read().await.daemon.max_batch_total_tokens;
+
+    let mut body = String::new();
+    let mut gauge = |name: &str, help: &str, value: u64| {
+        let _ = writeln!(body, "# HELP {} {}", name, help);
+        let _ = writeln!(body, "# TYPE {} gauge", name);
+        let _ = writeln!(body, "{} {}", name, value);
+    };
+    gauge(
+        "ohmygpu_batch_tokens",
+        "Tokens (prompt + max_tokens; max_tokens only without max_batch_total_tokens) reserved by the chat requests being processed.",
+        usage.tokens,
+    );
+    if let Some(limit) = limit {
+        gauge(
+            "ohmygpu_max_batch_total_tokens",
+            "Configured daemon.max_batch_total_tokens.",
+            limit,
+        );
+    }
+    gauge(
+        "ohmygpu_requests_processing",
+        "Chat requests being processed.",
+        usage.active.into(),
+    );
+    gauge(
+        "ohmygpu_requests_queued",
+        "Chat requests waiting for max_batch_total_tokens capacity.",
+        usage.queued.into(),
+    );
+
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], body)
+}
@@ -1,28 +1,97 @@
 pub mod chat;
+pub mod embeddings;
+pub mod image_gen;
+pub mod metrics;
 pub mod models;
 pub mod ollama;
 
-use axum::{routing::get, routing::post, Router};
+use axum::{
+    http::StatusCode, middleware, response::sse::Event, routing::get, routing::post, Router,
+};
+use ohmygpu_core::config::DaemonConfig;
+use ohmygpu_runtime_api::RuntimeError;
+use std::time::Duration;
+use tokio::sync::mpsc::Receiver;
+use tokio::time::error::Elapsed;
 
-use crate::state::AppState;
+use crate::images;
+use crate::limits;
+use crate::state::{AppState, LoadError};
 use std::sync::Arc;
 
 pub fn routes(state: Arc<AppState>) -> Router {
+    // Endpoints that run the model count against the per-client request cap
+    let generation = Router::new()
+        .route("/v1/chat/completions", post(chat::chat_completions))
+        .route("/v1/embeddings", post(embeddings::embeddings))
+        .route("/v1/images/generations", post(image_gen::generate_images))
+        .route("/api/chat", post(ollama::chat))
+        .route("/api/generate", post(ollama::generate))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            limits::limit_concurrency,
+        ));
+
     Router::new()
         // Health check
         .route("/health", get(health))
+        // Prometheus metrics: token budget utilization
+        .route("/metrics", get(metrics::metrics))
+        // Generated images, by the id in their URL
+        .route("/images/:file", get(images::serve_image))
         // OpenAI-compatible API
         .route("/v1/models", get(models::list_models))
-        .route("/v1/chat/completions", post(chat::chat_completions))
+        // Download and load progress of the last requested model
+        .route("/api/load-status", get(models::load_status))
         // Ollama-compatible API (drop-in replacement)
-        .route("/api/chat", post(ollama::chat))
-        .route("/api/generate", post(ollama::generate))
         .route("/api/tags", get(ollama::tags))
+        .route("/api/ps", get(ollama::ps))
         .route("/api/version", get(ollama::version))
         .route("/api/show", post(ollama::show))
+        .merge(generation)
         .with_state(state)
 }
 
 async fn health() -> &'static str {
     "ok"
 }
+
+/// HTTP status for a generation or model load error: requests for models or
+/// work the daemon cannot serve are client errors, anything else (including
+/// running out of memory while loading) is a server error
+pub(crate) fn error_status(error: &anyhow::Error) -> StatusCode {
+    if error.downcast_ref::<LoadError>().is_some() {
+        return StatusCode::BAD_REQUEST;
+    }
+    match error.downcast_ref::<RuntimeError>() {
+        Some(RuntimeError::PromptTooLong { .. }) => StatusCode::PAYLOAD_TOO_LARGE,
+        Some(RuntimeError::NotAChatModel(_) | RuntimeError::NotAnEmbeddingModel) => {
+            StatusCode::BAD_REQUEST
+        }
+        Some(RuntimeError::OutOfMemory) => StatusCode::SERVICE_UNAVAILABLE,
+        None => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// Interval between keep-alive comments, `None` when they are disabled
+pub(crate) fn keep_alive_interval(config: &DaemonConfig) -> Option<Duration> {
+    (config.sse_keep_alive_secs > 0).then(|| Duration::from_secs(config.sse_keep_alive_secs))
+}
+
+/// The next token of a stream, or `Err` if `keep_alive` passes without one.
+/// Streaming handlers pass the interval until the first token arrives and
+/// answer `Err` with [`keep_alive_event`].
+pub(crate) async fn recv_or_keep_alive<T>(
+    rx: &mut Receiver<T>,
+    keep_alive: Option<Duration>,
+) -> Result<Option<T>, Elapsed> {
+    match keep_alive {
+        Some(interval) => tokio::time::timeout(interval, rx.recv()).await,
+        None => Ok(rx.recv().await),
+    }
+}
+
+/// `:keep-alive`, an SSE comment clients ignore
+pub(crate) fn keep_alive_event() -> Event {
+    Event::default().comment("keep-alive")
+}
@@ -1,14 +1,18 @@
 use axum::{extract::State, Json};
+use ohmygpu_core::{ModelInfo, ModelSource};
+use ohmygpu_runtime_api::RuntimeCaps;
 use serde::Serialize;
 use std::sync::Arc;
 
-use crate::state::AppState;
+use crate::state::{AppState, LoadStatus};
 
 #[derive(Serialize)]
 pub struct ModelObject {
     pub id: String,
     pub object: &'static str,
-    pub owned_by: &'static str,
+    pub owned_by: String,
+    /// ohmygpu extension: which endpoints this model can serve
+    pub capabilities: RuntimeCaps,
 }
 
 #[derive(Serialize)]
@@ -25,7 +29,8 @@ pub async fn list_models(State(state): State<Arc<AppState>>) -> Json<ModelsRespo
         .map(|m| ModelObject {
             id: m.name.clone(),
             object: "model",
-            owned_by: "user",
+            owned_by: owned_by(m),
+            capabilities: RuntimeCaps::for_model_type(&m.model_type),
         })
         .collect();
 
@@ -34,3 +39,22 @@ pub async fn list_models(State(state): State<Arc<AppState>>) -> Json<ModelsRespo
         data: models,
     })
 }
+
+pub async fn load_status(State(state): State<Arc<AppState>>) -> Json<LoadStatus> {
+    Json(state.load_status())
+}
+
+/// The organization/user that published the model, or "local"
+fn owned_by(model: &ModelInfo) -> String {
+    match &model.source {
+        ModelSource::HuggingFace { repo_id } => repo_id
+            .split_once('/')
+            .map(|(owner, _)| owner.to_string())
+            .unwrap_or_else(|| "huggingface".to_string()),
+        ModelSource::GitHub { repo, .. } => repo
+            .split_once('/')
+            .map(|(owner, _)| owner.to_string())
+            .unwrap_or_else(|| "github".to_string()),
+        ModelSource::Local => "local".to_string(),
+    }
+}
@@ -10,12 +10,14 @@ use axum::{
     },
     Json,
 };
-use futures_util::stream::Stream;
 use serde::{Deserialize, Serialize};
-use std::{convert::Infallible, sync::Arc};
+use std::{convert::Infallible, sync::Arc, time::Instant};
 
+use crate::api::{error_status, keep_alive_event, keep_alive_interval, recv_or_keep_alive};
+use crate::limits::Throttle;
 use crate::state::AppState;
-use ohmygpu_runtime_api::{ChatMessage, ChatRequest, Runtime};
+use ohmygpu_core::config::InferenceConfig;
+use ohmygpu_runtime_api::{ChatMessage, ChatRequest};
 
 // ============================================================================
 // POST /api/chat - Ollama chat endpoint
@@ -29,6 +31,12 @@ pub struct OllamaChatRequest {
     pub stream: Option<bool>,
     #[serde(default)]
     pub options: Option<OllamaOptions>,
+    /// Return the rendered prompt instead of generating (ohmygpu extension)
+    #[serde(default)]
+    pub debug_prompt: bool,
+    /// Add each chunk's token ids to streamed messages (ohmygpu extension)
+    #[serde(default)]
+    pub return_token_ids: bool,
 }
 
 #[derive(Debug, Deserialize)]
@@ -42,7 +50,49 @@ pub struct OllamaOptions {
     #[serde(default)]
     pub temperature: Option<f32>,
     #[serde(default)]
+    pub top_p: Option<f32>,
+    #[serde(default)]
     pub num_predict: Option<u32>,
+    #[serde(default)]
+    pub top_k: Option<u32>,
+    #[serde(default)]
+    pub min_p: Option<f32>,
+    #[serde(default)]
+    pub repeat_penalty: Option<f32>,
+    #[serde(default)]
+    pub frequency_penalty: Option<f32>,
+    #[serde(default)]
+    pub presence_penalty: Option<f32>,
+    #[serde(default)]
+    pub seed: Option<u64>,
+}
+
+impl OllamaChatRequest {
+    /// The runtime request, with unset options taken from `defaults`
+    fn into_chat_request(self, defaults: &InferenceConfig, stream: bool) -> ChatRequest {
+        let options = self.options.unwrap_or_default();
+        ChatRequest {
+            messages: self
+                .messages
+                .into_iter()
+                .map(|m| ChatMessage {
+                    role: m.role,
+                    content: m.content,
+                })
+                .collect(),
+            max_tokens: options.num_predict.unwrap_or(defaults.max_tokens),
+            temperature: options.temperature.unwrap_or(defaults.temperature),
+            top_p: options.top_p.unwrap_or(defaults.top_p),
+            top_k: options.top_k.unwrap_or(0),
+            min_p: options.min_p.unwrap_or(0.0),
+            repetition_penalty: options.repeat_penalty.unwrap_or(1.0),
+            frequency_penalty: options.frequency_penalty.unwrap_or(0.0),
+            presence_penalty: options.presence_penalty.unwrap_or(0.0),
+            logit_bias: Default::default(),
+            seed: options.seed,
+            stream,
+        }
+    }
 }
 
 #[derive(Serialize)]
@@ -52,15 +102,24 @@ pub struct OllamaChatResponse {
     pub message: OllamaChatMessageOutput,
     pub done: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub done_reason: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub total_duration: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt_eval_duration: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub eval_count: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub eval_duration: Option<u64>,
 }
 
 #[derive(Serialize)]
 pub struct OllamaChatMessageOutput {
     pub role: String,
     pub content: String,
+    /// With `return_token_ids`: the tokens `content` was decoded from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_ids: Option<Vec<u32>>,
 }
 
 pub async fn chat(
@@ -71,7 +130,7 @@ pub async fn chat(
     if let Err(e) = state.load_model(&request.model).await {
         tracing::error!("Failed to load model {}: {}", request.model, e);
         return (
-            axum::http::StatusCode::BAD_REQUEST,
+            error_status(&e),
             Json(serde_json::json!({
                 "error": format!("Failed to load model '{}': {}", request.model, e)
             })),
@@ -79,6 +138,10 @@ pub async fn chat(
             .into_response();
     }
 
+    if request.debug_prompt {
+        return debug_prompt(state, request).await.into_response();
+    }
+
     let stream = request.stream.unwrap_or(true); // Ollama defaults to streaming
 
     if stream {
@@ -88,117 +151,188 @@ pub async fn chat(
     }
 }
 
-async fn chat_non_stream(
-    state: Arc<AppState>,
-    request: OllamaChatRequest,
-) -> Json<OllamaChatResponse> {
+async fn debug_prompt(state: Arc<AppState>, request: OllamaChatRequest) -> Json<serde_json::Value> {
+    let messages: Vec<ChatMessage> = request
+        .messages
+        .into_iter()
+        .map(|m| ChatMessage {
+            role: m.role,
+            content: m.content,
+        })
+        .collect();
+
     let runtime = state.runtime.read().await;
+    Json(serde_json::json!({
+        "model": request.model,
+        "created_at": chrono::Utc::now().to_rfc3339(),
+        "prompt": runtime.render_prompt(&messages),
+        "done": true,
+    }))
+}
 
-    let options = request.options.unwrap_or_default();
-    let chat_request = ChatRequest {
-        messages: request
-            .messages
-            .into_iter()
-            .map(|m| ChatMessage {
-                role: m.role,
-                content: m.content,
-            })
-            .collect(),
-        max_tokens: options.num_predict.unwrap_or(2048),
-        temperature: options.temperature.unwrap_or(0.7),
-        stream: false,
+async fn chat_non_stream(state: Arc<AppState>, request: OllamaChatRequest) -> Response {
+    let model = request.model.clone();
+    let defaults = state.request_defaults(&model).await;
+    let chat_request = request.into_chat_request(&defaults, false);
+    let _permit = match state.reserve_tokens(&chat_request).await {
+        Ok(permit) => permit,
+        Err(e) => return error_response(&e),
     };
+    let transcript = state.start_transcript("/api/chat", &model, &chat_request);
 
+    let runtime = state.runtime.read().await;
     match runtime.chat(chat_request).await {
-        Ok(response) => Json(OllamaChatResponse {
-            model: request.model,
-            created_at: chrono::Utc::now().to_rfc3339(),
-            message: OllamaChatMessageOutput {
-                role: "assistant".to_string(),
-                content: response.content,
-            },
-            done: true,
-            total_duration: None,
-            eval_count: Some(response.tokens_used),
-        }),
-        Err(e) => {
-            tracing::error!("Chat error: {}", e);
+        Ok(response) => {
+            if let Some(transcript) = transcript {
+                transcript.finish(
+                    response.content.clone(),
+                    response.tokens_used,
+                    Some(response.finish_reason.clone()),
+                );
+            }
             Json(OllamaChatResponse {
-                model: request.model,
+                model,
                 created_at: chrono::Utc::now().to_rfc3339(),
                 message: OllamaChatMessageOutput {
                     role: "assistant".to_string(),
-                    content: format!("Error: {}", e),
+                    content: response.content,
+                    token_ids: None,
                 },
                 done: true,
-                total_duration: None,
-                eval_count: None,
+                done_reason: Some(response.finish_reason),
+                // Ollama reports durations in nanoseconds
+                total_duration: Some(ms_to_ns(response.generation_ms)),
+                prompt_eval_duration: Some(ms_to_ns(response.prefill_ms)),
+                eval_count: Some(response.tokens_used),
+                eval_duration: Some(ms_to_ns(
+                    response.generation_ms.saturating_sub(response.prefill_ms),
+                )),
             })
+            .into_response()
         }
+        // A single `{"error": ...}` object, never a reply that looks like
+        // model output
+        Err(e) => error_response(&e),
     }
 }
 
-async fn chat_stream(
-    state: Arc<AppState>,
-    request: OllamaChatRequest,
-) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+/// Ollama-style `{"error": ...}` with the matching status code
+fn error_response(error: &anyhow::Error) -> Response {
+    tracing::error!("Chat error: {}", error);
+    (
+        error_status(error),
+        Json(serde_json::json!({ "error": error.to_string() })),
+    )
+        .into_response()
+}
+
+fn ms_to_ns(ms: u64) -> u64 {
+    ms.saturating_mul(1_000_000)
+}
+
+async fn chat_stream(state: Arc<AppState>, request: OllamaChatRequest) -> Response {
     let model = request.model.clone();
-    let runtime = state.runtime.clone();
+    let return_token_ids = request.return_token_ids;
+
+    let defaults = state.request_defaults(&request.model).await;
+    let mut throttle = Throttle::new(state.config.read().await.daemon.max_tokens_per_second);
+    let mut keep_alive = keep_alive_interval(&state.config.read().await.daemon);
+    let chat_request = request.into_chat_request(&defaults, true);
+    // Held until the stream ends
+    let permit = match state.reserve_tokens(&chat_request).await {
+        Ok(permit) => permit,
+        Err(e) => return error_response(&e),
+    };
+    let transcript = state.start_transcript("/api/chat", &model, &chat_request);
+
+    // Start generation before responding so request errors get a proper status
+    let start = Instant::now();
+    let rx = {
+        let runtime = state.runtime.read().await;
+        runtime.chat_stream(chat_request).await
+    };
+    let mut rx = match rx {
+        Ok(rx) => rx,
+        Err(e) => return error_response(&e),
+    };
 
     let stream = async_stream::stream! {
-        let options = request.options.unwrap_or_default();
-        let chat_request = ChatRequest {
-            messages: request
-                .messages
-                .into_iter()
-                .map(|m| ChatMessage {
-                    role: m.role,
-                    content: m.content,
-                })
-                .collect(),
-            max_tokens: options.num_predict.unwrap_or(2048),
-            temperature: options.temperature.unwrap_or(0.7),
-            stream: true,
-        };
+        let _permit = permit;
+        let mut output = String::new();
+        let mut tokens = 0;
+        let mut finish_reason = None;
+        // The prompt is evaluated before the first token arrives
+        let mut prefill = None;
+
+        loop {
+            let token = match recv_or_keep_alive(&mut rx, keep_alive).await {
+                Ok(Some(token)) => token,
+                Ok(None) => break,
+                Err(_) => {
+                    yield Ok(keep_alive_event());
+                    continue;
+                }
+            };
+            // The prompt has been evaluated; tokens keep the stream alive now
+            keep_alive = None;
+
+            // Generation failed partway: like Ollama, an error object ends
+            // the stream
+            if let Some(error) = token.error {
+                tracing::error!("Stream error: {}", error);
+                finish_reason = token.finish_reason;
+                let error = serde_json::json!({ "error": format!("Generation error: {}", error) });
+                yield Ok::<_, Infallible>(Event::default().data(error.to_string()));
+                break;
+            }
 
-        let runtime_guard = runtime.read().await;
-        match runtime_guard.chat_stream(chat_request).await {
-            Ok(mut rx) => {
-                while let Some(token) = rx.recv().await {
-                    let chunk = OllamaChatResponse {
-                        model: model.clone(),
-                        created_at: chrono::Utc::now().to_rfc3339(),
-                        message: OllamaChatMessageOutput {
-                            role: "assistant".to_string(),
-                            content: token.content,
-                        },
-                        done: token.finish_reason.is_some(),
-                        total_duration: None,
-                        eval_count: None,
-                    };
-                    // Ollama uses newline-delimited JSON, not SSE
-                    yield Ok(Event::default().data(serde_json::to_string(&chunk).unwrap()));
+            if !token.content.is_empty() {
+                throttle.wait().await;
+                prefill.get_or_insert_with(|| start.elapsed());
+                if transcript.is_some() {
+                    output.push_str(&token.content);
                 }
             }
-            Err(e) => {
-                tracing::error!("Stream error: {}", e);
-                let error_chunk = OllamaChatResponse {
-                    model: model.clone(),
-                    created_at: chrono::Utc::now().to_rfc3339(),
-                    message: OllamaChatMessageOutput {
-                        role: "assistant".to_string(),
-                        content: format!("Error: {}", e),
-                    },
-                    done: true,
-                    total_duration: None,
-                    eval_count: None,
-                };
-                yield Ok(Event::default().data(serde_json::to_string(&error_chunk).unwrap()));
+            // A chunk can hold several tokens (e.g. a multi-byte character)
+            tokens += token.token_ids.len() as u32;
+            finish_reason.clone_from(&token.finish_reason);
+
+            let done = token.finish_reason.is_some();
+            let mut chunk = OllamaChatResponse {
+                model: model.clone(),
+                created_at: chrono::Utc::now().to_rfc3339(),
+                message: OllamaChatMessageOutput {
+                    role: "assistant".to_string(),
+                    content: token.content,
+                    token_ids: (return_token_ids && !token.token_ids.is_empty())
+                        .then_some(token.token_ids),
+                },
+                done,
+                done_reason: token.finish_reason,
+                total_duration: None,
+                prompt_eval_duration: None,
+                eval_count: None,
+                eval_duration: None,
+            };
+            // Like Ollama, the final chunk carries the request's statistics
+            if done {
+                let total = start.elapsed();
+                let prefill = prefill.unwrap_or(total);
+                chunk.total_duration = Some(total.as_nanos() as u64);
+                chunk.prompt_eval_duration = Some(prefill.as_nanos() as u64);
+                chunk.eval_count = Some(tokens);
+                chunk.eval_duration = Some(total.saturating_sub(prefill).as_nanos() as u64);
             }
+            // Ollama uses newline-delimited JSON, not SSE
+            yield Ok::<_, Infallible>(Event::default().data(serde_json::to_string(&chunk).unwrap()));
+        }
+
+        if let Some(transcript) = transcript {
+            transcript.finish(output, tokens, finish_reason);
         }
     };
 
-    Sse::new(stream)
+    Sse::new(stream).into_response()
 }
 
 // ============================================================================
@@ -208,11 +342,20 @@ async fn chat_stream(
 #[derive(Debug, Deserialize)]
 pub struct OllamaGenerateRequest {
     pub model: String,
+    #[serde(default)]
     pub prompt: String,
+    /// How long to keep the model loaded; `0` unloads it. Either a number of
+    /// seconds or a duration string such as "5m".
+    #[serde(default)]
+    pub keep_alive: Option<serde_json::Value>,
     #[serde(default)]
     pub stream: Option<bool>,
     #[serde(default)]
     pub options: Option<OllamaOptions>,
+    #[serde(default)]
+    pub debug_prompt: bool,
+    #[serde(default)]
+    pub return_token_ids: bool,
 }
 
 #[derive(Serialize)]
@@ -224,13 +367,22 @@ pub struct OllamaGenerateResponse {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub total_duration: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt_eval_duration: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub eval_count: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub eval_duration: Option<u64>,
 }
 
 pub async fn generate(
     State(state): State<Arc<AppState>>,
     Json(request): Json<OllamaGenerateRequest>,
 ) -> Response {
+    // An empty prompt only loads (or with keep_alive: 0, unloads) the model
+    if request.prompt.is_empty() {
+        return load_or_unload(state, request).await;
+    }
+
     // Convert generate request to chat request (single user message)
     let chat_request = OllamaChatRequest {
         model: request.model,
@@ -240,11 +392,61 @@ pub async fn generate(
         }],
         stream: request.stream,
         options: request.options,
+        debug_prompt: request.debug_prompt,
+        return_token_ids: request.return_token_ids,
     };
 
     chat(State(state), Json(chat_request)).await
 }
 
+async fn load_or_unload(state: Arc<AppState>, request: OllamaGenerateRequest) -> Response {
+    let unload = request.keep_alive.as_ref().is_some_and(is_zero_duration);
+
+    let (result, done_reason) = if unload {
+        // Only unload if the requested model is the one in memory
+        let result = if state.get_current_model().await.as_deref() == Some(request.model.as_str()) {
+            state.unload_model().await
+        } else {
+            Ok(())
+        };
+        (result, "unload")
+    } else {
+        (state.load_model(&request.model).await, "load")
+    };
+
+    if let Err(e) = result {
+        tracing::error!("Failed to {} model {}: {}", done_reason, request.model, e);
+        return (
+            error_status(&e),
+            Json(serde_json::json!({
+                "error": format!("Failed to {} model '{}': {}", done_reason, request.model, e)
+            })),
+        )
+            .into_response();
+    }
+
+    Json(serde_json::json!({
+        "model": request.model,
+        "created_at": chrono::Utc::now().to_rfc3339(),
+        "response": "",
+        "done": true,
+        "done_reason": done_reason,
+    }))
+    .into_response()
+}
+
+/// Whether an Ollama `keep_alive` value means "unload now"
+fn is_zero_duration(value: &serde_json::Value) -> bool {
+    match value {
+        serde_json::Value::Number(n) => n.as_f64() == Some(0.0),
+        serde_json::Value::String(s) => {
+            let digits = s.trim().trim_end_matches(|c: char| c.is_ascii_alphabetic());
+            digits.parse::<f64>().is_ok_and(|n| n == 0.0)
+        }
+        _ => false,
+    }
+}
+
 // ============================================================================
 // GET /api/tags - List local models (Ollama format)
 // ============================================================================
@@ -293,6 +495,54 @@ pub async fn tags(State(state): State<Arc<AppState>>) -> Json<OllamaTagsResponse
     Json(OllamaTagsResponse { models })
 }
 
+// ============================================================================
+// GET /api/ps - List loaded models
+// ============================================================================
+
+#[derive(Serialize)]
+pub struct OllamaPsResponse {
+    pub models: Vec<OllamaRunningModel>,
+}
+
+#[derive(Serialize)]
+pub struct OllamaRunningModel {
+    pub name: String,
+    pub model: String,
+    pub size: u64,
+    /// Bytes of the model held in GPU memory; the weights' size on disk
+    /// when the model runs on a GPU, 0 on the CPU
+    pub size_vram: u64,
+    /// ohmygpu extension: the device the model runs on (e.g. "cuda:0")
+    pub device: String,
+}
+
+pub async fn ps(State(state): State<Arc<AppState>>) -> Json<OllamaPsResponse> {
+    let info = state.runtime.read().await.model_info();
+    let Some(info) = info else {
+        return Json(OllamaPsResponse { models: Vec::new() });
+    };
+
+    let name = state.get_current_model().await.unwrap_or(info.name);
+    let size = state
+        .registry
+        .read()
+        .await
+        .get(&name)
+        .map(|m| m.size_bytes)
+        .unwrap_or(0);
+    let size_vram = if info.device == "cpu" { 0 } else { size };
+
+    Json(OllamaPsResponse {
+        models: vec![OllamaRunningModel {
+            model: name.clone(),
+            name,
+            size,
+            size_vram,
+            device: info.device,
+        }],
+    })
+}
+
 // ============================================================================
 // GET /api/version - Version info
 // ============================================================================
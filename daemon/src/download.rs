@@ -0,0 +1,107 @@
+//! Downloading models on first request (`daemon.auto_download`)
+//!
+//! Off by default: a typo in a request could otherwise start a download of
+//! many gigabytes on the server. Before downloading, the repo must be a
+//! model type the runtime serves, have safetensors weights, and fit in the
+//! free disk space.
+//!
+//! The download runs in its own task, so a client that times out or
+//! disconnects doesn't abort it halfway; the next request for the model
+//! finds it registered.
+
+use anyhow::Result;
+use ohmygpu_core::disk::free_space_bytes;
+use ohmygpu_core::downloaders::huggingface::{select_files, HuggingFaceDownloader};
+use ohmygpu_core::{ModelInfo, ModelRegistry, ModelType};
+use std::sync::Arc;
+
+use crate::state::{AppState, LoadError, LoadStatus};
+
+/// Download `repo_id` from HuggingFace and register it in a background
+/// task, reporting progress through the daemon's load status
+pub async fn download_model(state: Arc<AppState>, repo_id: &str) -> Result<ModelInfo> {
+    let repo_id = repo_id.to_string();
+    tokio::spawn(async move {
+        let result = download(&state, &repo_id).await;
+        state.set_load_status(match &result {
+            Ok(_) => LoadStatus::Downloaded { model: repo_id },
+            Err(e) => LoadStatus::Failed {
+                model: repo_id,
+                error: format!("{:#}", e),
+            },
+        });
+        result
+    })
+    .await?
+}
+
+async fn download(state: &AppState, repo_id: &str) -> Result<ModelInfo> {
+    let is_repo_id = matches!(
+        repo_id.split_once('/'),
+        Some((owner, name)) if !owner.is_empty() && !name.is_empty() && !name.contains('/')
+    );
+    if !is_repo_id {
+        return Err(LoadError::NotARepoId(repo_id.to_string()).into());
+    }
+
+    // One download at a time; a request that waited here may find its model
+    // already downloaded
+    let _download = state.download_lock.lock().await;
+    let registry = ModelRegistry::load_from(state.registry_path.clone())?;
+    if let Some(info) = registry.get(&repo_id.replace('/', "--")) {
+        return Ok(info.clone());
+    }
+
+    let load_status = state.load_status.clone();
+    let model = repo_id.to_string();
+    let downloader = HuggingFaceDownloader::new().with_progress(Arc::new(move |progress| {
+        *load_status.lock().unwrap() = LoadStatus::Downloading {
+            model: model.clone(),
+            progress: Some(progress.clone()),
+        };
+    }));
+    state.set_load_status(LoadStatus::Downloading {
+        model: repo_id.to_string(),
+        progress: None,
+    });
+
+    let hf_info = downloader.get_model_info(repo_id).await?;
+    let model_type = hf_info.model_type();
+    if !matches!(model_type, ModelType::LLM | ModelType::Embedding) {
+        return Err(LoadError::WrongType {
+            model: repo_id.to_string(),
+            model_type: model_type.as_str(),
+            reason: "which the daemon can't serve",
+        }
+        .into());
+    }
+    let files = select_files(&hf_info, None);
+    if !files.iter().any(|file| file.ends_with(".safetensors")) {
+        return Err(LoadError::NoSafetensors(repo_id.to_string()).into());
+    }
+    let models_dir = ModelRegistry::models_dir()?;
+    if let (Some(size), Some(free)) = (hf_info.download_size(&files), free_space_bytes(&models_dir))
+    {
+        if size > free {
+            anyhow::bail!(
+                "'{}' needs {:.1} GB, but only {:.1} GB is free in {}",
+                repo_id,
+                size as f64 / 1_073_741_824.0,
+                free as f64 / 1_073_741_824.0,
+                models_dir.display()
+            );
+        }
+    }
+
+    tracing::info!(
+        "Downloading {} from HuggingFace (daemon.auto_download)",
+        repo_id
+    );
+    let info = downloader.download_with_info(&hf_info, None).await?;
+
+    let mut registry = ModelRegistry::load_from(state.registry_path.clone())?;
+    registry.add(info.clone())?;
+    *state.registry.write().await = registry;
+    tracing::info!("Downloaded {} as '{}'", repo_id, info.name);
+    Ok(info)
+}
@@ -0,0 +1,151 @@
+//! Generated images served over HTTP at `GET /images/<id>.png`, deleted
+//! after `daemon.image_retention_secs`
+
+use anyhow::{Context, Result};
+use axum::{
+    extract::{Path as UrlPath, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use ohmygpu_core::random::random_u64;
+use ohmygpu_core::Config;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use crate::state::AppState;
+
+/// How often expired images are looked for, at most
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60);
+
+pub struct ImageStore {
+    dir: PathBuf,
+}
+
+impl ImageStore {
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let dir = match &config.daemon.image_dir {
+            Some(dir) => dir.clone(),
+            None => std::env::temp_dir().join("ohmygpu-images"),
+        };
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create image directory {:?}", dir))?;
+        Ok(Self { dir })
+    }
+
+    /// Store a PNG and return its id. Ids are random, so a URL cannot be
+    /// guessed from another one.
+    pub fn save_png(&self, png: &[u8]) -> Result<String> {
+        let id = random_id();
+        // Written under a temporary name so a half-written file is never served
+        let tmp = self.dir.join(format!(".{}.tmp", id));
+        std::fs::write(&tmp, png)?;
+        std::fs::rename(&tmp, self.dir.join(format!("{}.png", id)))?;
+        Ok(id)
+    }
+
+    /// The file for `id`, or `None` if `id` is not a well-formed image id.
+    /// Only ids from [`Self::save_png`] are accepted, so a request can never
+    /// reach outside the image directory.
+    pub fn path(&self, id: &str) -> Option<PathBuf> {
+        let valid = id.len() == 32 && id.bytes().all(|b| b.is_ascii_hexdigit());
+        valid.then(|| self.dir.join(format!("{}.png", id)))
+    }
+
+    /// The URL of image `id`, under `daemon.image_base_url`
+    pub fn url(config: &Config, id: &str) -> String {
+        let base = match &config.daemon.image_base_url {
+            Some(base) => base.trim_end_matches('/').to_string(),
+            None => {
+                // A daemon listening on all interfaces is still reached via loopback
+                let host = match config.daemon.host.as_str() {
+                    "0.0.0.0" | "::" => "127.0.0.1",
+                    host => host,
+                };
+                format!("http://{}:{}", host, config.daemon.port)
+            }
+        };
+        format!("{}/images/{}.png", base, id)
+    }
+
+    /// Delete images older than `retention`; returns how many were removed
+    pub fn remove_expired(&self, retention: Duration) -> Result<usize> {
+        let now = SystemTime::now();
+        let mut removed = 0;
+        for entry in std::fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if !is_image_file(&path) {
+                continue;
+            }
+            let age = std::fs::metadata(&path)
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|modified| now.duration_since(modified).ok());
+            if age.is_some_and(|age| age > retention) && std::fs::remove_file(&path).is_ok() {
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+}
+
+/// Our own files only: `<id>.png` and leftover `.<id>.tmp`
+fn is_image_file(path: &Path) -> bool {
+    let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+        return false;
+    };
+    let id = name
+        .strip_suffix(".png")
+        .or_else(|| name.strip_prefix('.').and_then(|n| n.strip_suffix(".tmp")));
+    id.is_some_and(|id| id.len() == 32 && id.bytes().all(|b| b.is_ascii_hexdigit()))
+}
+
+fn random_id() -> String {
+    format!("{:016x}{:016x}", random_u64(), random_u64())
+}
+
+/// Delete expired images in the background for as long as the daemon runs
+pub fn spawn_cleanup(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        loop {
+            let retention = state.config.read().await.daemon.image_retention_secs;
+            let retention = Duration::from_secs(retention);
+            let images = state.clone();
+            let result =
+                tokio::task::spawn_blocking(move || images.images.remove_expired(retention)).await;
+            match result {
+                Ok(Ok(0)) => {}
+                Ok(Ok(removed)) => tracing::debug!("Removed {} expired image(s)", removed),
+                Ok(Err(e)) => tracing::warn!("Image cleanup failed: {}", e),
+                Err(e) => tracing::warn!("Image cleanup failed: {}", e),
+            }
+            tokio::time::sleep(retention.min(CLEANUP_INTERVAL)).await;
+        }
+    });
+}
+
+/// GET /images/{id}.png
+pub async fn serve_image(
+    State(state): State<Arc<AppState>>,
+    UrlPath(file): UrlPath<String>,
+) -> Response {
+    let path = file
+        .strip_suffix(".png")
+        .and_then(|id| state.images.path(id));
+    let Some(path) = path else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    match tokio::fs::read(&path).await {
+        Ok(bytes) => (
+            [
+                (header::CONTENT_TYPE, "image/png"),
+                // An id always names the same image
+                (header::CACHE_CONTROL, "public, max-age=31536000, immutable"),
+            ],
+            bytes,
+        )
+            .into_response(),
+        Err(_) => StatusCode::NOT_FOUND.into_response(),
+    }
+}
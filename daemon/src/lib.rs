@@ -6,7 +6,13 @@
 //! - Handles concurrent requests
 
 pub mod api;
+pub mod download;
+pub mod images;
+pub mod limits;
+pub mod logging;
 pub mod server;
 pub mod state;
+pub mod transcript;
+pub mod watch;
 
-pub use server::run_server;
+pub use server::{run_server, ConfigOverrides, ServerOptions};
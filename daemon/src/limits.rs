@@ -0,0 +1,273 @@
+//! Limits for shared deployments: a per-client cap on concurrent
+//! generation requests, a cap on the tokens of all chat requests being
+//! processed, and a tokens-per-second cap on streams
+
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use futures_util::StreamExt;
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::time::Instant;
+
+use crate::state::AppState;
+
+/// Generation requests in flight, per client
+#[derive(Default)]
+pub struct ClientLimiter {
+    active: Mutex<HashMap<String, u32>>,
+}
+
+impl ClientLimiter {
+    /// Count a request against `client`, or `None` if it already has
+    /// `limit` requests in flight
+    pub fn try_acquire(self: &Arc<Self>, client: String, limit: u32) -> Option<ClientPermit> {
+        let mut active = self.active.lock().unwrap();
+        let count = active.entry(client.clone()).or_default();
+        if *count >= limit {
+            return None;
+        }
+        *count += 1;
+        Some(ClientPermit {
+            limiter: self.clone(),
+            client,
+        })
+    }
+}
+
+/// Releases its request slot when dropped
+pub struct ClientPermit {
+    limiter: Arc<ClientLimiter>,
+    client: String,
+}
+
+impl Drop for ClientPermit {
+    fn drop(&mut self) {
+        let mut active = self.limiter.active.lock().unwrap();
+        if let Some(count) = active.get_mut(&self.client) {
+            *count -= 1;
+            if *count == 0 {
+                active.remove(&self.client);
+            }
+        }
+    }
+}
+
+/// Middleware enforcing `daemon.max_concurrent_requests_per_client`.
+///
+/// Clients are identified by their bearer token, falling back to the peer
+/// IP address. The slot is held until the response body has been sent, so
+/// a stream counts for as long as it runs.
+pub async fn limit_concurrency(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let limit = state
+        .config
+        .read()
+        .await
+        .daemon
+        .max_concurrent_requests_per_client;
+    let Some(limit) = limit else {
+        return next.run(request).await;
+    };
+
+    let client = client_key(&request);
+    let Some(permit) = state.client_limiter.try_acquire(client, limit) else {
+        let message = format!(
+            "Too many concurrent requests (limit {} per client); retry when one finishes",
+            limit
+        );
+        tracing::warn!("{}", message);
+        let body = if request.uri().path().starts_with("/api/") {
+            serde_json::json!({ "error": message })
+        } else {
+            serde_json::json!({
+                "error": { "message": message, "type": "rate_limit_error" }
+            })
+        };
+        return (StatusCode::TOO_MANY_REQUESTS, Json(body)).into_response();
+    };
+
+    let (parts, body) = next.run(request).await.into_parts();
+    let body = body.into_data_stream().map(move |chunk| {
+        let _held = &permit;
+        chunk
+    });
+    Response::from_parts(parts, Body::from_stream(body))
+}
+
+/// The peer's IP address. API keys are not validated, so keying on them
+/// would let a client escape the cap by sending a fresh one per request.
+fn client_key(request: &Request) -> String {
+    request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip().to_string())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Tokens reserved by the chat requests being processed, for
+/// `daemon.max_batch_total_tokens`. Waiting requests are admitted in
+/// arrival order, so a large request can't be starved by smaller ones that
+/// keep fitting in ahead of it.
+#[derive(Default)]
+pub struct TokenBudget {
+    state: Mutex<BudgetState>,
+    /// Signalled whenever the head of the queue may be able to go
+    changed: Notify,
+}
+
+#[derive(Default)]
+struct BudgetState {
+    usage: BudgetUsage,
+    next_ticket: u64,
+    /// Tickets of the waiting requests, first in line at the front
+    waiting: VecDeque<u64>,
+}
+
+/// Snapshot of a [`TokenBudget`], reported at `/metrics`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BudgetUsage {
+    /// Tokens reserved by the requests being processed
+    pub tokens: u64,
+    /// Requests being processed
+    pub active: u32,
+    /// Requests waiting for tokens to be released
+    pub queued: u32,
+}
+
+impl TokenBudget {
+    pub fn usage(&self) -> BudgetUsage {
+        self.state.lock().unwrap().usage
+    }
+
+    /// Reserve `tokens`, waiting while they would take the total past
+    /// `limit` or while earlier requests are waiting. A request larger than
+    /// the limit runs once nothing else is.
+    pub async fn acquire(self: &Arc<Self>, tokens: u64, limit: Option<u64>) -> TokenPermit {
+        let mut queued: Option<QueuedRequest> = None;
+        loop {
+            // Registered before checking, so a change in between wakes us
+            let changed = self.changed.notified();
+            tokio::pin!(changed);
+            changed.as_mut().enable();
+
+            {
+                let mut state = self.state.lock().unwrap();
+                let first = match &queued {
+                    Some(queued) => state.waiting.front() == Some(&queued.ticket),
+                    None => state.waiting.is_empty(),
+                };
+                let usage = &mut state.usage;
+                let fits = limit.is_none_or(|limit| usage.tokens + tokens <= limit);
+                if first && (fits || usage.active == 0) {
+                    usage.tokens += tokens;
+                    usage.active += 1;
+                    if queued.is_some() {
+                        usage.queued -= 1;
+                        state.waiting.pop_front();
+                    }
+                    drop(state);
+                    // Disarmed: it has left the queue
+                    drop(queued);
+                    // The next in line may fit as well
+                    self.changed.notify_waiters();
+                    return TokenPermit {
+                        budget: self.clone(),
+                        tokens,
+                    };
+                }
+                if queued.is_none() {
+                    let ticket = state.next_ticket;
+                    state.next_ticket += 1;
+                    state.waiting.push_back(ticket);
+                    state.usage.queued += 1;
+                    tracing::info!(
+                        "Request for {} tokens queued ({} of {} in use, {} waiting)",
+                        tokens,
+                        state.usage.tokens,
+                        limit.unwrap_or_default(),
+                        state.usage.queued
+                    );
+                    queued = Some(QueuedRequest {
+                        budget: self.clone(),
+                        ticket,
+                    });
+                }
+            }
+            changed.await;
+        }
+    }
+}
+
+/// Releases its tokens when dropped
+pub struct TokenPermit {
+    budget: Arc<TokenBudget>,
+    tokens: u64,
+}
+
+impl Drop for TokenPermit {
+    fn drop(&mut self) {
+        let mut state = self.budget.state.lock().unwrap();
+        state.usage.tokens -= self.tokens;
+        state.usage.active -= 1;
+        drop(state);
+        self.budget.changed.notify_waiters();
+    }
+}
+
+/// A request's place in the queue, given up if the client goes away
+/// before it is admitted
+struct QueuedRequest {
+    budget: Arc<TokenBudget>,
+    ticket: u64,
+}
+
+impl Drop for QueuedRequest {
+    fn drop(&mut self) {
+        let mut state = self.budget.state.lock().unwrap();
+        let Some(position) = state.waiting.iter().position(|&t| t == self.ticket) else {
+            return;
+        };
+        state.waiting.remove(position);
+        state.usage.queued -= 1;
+        drop(state);
+        // The request behind it may be first now
+        self.budget.changed.notify_waiters();
+    }
+}
+
+/// Paces a token stream to at most `daemon.max_tokens_per_second`
+pub struct Throttle {
+    interval: Option<Duration>,
+    next: Instant,
+}
+
+impl Throttle {
+    pub fn new(tokens_per_second: Option<f64>) -> Self {
+        Self {
+            interval: tokens_per_second
+                .filter(|rate| *rate > 0.0 && rate.is_finite())
+                .map(|rate| Duration::from_secs_f64(1.0 / rate)),
+            next: Instant::now(),
+        }
+    }
+
+    /// Wait until the next token may be sent
+    pub async fn wait(&mut self) {
+        if let Some(interval) = self.interval {
+            tokio::time::sleep_until(self.next).await;
+            self.next = self.next.max(Instant::now()) + interval;
+        }
+    }
+}
@@ -0,0 +1,29 @@
+//! The global log filter, kept reloadable so `daemon.log_level` can change
+//! while the daemon runs
+
+use anyhow::Result;
+use tracing_subscriber::{prelude::*, reload, EnvFilter, Registry};
+
+/// Changes the level of the subscriber installed by [`init`]
+pub type LogFilter = reload::Handle<EnvFilter, Registry>;
+
+/// Install the global subscriber at `info`, with RUST_LOG directives on top
+pub fn init() -> LogFilter {
+    let (filter, handle) = reload::Layer::new(filter(tracing::Level::INFO));
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer())
+        .init();
+    handle
+}
+
+/// Log at `level` (error, warn, info, debug or trace) from now on
+pub fn set_level(log_filter: &LogFilter, level: &str) -> Result<()> {
+    let level: tracing::Level = level.parse()?;
+    log_filter.reload(filter(level))?;
+    Ok(())
+}
+
+fn filter(level: tracing::Level) -> EnvFilter {
+    EnvFilter::from_default_env().add_directive(level.into())
+}
@@ -1,19 +1,78 @@
 use anyhow::Result;
+use axum::http::{request::Parts, HeaderValue};
+use ohmygpu_core::Config;
 use std::net::SocketAddr;
 use std::sync::Arc;
-use tower_http::cors::{Any, CorsLayer};
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
 use tower_http::trace::TraceLayer;
 
 use crate::api;
+use crate::images;
+use crate::logging::{self, LogFilter};
 use crate::state::AppState;
+use crate::watch;
 
-pub async fn run_server(addr: SocketAddr) -> Result<()> {
-    let state = Arc::new(AppState::new()?);
+/// Settings given on the command line, which take precedence over config.toml
+#[derive(Debug, Clone, Default)]
+pub struct ConfigOverrides {
+    pub gpu_id: Option<u32>,
+    pub chat_template: Option<String>,
+    pub context_length: Option<usize>,
+    pub dtype: Option<String>,
+    pub max_batch_total_tokens: Option<u64>,
+}
+
+impl ConfigOverrides {
+    pub fn apply(&self, config: &mut Config) {
+        if let Some(gpu_id) = self.gpu_id {
+            config.inference.gpu_id = gpu_id;
+        }
+        if let Some(template) = &self.chat_template {
+            config.inference.chat_template = Some(template.clone());
+        }
+        if let Some(context_length) = self.context_length {
+            config.inference.context_length = Some(context_length);
+        }
+        if let Some(dtype) = &self.dtype {
+            config.inference.dtype = Some(dtype.clone());
+        }
+        if let Some(limit) = self.max_batch_total_tokens {
+            config.daemon.max_batch_total_tokens = Some(limit);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ServerOptions {
+    /// Reload config.toml when it changes
+    pub watch_config: bool,
+    /// Re-applied on every reload
+    pub overrides: ConfigOverrides,
+    /// Allow any localhost origin, for front-end development
+    pub cors_dev: bool,
+    /// Set to `daemon.log_level` at startup and on every reload
+    pub log_filter: Option<LogFilter>,
+}
+
+pub async fn run_server(addr: SocketAddr, config: Config, options: ServerOptions) -> Result<()> {
+    // Built before the config moves into the state; changes need a restart
+    let cors = cors_layer(&config.daemon.cors_origins, options.cors_dev);
+    if let Some(log_filter) = &options.log_filter {
+        logging::set_level(log_filter, &config.daemon.log_level)?;
+    }
+    let state = Arc::new(AppState::new(config)?);
+    images::spawn_cleanup(state.clone());
 
-    let cors = CorsLayer::new()
-        .allow_origin(Any)
-        .allow_methods(Any)
-        .allow_headers(Any);
+    // Kept alive for as long as the server runs
+    let _watcher = if options.watch_config {
+        Some(watch::watch_config(
+            state.clone(),
+            options.overrides,
+            options.log_filter,
+        )?)
+    } else {
+        None
+    };
 
     let app = api::routes(state)
         .layer(cors)
@@ -22,7 +81,57 @@ pub async fn run_server(addr: SocketAddr) -> Result<()> {
     tracing::info!("Starting daemon on {}", addr);
 
     let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
+    // Peer addresses identify clients for the per-client request cap
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await?;
 
     Ok(())
 }
+
+/// CORS for `daemon.cors_origins`, plus every localhost origin with
+/// `cors_dev`
+fn cors_layer(origins: &[String], cors_dev: bool) -> CorsLayer {
+    let layer = CorsLayer::new().allow_methods(Any).allow_headers(Any);
+    if origins.iter().any(|origin| origin == "*") {
+        return layer.allow_origin(Any);
+    }
+
+    let allowed: Vec<HeaderValue> = origins
+        .iter()
+        .filter_map(|origin| match HeaderValue::from_str(origin) {
+            Ok(value) => Some(value),
+            Err(_) => {
+                tracing::warn!("Ignoring invalid CORS origin {:?}", origin);
+                None
+            }
+        })
+        .collect();
+    if !cors_dev {
+        return layer.allow_origin(allowed);
+    }
+
+    layer.allow_origin(AllowOrigin::predicate(
+        move |origin: &HeaderValue, _: &Parts| allowed.contains(origin) || is_localhost(origin),
+    ))
+}
+
+/// `http(s)://localhost`, `127.0.0.1` or `[::1]`, on any port
+fn is_localhost(origin: &HeaderValue) -> bool {
+    let Ok(origin) = origin.to_str() else {
+        return false;
+    };
+    let Some(host) = origin
+        .strip_prefix("http://")
+        .or_else(|| origin.strip_prefix("https://"))
+    else {
+        return false;
+    };
+    let host = match host.strip_prefix("[::1]") {
+        Some(port) => return port.is_empty() || port.starts_with(':'),
+        None => host.split(':').next().unwrap_or_default(),
+    };
+    host == "localhost" || host == "127.0.0.1"
+}
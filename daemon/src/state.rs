@@ -1,25 +1,211 @@
 use anyhow::Result;
-use ohmygpu_core::ModelRegistry;
-use ohmygpu_runtime_api::{Runtime, RuntimeConfig, RuntimeStatus};
+use candle_core::Device;
+use ohmygpu_core::config::InferenceConfig;
+use ohmygpu_core::downloaders::huggingface::DownloadProgress;
+use ohmygpu_core::{Config, ModelRegistry, ModelType};
+use ohmygpu_runtime_api::{ChatRequest, Runtime, RuntimeConfig, RuntimeError, RuntimeStatus};
 use ohmygpu_runtime_candle::CandleRuntime;
-use std::sync::Arc;
+use ohmygpu_runtime_diffusion::{detect_model_type, load_model, DiffusionModel};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
 use tokio::sync::RwLock;
 
+use crate::download;
+use crate::images::ImageStore;
+use crate::limits::{ClientLimiter, TokenBudget, TokenPermit};
+use crate::transcript::{Transcript, TranscriptLogger};
+
 pub struct AppState {
+    /// Replaced in place when `serve --watch` picks up a config change
+    pub config: RwLock<Config>,
     pub registry: Arc<RwLock<ModelRegistry>>,
-    pub runtime: Arc<RwLock<CandleRuntime>>,
+    /// The registry file, re-read when the CLI changes it
+    pub registry_path: PathBuf,
+    /// Modification time of the registry file when it was last read
+    registry_modified: Mutex<Option<SystemTime>>,
+    pub runtime: Arc<RwLock<Box<dyn Runtime>>>,
     pub current_model: Arc<RwLock<Option<String>>>,
+    pub transcripts: Option<TranscriptLogger>,
+    pub client_limiter: Arc<ClientLimiter>,
+    pub token_budget: Arc<TokenBudget>,
+    pub images: ImageStore,
+    /// What the last model load did, served at `/api/load-status`
+    pub load_status: Arc<Mutex<LoadStatus>>,
+    /// Held while a model downloads, so concurrent requests for the same
+    /// model download it once
+    pub download_lock: tokio::sync::Mutex<()>,
+    /// The diffusion pipeline behind `/v1/images/generations`, loaded on
+    /// first use. Locked for a whole generation, so images are generated one
+    /// at a time; the generating task holds the lock even after the client
+    /// goes away, until it has stopped.
+    pub image_pipeline: Arc<tokio::sync::Mutex<Option<ImagePipeline>>>,
+}
+
+/// A loaded diffusion pipeline and the registry name it was loaded as
+pub struct ImagePipeline {
+    pub model: String,
+    pub pipeline: Arc<dyn DiffusionModel>,
+}
+
+/// Progress of the most recent model load
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum LoadStatus {
+    /// No model has been requested yet
+    #[default]
+    Idle,
+    /// Fetching the model from HuggingFace (`daemon.auto_download`);
+    /// `progress` is unset until the first bytes arrive
+    Downloading {
+        model: String,
+        progress: Option<DownloadProgress>,
+    },
+    /// Downloaded and registered; it loads on the next request for it
+    Downloaded {
+        model: String,
+    },
+    Loading {
+        model: String,
+    },
+    Ready {
+        model: String,
+    },
+    Failed {
+        model: String,
+        error: String,
+    },
+}
+
+/// Load failures caused by the requested model rather than by the daemon, so
+/// the API can answer them as client errors
+#[derive(Debug, thiserror::Error)]
+pub enum LoadError {
+    #[error("Model '{0}' not found in registry")]
+    NotFound(String),
+    #[error("Model '{0}' not found in registry (pull it with `omg model pull`, or set daemon.auto_download to download models on first request)")]
+    NotDownloaded(String),
+    #[error("Model '{0}' not found in registry, and it is not a HuggingFace repo id (owner/name) to download")]
+    NotARepoId(String),
+    #[error("'{model}' is a {model_type} model, {reason}")]
+    WrongType {
+        model: String,
+        model_type: &'static str,
+        reason: &'static str,
+    },
+    #[error("'{0}' has no safetensors weights the daemon can load; pull a specific file with `omg model pull {0} --file <file>`")]
+    NoSafetensors(String),
 }
 
 impl AppState {
-    pub fn new() -> anyhow::Result<Self> {
+    pub fn new(config: Config) -> anyhow::Result<Self> {
+        Self::with_runtime(
+            config,
+            Config::registry_path()?,
+            Box::new(CandleRuntime::new()),
+        )
+    }
+
+    /// State serving models through `runtime`, with the model registry at
+    /// `registry_path`
+    pub fn with_runtime(
+        config: Config,
+        registry_path: PathBuf,
+        runtime: Box<dyn Runtime>,
+    ) -> anyhow::Result<Self> {
         Ok(Self {
-            registry: Arc::new(RwLock::new(ModelRegistry::load()?)),
-            runtime: Arc::new(RwLock::new(CandleRuntime::new())),
+            transcripts: TranscriptLogger::from_config(&config)?,
+            images: ImageStore::from_config(&config)?,
+            config: RwLock::new(config),
+            registry_modified: Mutex::new(modified_time(&registry_path)),
+            registry: Arc::new(RwLock::new(ModelRegistry::load_from(
+                registry_path.clone(),
+            )?)),
+            registry_path,
+            runtime: Arc::new(RwLock::new(runtime)),
             current_model: Arc::new(RwLock::new(None)),
+            client_limiter: Arc::new(ClientLimiter::default()),
+            token_budget: Arc::new(TokenBudget::default()),
+            load_status: Arc::new(Mutex::new(LoadStatus::Idle)),
+            download_lock: tokio::sync::Mutex::new(()),
+            image_pipeline: Arc::new(tokio::sync::Mutex::new(None)),
         })
     }
 
+    pub fn load_status(&self) -> LoadStatus {
+        self.load_status.lock().unwrap().clone()
+    }
+
+    pub fn set_load_status(&self, status: LoadStatus) {
+        *self.load_status.lock().unwrap() = status;
+    }
+
+    /// Start recording a request if transcripts are enabled
+    pub fn start_transcript(
+        &self,
+        endpoint: &'static str,
+        model: &str,
+        request: &ChatRequest,
+    ) -> Option<Transcript> {
+        self.transcripts
+            .as_ref()
+            .map(|logger| logger.start(endpoint, model, request))
+    }
+
+    /// Reserve the tokens `request` may use (prompt + `max_tokens`) against
+    /// `daemon.max_batch_total_tokens`, waiting until they fit. Processing
+    /// lasts until the permit is dropped.
+    pub async fn reserve_tokens(&self, request: &ChatRequest) -> Result<TokenPermit> {
+        let limit = self.config.read().await.daemon.max_batch_total_tokens;
+        // Without a limit nothing waits, so skip tokenizing the prompt and
+        // count `max_tokens` only
+        let prompt_tokens = match limit {
+            Some(_) => {
+                self.runtime
+                    .read()
+                    .await
+                    .count_prompt_tokens(&request.messages)
+                    .await?
+            }
+            None => 0,
+        };
+        let tokens = prompt_tokens as u64 + request.max_tokens as u64;
+        Ok(self.token_budget.acquire(tokens, limit).await)
+    }
+
+    /// Request defaults for `model`: the `[inference]` config with the
+    /// model's own sampling defaults (`omg model set-params`) on top
+    pub async fn request_defaults(&self, model: &str) -> InferenceConfig {
+        let mut defaults = self.config.read().await.inference.clone();
+
+        self.refresh_registry().await;
+        let registry = self.registry.read().await;
+        if let Some(sampling) = registry.get(model).map(|info| &info.sampling) {
+            defaults.temperature = sampling.temperature.unwrap_or(defaults.temperature);
+            defaults.top_p = sampling.top_p.unwrap_or(defaults.top_p);
+        }
+        defaults
+    }
+
+    /// Re-read the registry if its file changed since it was last read. The
+    /// CLI edits the file directly, so this picks up new parameters without
+    /// a restart.
+    async fn refresh_registry(&self) {
+        let modified = modified_time(&self.registry_path);
+        {
+            let mut seen = self.registry_modified.lock().unwrap();
+            if *seen == modified {
+                return;
+            }
+            *seen = modified;
+        }
+        match ModelRegistry::load_from(self.registry_path.clone()) {
+            Ok(fresh) => *self.registry.write().await = fresh,
+            Err(e) => tracing::warn!("Could not reload the model registry: {}", e),
+        }
+    }
+
     pub async fn is_model_loaded(&self) -> bool {
         let runtime = self.runtime.read().await;
         runtime.status() == RuntimeStatus::Ready
@@ -30,7 +216,10 @@ impl AppState {
     }
 
     /// Load a model by name. Returns Ok if model is already loaded or loads successfully.
-    pub async fn load_model(&self, model_name: &str) -> Result<()> {
+    ///
+    /// With `daemon.auto_download`, a HuggingFace repo id that isn't in the
+    /// registry is downloaded first.
+    pub async fn load_model(self: &Arc<Self>, model_name: &str) -> Result<()> {
         // Check if already loaded
         {
             let current = self.current_model.read().await;
@@ -43,16 +232,43 @@ impl AppState {
             }
         }
 
-        // Find model in registry
-        let model_path = {
-            let registry = self.registry.read().await;
-            let model_info = registry
-                .get(model_name)
-                .ok_or_else(|| anyhow::anyhow!("Model '{}' not found in registry", model_name))?;
-            model_info.path.clone()
+        let result = self.find_and_load(model_name).await;
+        self.set_load_status(match &result {
+            Ok(()) => LoadStatus::Ready {
+                model: model_name.to_string(),
+            },
+            Err(e) => LoadStatus::Failed {
+                model: model_name.to_string(),
+                error: format!("{:#}", e),
+            },
+        });
+        result
+    }
+
+    /// The registry entry for `model_name`, also under the name a
+    /// HuggingFace download gets (`owner--name`)
+    async fn find_model(&self, model_name: &str) -> Option<(PathBuf, ModelType)> {
+        let registry = self.registry.read().await;
+        registry
+            .get(model_name)
+            .or_else(|| registry.get(&model_name.replace('/', "--")))
+            .map(|info| (info.path.clone(), info.model_type.clone()))
+    }
+
+    async fn find_and_load(self: &Arc<Self>, model_name: &str) -> Result<()> {
+        let (model_path, model_type) = match self.find_model(model_name).await {
+            Some(found) => found,
+            None if self.config.read().await.daemon.auto_download => {
+                let info = download::download_model(self.clone(), model_name).await?;
+                (info.path, info.model_type)
+            }
+            None => return Err(LoadError::NotDownloaded(model_name.to_string()).into()),
         };
 
         tracing::info!("Loading model {} from {:?}", model_name, model_path);
+        self.set_load_status(LoadStatus::Loading {
+            model: model_name.to_string(),
+        });
 
         // Unload current model if any
         {
@@ -64,12 +280,19 @@ impl AppState {
 
         // Load the new model
         {
+            let inference = self.config.read().await.inference.clone();
             let mut runtime = self.runtime.write().await;
             let config = RuntimeConfig {
                 model_path,
-                gpu_id: Some(0),
+                gpu_id: Some(inference.gpu_id),
                 vram_budget_mb: None,
                 cpu_threads: None,
+                stream_buffer_size: Some(inference.stream_buffer_size),
+                chat_template: inference.chat_template,
+                model_type: Some(model_type),
+                dtype: inference.dtype,
+                model_name: Some(model_name.to_string()),
+                context_length: inference.context_length,
             };
             runtime.load(config).await?;
         }
@@ -84,6 +307,53 @@ impl AppState {
         Ok(())
     }
 
+    /// The diffusion pipeline for `model_name`, loading it into `slot` (the
+    /// locked [`Self::image_pipeline`]) unless it is already there
+    pub async fn image_pipeline(
+        &self,
+        slot: &mut Option<ImagePipeline>,
+        model_name: &str,
+    ) -> Result<Arc<dyn DiffusionModel>> {
+        if let Some(loaded) = slot.as_ref().filter(|loaded| loaded.model == model_name) {
+            return Ok(loaded.pipeline.clone());
+        }
+
+        let (model_path, model_type) = self
+            .find_model(model_name)
+            .await
+            .ok_or_else(|| LoadError::NotFound(model_name.to_string()))?;
+        if model_type != ModelType::ImageGeneration {
+            return Err(LoadError::WrongType {
+                model: model_name.to_string(),
+                model_type: model_type.as_str(),
+                reason: "not an image generation model",
+            }
+            .into());
+        }
+
+        // Free the previous pipeline before loading the next
+        *slot = None;
+        tracing::info!("Loading image model {} from {:?}", model_name, model_path);
+        let gpu_id = self.config.read().await.inference.gpu_id as usize;
+        let pipeline = tokio::task::spawn_blocking(move || -> Result<Arc<dyn DiffusionModel>> {
+            let device = match Device::cuda_if_available(gpu_id)? {
+                Device::Cpu => Device::metal_if_available(gpu_id)?,
+                device => device,
+            };
+            let diffusion_type = detect_model_type(&model_path)?;
+            Ok(load_model(&model_path, diffusion_type, &device, None)?.into())
+        })
+        .await?
+        .map_err(RuntimeError::map_out_of_memory)?;
+
+        *slot = Some(ImagePipeline {
+            model: model_name.to_string(),
+            pipeline: pipeline.clone(),
+        });
+        tracing::info!("Image model {} loaded", model_name);
+        Ok(pipeline)
+    }
+
     /// Unload the current model
     pub async fn unload_model(&self) -> Result<()> {
         let mut runtime = self.runtime.write().await;
@@ -95,3 +365,8 @@ impl AppState {
         Ok(())
     }
 }
+
+/// Modification time of a file, `None` if it doesn't exist yet
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).ok()?.modified().ok()
+}
@@ -0,0 +1,143 @@
+//! Optional JSONL transcript of completed requests (`daemon.log_transcripts`).
+//!
+//! Each finished request is appended as one JSON line. Writes happen on a
+//! spawned task so they never hold up generation, and the file is rotated
+//! once it grows past `daemon.transcript_max_mb`.
+
+use anyhow::Result;
+use ohmygpu_core::Config;
+use ohmygpu_runtime_api::{ChatMessage, ChatRequest};
+use serde::Serialize;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+#[derive(Clone)]
+pub struct TranscriptLogger {
+    path: PathBuf,
+    max_bytes: u64,
+    /// Serializes appends and rotation across concurrent requests
+    write_lock: Arc<Mutex<()>>,
+}
+
+#[derive(Serialize)]
+struct TranscriptEntry {
+    timestamp: String,
+    endpoint: &'static str,
+    model: String,
+    messages: Vec<ChatMessage>,
+    max_tokens: u32,
+    temperature: f32,
+    output: String,
+    tokens: u32,
+    finish_reason: Option<String>,
+    latency_ms: u64,
+}
+
+/// A request being recorded; call `finish` once the output is known
+pub struct Transcript {
+    logger: TranscriptLogger,
+    endpoint: &'static str,
+    model: String,
+    messages: Vec<ChatMessage>,
+    max_tokens: u32,
+    temperature: f32,
+    start: Instant,
+}
+
+impl TranscriptLogger {
+    /// Returns `None` unless transcripts are enabled in the config
+    pub fn from_config(config: &Config) -> Result<Option<Self>> {
+        if !config.daemon.log_transcripts {
+            return Ok(None);
+        }
+
+        let path = match &config.daemon.transcript_path {
+            Some(path) => path.clone(),
+            None => Config::logs_dir()?.join("transcripts.jsonl"),
+        };
+
+        tracing::warn!(
+            "Transcript logging is enabled: prompts and responses are written to {:?}. \
+             They may contain personal or sensitive data.",
+            path
+        );
+
+        Ok(Some(Self {
+            path,
+            max_bytes: config.daemon.transcript_max_mb.saturating_mul(1024 * 1024),
+            write_lock: Arc::new(Mutex::new(())),
+        }))
+    }
+
+    pub fn start(&self, endpoint: &'static str, model: &str, request: &ChatRequest) -> Transcript {
+        Transcript {
+            logger: self.clone(),
+            endpoint,
+            model: model.to_string(),
+            messages: request.messages.clone(),
+            max_tokens: request.max_tokens,
+            temperature: request.temperature,
+            start: Instant::now(),
+        }
+    }
+
+    async fn append(&self, line: String) -> Result<()> {
+        let _guard = self.write_lock.lock().await;
+
+        if let Some(parent) = self.path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        // Rotate: keep a single previous file next to the current one
+        if let Ok(metadata) = tokio::fs::metadata(&self.path).await {
+            if self.max_bytes > 0 && metadata.len() + line.len() as u64 > self.max_bytes {
+                let mut rotated = self.path.clone().into_os_string();
+                rotated.push(".1");
+                tokio::fs::rename(&self.path, rotated).await?;
+            }
+        }
+
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await?;
+        file.write_all(line.as_bytes()).await?;
+        Ok(())
+    }
+}
+
+impl Transcript {
+    /// Record the output and write the entry in the background
+    pub fn finish(self, output: String, tokens: u32, finish_reason: Option<String>) {
+        let entry = TranscriptEntry {
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            endpoint: self.endpoint,
+            model: self.model,
+            messages: self.messages,
+            max_tokens: self.max_tokens,
+            temperature: self.temperature,
+            output,
+            tokens,
+            finish_reason,
+            latency_ms: self.start.elapsed().as_millis() as u64,
+        };
+        let logger = self.logger;
+
+        tokio::spawn(async move {
+            let line = match serde_json::to_string(&entry) {
+                Ok(json) => json + "\n",
+                Err(e) => {
+                    tracing::warn!("Failed to serialize transcript entry: {}", e);
+                    return;
+                }
+            };
+            if let Err(e) = logger.append(line).await {
+                tracing::warn!("Failed to write transcript to {:?}: {}", logger.path, e);
+            }
+        });
+    }
+}
@@ -0,0 +1,166 @@
+//! Live reload of config.toml (`omg serve --watch`)
+//!
+//! Request defaults (temperature, max tokens), request limits and the SSE
+//! keep-alive interval apply to the next request, the log level right away
+//! and model settings to the next model load. Settings the daemon only reads
+//! at startup are logged as needing a restart.
+
+use anyhow::Result;
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use ohmygpu_core::Config;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+use crate::logging::{self, LogFilter};
+use crate::server::ConfigOverrides;
+use crate::state::AppState;
+
+/// Editors often save in several steps (truncate, write, rename)
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Start watching config.toml. Changes stop being picked up once the
+/// returned watcher is dropped.
+pub fn watch_config(
+    state: Arc<AppState>,
+    overrides: ConfigOverrides,
+    log_filter: Option<LogFilter>,
+) -> Result<notify::RecommendedWatcher> {
+    let config_path = Config::config_path()?;
+    let config_dir = config_path
+        .parent()
+        .map(PathBuf::from)
+        .ok_or_else(|| anyhow::anyhow!("Invalid config path {:?}", config_path))?;
+    std::fs::create_dir_all(&config_dir)?;
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+
+    // Watch the directory rather than the file, so saves that replace the
+    // file are still seen
+    let watched_path = config_path.clone();
+    let mut watcher =
+        notify::recommended_watcher(move |event: notify::Result<Event>| match event {
+            Ok(event) => {
+                let relevant = matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_))
+                    && event.paths.iter().any(|p| p == &watched_path);
+                if relevant {
+                    let _ = tx.send(());
+                }
+            }
+            Err(e) => tracing::warn!("Config watcher error: {}", e),
+        })?;
+    watcher.watch(&config_dir, RecursiveMode::NonRecursive)?;
+
+    tokio::spawn(async move {
+        while rx.recv().await.is_some() {
+            while let Ok(Some(())) = tokio::time::timeout(DEBOUNCE, rx.recv()).await {}
+            reload(&state, &overrides, log_filter.as_ref()).await;
+        }
+    });
+
+    tracing::info!("Watching {:?} for changes", config_path);
+    Ok(watcher)
+}
+
+async fn reload(state: &AppState, overrides: &ConfigOverrides, log_filter: Option<&LogFilter>) {
+    let mut new_config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            tracing::warn!("Ignoring config.toml change: {:#}", e);
+            return;
+        }
+    };
+    overrides.apply(&mut new_config);
+    if let Err(e) = new_config.validate() {
+        tracing::warn!("Ignoring config.toml change: {:#}", e);
+        return;
+    }
+
+    let mut config = state.config.write().await;
+    log_changes(&config, &new_config);
+    if let Some(log_filter) = log_filter {
+        if let Err(e) = logging::set_level(log_filter, &new_config.daemon.log_level) {
+            tracing::warn!("Failed to change the log level: {:#}", e);
+        }
+    }
+    *config = new_config;
+}
+
+/// Log each setting that differs between `old` and `new`, and when the
+/// change takes effect
+fn log_changes(old: &Config, new: &Config) {
+    let (old, new) = (settings(old), settings(new));
+    let keys: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+    for key in keys {
+        let (old, new) = (shown(key, old.get(key)), shown(key, new.get(key)));
+        if old == new {
+            continue;
+        }
+        match applies(key) {
+            Some(when) => {
+                tracing::info!("Config: {} {} -> {} (applies {})", key, old, new, when)
+            }
+            None => tracing::warn!(
+                "Config: {} changed to {}; restart the daemon to apply it",
+                key,
+                new
+            ),
+        }
+    }
+}
+
+/// When a changed setting takes effect; settings not listed are only read
+/// at startup
+fn applies(key: &str) -> Option<&'static str> {
+    match key {
+        "inference.max_tokens"
+        | "inference.temperature"
+        | "inference.top_p"
+        | "daemon.max_tokens_per_second"
+        | "daemon.max_concurrent_requests_per_client"
+        | "daemon.max_batch_total_tokens"
+        | "daemon.sse_keep_alive_secs"
+        | "daemon.auto_download" => Some("from the next request"),
+        "inference.gpu_id"
+        | "inference.stream_buffer_size"
+        | "inference.chat_template"
+        | "inference.dtype"
+        | "inference.context_length" => Some("on the next model load"),
+        "daemon.image_base_url" => Some("to images generated from now on"),
+        "daemon.image_retention_secs" => Some("at the next cleanup"),
+        "daemon.log_level" => Some("now"),
+        _ => None,
+    }
+}
+
+/// Every setting of `config` by its `section.key` name, as in `omg config`
+fn settings(config: &Config) -> BTreeMap<String, serde_json::Value> {
+    let Ok(serde_json::Value::Object(sections)) = serde_json::to_value(config) else {
+        return BTreeMap::new();
+    };
+    let mut settings = BTreeMap::new();
+    for (section, values) in sections {
+        if let serde_json::Value::Object(values) = values {
+            for (key, value) in values {
+                settings.insert(format!("{}.{}", section, key), value);
+            }
+        }
+    }
+    settings
+}
+
+/// A setting's value for the log; the HuggingFace token is never written
+fn shown(key: &str, value: Option<&serde_json::Value>) -> String {
+    match value {
+        None | Some(serde_json::Value::Null) => "(unset)".to_string(),
+        Some(_) if key == "models.hf_token" => "***".to_string(),
+        // f32 settings widened to f64 (0.699999988 for 0.7) print as written
+        Some(serde_json::Value::Number(n)) => match n.as_f64() {
+            Some(x) if n.is_f64() && (x as f32) as f64 == x => (x as f32).to_string(),
+            _ => n.to_string(),
+        },
+        Some(value) => value.to_string(),
+    }
+}
@@ -0,0 +1,119 @@
+//! A chat request for a model that isn't loaded loads it, then answers.
+
+mod common;
+
+use anyhow::Result;
+use axum::http::StatusCode;
+use common::{StubRuntime, TestDaemon};
+use ohmygpu_core::ModelType;
+use ohmygpu_daemon::state::LoadStatus;
+use ohmygpu_runtime_api::RuntimeStatus;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+
+const MODEL_NAME: &str = "tiny-llama";
+
+/// Poll until `done` holds, failing after a few seconds
+async fn wait_for(mut done: impl FnMut() -> bool) {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while !done() {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    })
+    .await
+    .expect("timed out waiting for the daemon");
+}
+
+#[tokio::test]
+async fn chat_request_loads_the_model() -> Result<()> {
+    let gate = Arc::new(Semaphore::new(0));
+    let runtime = StubRuntime::new(&["Once", " upon", " a", " time"]).with_load_gate(gate.clone());
+    let daemon = Arc::new(TestDaemon::new(runtime, &[(MODEL_NAME, ModelType::LLM)])?);
+    let state = daemon.state.clone();
+    assert_eq!(state.runtime.read().await.status(), RuntimeStatus::Unloaded);
+    assert_eq!(state.get_current_model().await, None);
+    assert!(matches!(state.load_status(), LoadStatus::Idle));
+
+    let request = tokio::spawn({
+        let daemon = daemon.clone();
+        async move {
+            let body = serde_json::json!({
+                "model": MODEL_NAME,
+                "messages": [{ "role": "user", "content": "Tell me a story" }],
+                "max_tokens": 8,
+            });
+            daemon.post_json("/v1/chat/completions", body).await
+        }
+    });
+
+    // The load blocks on the gate, so the request stays in `Loading`
+    wait_for(
+        || matches!(state.load_status(), LoadStatus::Loading { model } if model == MODEL_NAME),
+    )
+    .await;
+    assert_eq!(state.get_current_model().await, None);
+    assert!(!request.is_finished());
+    gate.add_permits(1);
+
+    let (status, completion) = request.await??;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(completion["object"], "chat.completion");
+    assert_eq!(completion["model"], MODEL_NAME);
+    let choice = &completion["choices"][0];
+    assert_eq!(choice["message"]["role"], "assistant");
+    assert_eq!(choice["message"]["content"], "Once upon a time");
+    assert_eq!(choice["finish_reason"], "stop");
+    assert_eq!(completion["usage"]["completion_tokens"], 4);
+
+    assert!(matches!(state.load_status(), LoadStatus::Ready { model } if model == MODEL_NAME));
+    assert_eq!(state.runtime.read().await.status(), RuntimeStatus::Ready);
+    assert_eq!(state.get_current_model().await.as_deref(), Some(MODEL_NAME));
+    let info = state.runtime.read().await.model_info().unwrap();
+    assert_eq!(info.name, MODEL_NAME);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn unknown_model_fails_to_load() -> Result<()> {
+    let daemon = TestDaemon::new(StubRuntime::new(&["Hi"]), &[])?;
+    let body = serde_json::json!({
+        "model": "missing",
+        "messages": [{ "role": "user", "content": "Hello" }],
+    });
+    let (status, response) = daemon.post_json("/v1/chat/completions", body).await?;
+
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(response["error"]["type"], "invalid_request_error");
+    assert!(
+        matches!(daemon.state.load_status(), LoadStatus::Failed { model, .. } if model == "missing")
+    );
+    assert_eq!(daemon.state.get_current_model().await, None);
+    Ok(())
+}
+
+#[tokio::test]
+async fn out_of_memory_while_loading_is_a_server_error() -> Result<()> {
+    let runtime = StubRuntime::new(&["Hi"]).with_load_out_of_memory();
+    let daemon = TestDaemon::new(runtime, &[(MODEL_NAME, ModelType::LLM)])?;
+
+    let body = serde_json::json!({
+        "model": MODEL_NAME,
+        "messages": [{ "role": "user", "content": "Hello" }],
+    });
+    let (status, response) = daemon.post_json("/v1/chat/completions", body).await?;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(response["error"]["type"], "server_error");
+    let message = response["error"]["message"].as_str().unwrap();
+    assert!(message.contains("Out of GPU memory"), "{}", message);
+
+    let body = serde_json::json!({ "model": MODEL_NAME, "prompt": "" });
+    let (status, response) = daemon.post_json("/api/generate", body).await?;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    let message = response["error"].as_str().unwrap();
+    assert!(message.contains("Out of GPU memory"), "{}", message);
+
+    assert_eq!(daemon.state.get_current_model().await, None);
+    Ok(())
+}
@@ -0,0 +1,294 @@
+//! A stub runtime and a daemon built on it, for driving the API without a
+//! model. Each test binary uses a different part of this module.
+#![allow(dead_code)]
+
+use anyhow::Result;
+use async_trait::async_trait;
+use axum::body::Body;
+use axum::http::{header, Request, StatusCode};
+use ohmygpu_core::{Config, ModelInfo, ModelRegistry, ModelSource, ModelType};
+use ohmygpu_daemon::api;
+use ohmygpu_daemon::state::AppState;
+use ohmygpu_runtime_api::{
+    ChatMessage, ChatRequest, ChatResponse, ChatToken, EmbeddingResponse, LoadedModelInfo, Runtime,
+    RuntimeCaps, RuntimeConfig, RuntimeError, RuntimeStatus,
+};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tempfile::TempDir;
+use tokio::sync::{mpsc, Semaphore};
+use tower::ServiceExt;
+
+/// Timing the stub reports for non-streaming chats
+pub const STUB_PREFILL_MS: u64 = 2;
+pub const STUB_GENERATION_MS: u64 = 5;
+
+/// Answers every chat request with the same tokens, without a model.
+pub struct StubRuntime {
+    status: RuntimeStatus,
+    info: Option<LoadedModelInfo>,
+    reply: Vec<String>,
+    load_gate: Option<Arc<Semaphore>>,
+    stream_buffer_size: usize,
+    tokens_per_chunk: usize,
+    fail_after: Option<usize>,
+    fail_load: bool,
+}
+
+impl StubRuntime {
+    /// A runtime whose completions are `reply`, one token per item
+    pub fn new(reply: &[&str]) -> Self {
+        Self {
+            status: RuntimeStatus::Unloaded,
+            info: None,
+            reply: reply.iter().map(|token| token.to_string()).collect(),
+            load_gate: None,
+            stream_buffer_size: 32,
+            tokens_per_chunk: 1,
+            fail_after: None,
+            fail_load: false,
+        }
+    }
+
+    /// Decode each reply item from `n` tokens, like a character spanning
+    /// several byte tokens
+    pub fn with_tokens_per_chunk(mut self, n: usize) -> Self {
+        self.tokens_per_chunk = n;
+        self
+    }
+
+    /// Fail streamed generations with an out-of-memory error after `n`
+    /// reply items
+    pub fn with_stream_failure_after(mut self, n: usize) -> Self {
+        self.fail_after = Some(n);
+        self
+    }
+
+    /// Fail `load` with an out-of-memory error
+    pub fn with_load_out_of_memory(mut self) -> Self {
+        self.fail_load = true;
+        self
+    }
+
+    /// Make `load` wait for a permit from `gate`, so tests can look at the
+    /// daemon while a model is loading
+    pub fn with_load_gate(mut self, gate: Arc<Semaphore>) -> Self {
+        self.load_gate = Some(gate);
+        self
+    }
+}
+
+#[async_trait]
+impl Runtime for StubRuntime {
+    fn caps(&self) -> RuntimeCaps {
+        self.info
+            .as_ref()
+            .map(|info| info.caps.clone())
+            .unwrap_or_default()
+    }
+
+    fn status(&self) -> RuntimeStatus {
+        self.status
+    }
+
+    fn model_info(&self) -> Option<LoadedModelInfo> {
+        self.info.clone()
+    }
+
+    async fn load(&mut self, config: RuntimeConfig) -> Result<()> {
+        self.status = RuntimeStatus::Loading;
+        if let Some(gate) = &self.load_gate {
+            gate.acquire().await?.forget();
+        }
+        if self.fail_load {
+            self.status = RuntimeStatus::Unloaded;
+            return Err(RuntimeError::OutOfMemory.into());
+        }
+        let model_type = config.model_type.unwrap_or(ModelType::LLM);
+        self.info = Some(LoadedModelInfo {
+            name: config.model_name.unwrap_or_default(),
+            architecture: "stub".to_string(),
+            dtype: "f32".to_string(),
+            device: "cpu".to_string(),
+            context_length: 2048,
+            caps: RuntimeCaps::for_model_type(&model_type),
+        });
+        if let Some(size) = config.stream_buffer_size {
+            self.stream_buffer_size = size;
+        }
+        self.status = RuntimeStatus::Ready;
+        Ok(())
+    }
+
+    async fn unload(&mut self) -> Result<()> {
+        self.status = RuntimeStatus::Unloaded;
+        self.info = None;
+        Ok(())
+    }
+
+    fn render_prompt(&self, messages: &[ChatMessage]) -> String {
+        messages
+            .iter()
+            .map(|m| format!("{}: {}\n", m.role, m.content))
+            .collect()
+    }
+
+    async fn count_prompt_tokens(&self, messages: &[ChatMessage]) -> Result<usize> {
+        Ok(self.render_prompt(messages).split_whitespace().count())
+    }
+
+    async fn chat(&self, request: ChatRequest) -> Result<ChatResponse> {
+        let tokens = self.completion(&request);
+        Ok(ChatResponse {
+            content: tokens.concat(),
+            tokens_used: (tokens.len() * self.tokens_per_chunk) as u32,
+            finish_reason: self.finish_reason(&request).to_string(),
+            prefill_ms: STUB_PREFILL_MS,
+            generation_ms: STUB_GENERATION_MS,
+        })
+    }
+
+    async fn chat_stream(&self, request: ChatRequest) -> Result<mpsc::Receiver<ChatToken>> {
+        let tokens = self.completion(&request);
+        let finish_reason = self.finish_reason(&request).to_string();
+        let per_chunk = self.tokens_per_chunk;
+        let fail_after = self.fail_after;
+        let (tx, rx) = mpsc::channel(self.stream_buffer_size);
+        tokio::spawn(async move {
+            for (i, content) in tokens.into_iter().enumerate() {
+                if fail_after == Some(i) {
+                    let _ = tx
+                        .send(ChatToken::failed(&RuntimeError::OutOfMemory.into()))
+                        .await;
+                    return;
+                }
+                let first_id = (i * per_chunk) as u32;
+                let token = ChatToken {
+                    content,
+                    token_ids: (first_id..first_id + per_chunk as u32).collect(),
+                    finish_reason: None,
+                    error: None,
+                };
+                if tx.send(token).await.is_err() {
+                    return;
+                }
+            }
+            // Like the candle runtime, the end is an empty token
+            let _ = tx
+                .send(ChatToken {
+                    content: String::new(),
+                    token_ids: Vec::new(),
+                    finish_reason: Some(finish_reason),
+                    error: None,
+                })
+                .await;
+        });
+        Ok(rx)
+    }
+
+    async fn embed(&self, _inputs: Vec<String>) -> Result<EmbeddingResponse> {
+        Err(RuntimeError::NotAnEmbeddingModel.into())
+    }
+}
+
+impl StubRuntime {
+    fn completion(&self, request: &ChatRequest) -> Vec<String> {
+        self.reply
+            .iter()
+            .take(request.max_tokens as usize)
+            .cloned()
+            .collect()
+    }
+
+    fn finish_reason(&self, request: &ChatRequest) -> &'static str {
+        if self.reply.len() > request.max_tokens as usize {
+            "length"
+        } else {
+            "stop"
+        }
+    }
+}
+
+/// A registry entry; the stub runtime never reads its files
+pub fn model(name: &str, model_type: ModelType, source: ModelSource) -> ModelInfo {
+    ModelInfo {
+        name: name.to_string(),
+        source,
+        model_type,
+        path: PathBuf::from(name),
+        size_bytes: 0,
+        files: Vec::new(),
+        downloaded_at: chrono::Utc::now(),
+        sampling: Default::default(),
+        pinned: false,
+    }
+}
+
+/// Daemon state over a [`StubRuntime`], with its registry and images in a
+/// temporary directory removed on drop
+pub struct TestDaemon {
+    pub state: Arc<AppState>,
+    pub dir: TempDir,
+}
+
+impl TestDaemon {
+    /// A daemon whose registry holds local `models` (name and type)
+    pub fn new(runtime: StubRuntime, models: &[(&str, ModelType)]) -> Result<Self> {
+        let models = models
+            .iter()
+            .map(|(name, model_type)| model(name, model_type.clone(), ModelSource::Local))
+            .collect();
+        Self::with_models(runtime, models, Config::default())
+    }
+
+    pub fn with_models(
+        runtime: StubRuntime,
+        models: Vec<ModelInfo>,
+        mut config: Config,
+    ) -> Result<Self> {
+        let dir = tempfile::tempdir()?;
+        let registry_path = dir.path().join("registry.json");
+        let mut registry = ModelRegistry::load_from(registry_path.clone())?;
+        for model in models {
+            registry.add(model)?;
+        }
+
+        config.daemon.image_dir = Some(dir.path().join("images"));
+        let state = AppState::with_runtime(config, registry_path, Box::new(runtime))?;
+        Ok(Self {
+            state: Arc::new(state),
+            dir,
+        })
+    }
+
+    /// Send a request through the API routes, returning the status and the
+    /// whole body
+    pub async fn send(&self, request: Request<Body>) -> Result<(StatusCode, Vec<u8>)> {
+        let response = api::routes(self.state.clone()).oneshot(request).await?;
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
+        Ok((status, body.to_vec()))
+    }
+
+    pub async fn get_json(&self, uri: &str) -> Result<(StatusCode, serde_json::Value)> {
+        let (status, body) = self.send(Request::get(uri).body(Body::empty())?).await?;
+        Ok((status, serde_json::from_slice(&body)?))
+    }
+
+    pub async fn post_json(
+        &self,
+        uri: &str,
+        body: serde_json::Value,
+    ) -> Result<(StatusCode, serde_json::Value)> {
+        let (status, body) = self.post(uri, body).await?;
+        Ok((status, serde_json::from_slice(&body)?))
+    }
+
+    /// POST `body` and return the raw response body (e.g. a stream)
+    pub async fn post(&self, uri: &str, body: serde_json::Value) -> Result<(StatusCode, Vec<u8>)> {
+        let request = Request::post(uri)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))?;
+        self.send(request).await
+    }
+}
@@ -0,0 +1,86 @@
+//! A client that goes away stops its image generation, and the pipeline
+//! stays locked until the generation has actually stopped.
+
+mod common;
+
+use anyhow::Result;
+use common::{StubRuntime, TestDaemon};
+use ohmygpu_daemon::state::ImagePipeline;
+use ohmygpu_runtime_diffusion::{
+    CancellationToken, DiffusionError, DiffusionModel, ImageGenRequest, ImageGenResponse,
+};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Duration;
+
+const MODEL_NAME: &str = "stub-image";
+/// Far longer than the test waits, so only cancellation ends it
+const STEPS: u32 = 10_000;
+const STEP: Duration = Duration::from_millis(5);
+
+/// Denoises by sleeping, reporting the step it was cancelled at and then
+/// holding on until the test lets it return
+struct SlowPipeline {
+    cancelled: Mutex<mpsc::Sender<u32>>,
+    finish: Mutex<mpsc::Receiver<()>>,
+}
+
+impl DiffusionModel for SlowPipeline {
+    fn generate(&self, request: &ImageGenRequest) -> Result<ImageGenResponse> {
+        self.generate_cancellable(request, CancellationToken::new())
+    }
+
+    fn generate_cancellable(
+        &self,
+        _request: &ImageGenRequest,
+        token: CancellationToken,
+    ) -> Result<ImageGenResponse> {
+        for step in 0..STEPS {
+            if token.is_cancelled() {
+                self.cancelled.lock().unwrap().send(step)?;
+                let _ = self.finish.lock().unwrap().recv();
+                return Err(DiffusionError::Cancelled.into());
+            }
+            std::thread::sleep(STEP);
+        }
+        anyhow::bail!("generation was never cancelled")
+    }
+
+    fn name(&self) -> &str {
+        MODEL_NAME
+    }
+}
+
+#[tokio::test]
+async fn dropped_request_cancels_generation() -> Result<()> {
+    let daemon = TestDaemon::new(StubRuntime::new(&[]), &[])?;
+    let (cancelled_tx, cancelled_rx) = mpsc::channel();
+    let (finish_tx, finish_rx) = mpsc::channel();
+    *daemon.state.image_pipeline.lock().await = Some(ImagePipeline {
+        model: MODEL_NAME.to_string(),
+        pipeline: Arc::new(SlowPipeline {
+            cancelled: Mutex::new(cancelled_tx),
+            finish: Mutex::new(finish_rx),
+        }),
+    });
+
+    // The client gives up while the image is generating
+    let request = daemon.post(
+        "/v1/images/generations",
+        serde_json::json!({ "model": MODEL_NAME, "prompt": "a lighthouse", "n": 2 }),
+    );
+    let timed_out = tokio::time::timeout(Duration::from_millis(100), request).await;
+    assert!(timed_out.is_err(), "generation finished before the drop");
+
+    let step =
+        tokio::task::spawn_blocking(move || cancelled_rx.recv_timeout(Duration::from_secs(5)))
+            .await??;
+    assert!(step < STEPS);
+
+    // Still stopping: a second generation can't start yet
+    assert!(daemon.state.image_pipeline.try_lock().is_err());
+    finish_tx.send(())?;
+    let slot =
+        tokio::time::timeout(Duration::from_secs(5), daemon.state.image_pipeline.lock()).await?;
+    assert!(slot.is_some());
+    Ok(())
+}
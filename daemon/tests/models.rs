@@ -0,0 +1,64 @@
+//! `/v1/models` lists every registered model with its owner and what it
+//! can serve.
+
+mod common;
+
+use anyhow::Result;
+use axum::http::StatusCode;
+use common::{model, StubRuntime, TestDaemon};
+use ohmygpu_core::{Config, ModelSource, ModelType};
+
+fn hugging_face(repo_id: &str) -> ModelSource {
+    ModelSource::HuggingFace {
+        repo_id: repo_id.to_string(),
+    }
+}
+
+#[tokio::test]
+async fn models_report_owner_and_capabilities() -> Result<()> {
+    let models = vec![
+        model(
+            "TinyLlama--TinyLlama-1.1B-Chat-v1.0",
+            ModelType::LLM,
+            hugging_face("TinyLlama/TinyLlama-1.1B-Chat-v1.0"),
+        ),
+        model(
+            "bge-small",
+            ModelType::Embedding,
+            hugging_face("BAAI/bge-small-en-v1.5"),
+        ),
+        model("z-image", ModelType::ImageGeneration, ModelSource::Local),
+    ];
+    let daemon = TestDaemon::with_models(StubRuntime::new(&[]), models, Config::default())?;
+
+    let (status, body) = daemon.get_json("/v1/models").await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["object"], "list");
+    let data = body["data"].as_array().unwrap();
+    assert_eq!(data.len(), 3);
+    let entry = |id: &str| {
+        data.iter()
+            .find(|m| m["id"] == id)
+            .unwrap_or_else(|| panic!("{} missing from {:?}", id, data))
+    };
+
+    let llm = entry("TinyLlama--TinyLlama-1.1B-Chat-v1.0");
+    assert_eq!(llm["object"], "model");
+    assert_eq!(llm["owned_by"], "TinyLlama");
+    assert_eq!(llm["capabilities"]["chat"], true);
+    assert_eq!(llm["capabilities"]["streaming"], true);
+    assert_eq!(llm["capabilities"]["embeddings"], false);
+    assert_eq!(llm["capabilities"]["images"], false);
+
+    let embedding = entry("bge-small");
+    assert_eq!(embedding["owned_by"], "BAAI");
+    assert_eq!(embedding["capabilities"]["embeddings"], true);
+    assert_eq!(embedding["capabilities"]["chat"], false);
+
+    let image = entry("z-image");
+    assert_eq!(image["owned_by"], "local");
+    assert_eq!(image["capabilities"]["images"], true);
+    assert_eq!(image["capabilities"]["chat"], false);
+    assert_eq!(image["capabilities"]["embeddings"], false);
+    Ok(())
+}
@@ -0,0 +1,85 @@
+//! `/api/chat` answers `stream: false` with one complete object, and
+//! streams chunks whose last one carries the request's statistics.
+
+mod common;
+
+use anyhow::Result;
+use axum::http::StatusCode;
+use common::{StubRuntime, TestDaemon, STUB_GENERATION_MS, STUB_PREFILL_MS};
+use ohmygpu_core::ModelType;
+
+const MODEL_NAME: &str = "tiny-llama";
+const REPLY: &[&str] = &["Once", " upon", " a", " time"];
+/// Tokens behind each reply item, as for multi-byte characters
+const TOKENS_PER_CHUNK: usize = 2;
+
+fn daemon() -> Result<TestDaemon> {
+    let runtime = StubRuntime::new(REPLY).with_tokens_per_chunk(TOKENS_PER_CHUNK);
+    TestDaemon::new(runtime, &[(MODEL_NAME, ModelType::LLM)])
+}
+
+fn request(stream: bool) -> serde_json::Value {
+    serde_json::json!({
+        "model": MODEL_NAME,
+        "messages": [{ "role": "user", "content": "Tell me a story" }],
+        "stream": stream,
+        "options": { "num_predict": 16 },
+    })
+}
+
+#[tokio::test]
+async fn non_streaming_returns_one_object() -> Result<()> {
+    let daemon = daemon()?;
+    let (status, body) = daemon.post("/api/chat", request(false)).await?;
+    assert_eq!(status, StatusCode::OK);
+
+    // Exactly one JSON object, not a sequence of chunks
+    let mut objects = serde_json::Deserializer::from_slice(&body).into_iter::<serde_json::Value>();
+    let response = objects.next().unwrap()?;
+    assert!(objects.next().is_none(), "more than one object");
+
+    assert_eq!(response["model"], MODEL_NAME);
+    assert_eq!(response["message"]["role"], "assistant");
+    assert_eq!(response["message"]["content"], REPLY.concat());
+    assert_eq!(response["done"], true);
+    assert_eq!(response["done_reason"], "stop");
+    assert_eq!(response["eval_count"], REPLY.len() * TOKENS_PER_CHUNK);
+    let ms = 1_000_000;
+    assert_eq!(response["total_duration"], STUB_GENERATION_MS * ms);
+    assert_eq!(response["prompt_eval_duration"], STUB_PREFILL_MS * ms);
+    assert_eq!(
+        response["eval_duration"],
+        (STUB_GENERATION_MS - STUB_PREFILL_MS) * ms
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn streaming_counts_tokens_not_chunks() -> Result<()> {
+    let daemon = daemon()?;
+    let (status, body) = daemon.post("/api/chat", request(true)).await?;
+    assert_eq!(status, StatusCode::OK);
+
+    let chunks: Vec<serde_json::Value> = String::from_utf8(body)?
+        .lines()
+        .filter_map(|line| line.strip_prefix("data: "))
+        .map(serde_json::from_str)
+        .collect::<Result<_, _>>()?;
+    let (last, content) = chunks.split_last().unwrap();
+    assert_eq!(content.len(), REPLY.len());
+    for (chunk, text) in content.iter().zip(REPLY) {
+        assert_eq!(chunk["message"]["content"], *text);
+        assert_eq!(chunk["done"], false);
+        assert!(chunk.get("eval_count").is_none());
+    }
+
+    assert_eq!(last["done"], true);
+    assert_eq!(last["done_reason"], "stop");
+    assert_eq!(last["eval_count"], REPLY.len() * TOKENS_PER_CHUNK);
+    let total = last["total_duration"].as_u64().unwrap();
+    let prompt_eval = last["prompt_eval_duration"].as_u64().unwrap();
+    let eval = last["eval_duration"].as_u64().unwrap();
+    assert!(prompt_eval <= total);
+    assert_eq!(prompt_eval + eval, total);
+    Ok(())
+}
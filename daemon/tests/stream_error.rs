@@ -0,0 +1,82 @@
+//! A stream whose generation fails partway ends with an error the client
+//! can see, not as if the reply were complete.
+
+mod common;
+
+use anyhow::Result;
+use axum::http::StatusCode;
+use common::{StubRuntime, TestDaemon};
+use ohmygpu_core::ModelType;
+
+const MODEL_NAME: &str = "tiny-llama";
+const REPLY: &[&str] = &["Once", " upon", " a", " time"];
+/// Reply items sent before the failure
+const FAIL_AFTER: usize = 2;
+
+fn daemon() -> Result<TestDaemon> {
+    let runtime = StubRuntime::new(REPLY).with_stream_failure_after(FAIL_AFTER);
+    TestDaemon::new(runtime, &[(MODEL_NAME, ModelType::LLM)])
+}
+
+fn request() -> serde_json::Value {
+    serde_json::json!({
+        "model": MODEL_NAME,
+        "messages": [{ "role": "user", "content": "Tell me a story" }],
+        "stream": true,
+    })
+}
+
+/// The `data:` payloads of an SSE body
+fn events(body: Vec<u8>) -> Result<Vec<String>> {
+    Ok(String::from_utf8(body)?
+        .lines()
+        .filter_map(|line| line.strip_prefix("data: "))
+        .map(str::to_string)
+        .collect())
+}
+
+#[tokio::test]
+async fn openai_stream_ends_with_an_error_object() -> Result<()> {
+    let daemon = daemon()?;
+    let (status, body) = daemon.post("/v1/chat/completions", request()).await?;
+    assert_eq!(status, StatusCode::OK);
+
+    let events = events(body)?;
+    let (done, events) = events.split_last().unwrap();
+    assert_eq!(done, "[DONE]");
+    let chunks: Vec<serde_json::Value> = events
+        .iter()
+        .map(|event| serde_json::from_str(event))
+        .collect::<Result<_, _>>()?;
+    let (error, chunks) = chunks.split_last().unwrap();
+
+    // The role chunk, then the content sent before the failure
+    assert_eq!(chunks.len(), 1 + FAIL_AFTER);
+    assert!(chunks
+        .iter()
+        .all(|chunk| chunk["choices"][0]["finish_reason"].is_null()));
+
+    assert_eq!(error["error"]["type"], "server_error");
+    let message = error["error"]["message"].as_str().unwrap();
+    assert!(message.contains("Out of GPU memory"), "{}", message);
+    Ok(())
+}
+
+#[tokio::test]
+async fn ollama_stream_ends_with_an_error_object() -> Result<()> {
+    let daemon = daemon()?;
+    let (status, body) = daemon.post("/api/chat", request()).await?;
+    assert_eq!(status, StatusCode::OK);
+
+    let chunks: Vec<serde_json::Value> = events(body)?
+        .iter()
+        .map(|event| serde_json::from_str(event))
+        .collect::<Result<_, _>>()?;
+    let (error, chunks) = chunks.split_last().unwrap();
+
+    assert_eq!(chunks.len(), FAIL_AFTER);
+    assert!(chunks.iter().all(|chunk| chunk["done"] == false));
+    let message = error["error"].as_str().unwrap();
+    assert!(message.contains("Out of GPU memory"), "{}", message);
+    Ok(())
+}
@@ -0,0 +1,134 @@
+//! The token budget admits requests while they fit, queues the rest and
+//! admits them in arrival order.
+
+use ohmygpu_daemon::limits::{TokenBudget, TokenPermit};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+const LIMIT: Option<u64> = Some(100);
+
+/// Long enough for a waiter that could go to have gone
+const SETTLE: Duration = Duration::from_millis(50);
+
+/// Request `tokens` in the background, returning the permit once admitted
+fn spawn_acquire(budget: &Arc<TokenBudget>, tokens: u64) -> JoinHandle<TokenPermit> {
+    let budget = budget.clone();
+    tokio::spawn(async move { budget.acquire(tokens, LIMIT).await })
+}
+
+#[tokio::test]
+async fn permits_reserve_and_release_tokens() {
+    let budget = Arc::new(TokenBudget::default());
+    let first = budget.acquire(30, LIMIT).await;
+    let second = budget.acquire(70, LIMIT).await;
+    let usage = budget.usage();
+    assert_eq!((usage.tokens, usage.active, usage.queued), (100, 2, 0));
+
+    drop(first);
+    let usage = budget.usage();
+    assert_eq!((usage.tokens, usage.active), (70, 1));
+    drop(second);
+    let usage = budget.usage();
+    assert_eq!((usage.tokens, usage.active), (0, 0));
+}
+
+#[tokio::test]
+async fn requests_over_the_limit_wait_for_a_release() {
+    let budget = Arc::new(TokenBudget::default());
+    let held = budget.acquire(80, LIMIT).await;
+    let waiter = spawn_acquire(&budget, 40);
+    tokio::time::sleep(SETTLE).await;
+    assert!(!waiter.is_finished());
+    assert_eq!(budget.usage().queued, 1);
+
+    drop(held);
+    let permit = waiter.await.unwrap();
+    let usage = budget.usage();
+    assert_eq!((usage.tokens, usage.active, usage.queued), (40, 1, 0));
+    drop(permit);
+}
+
+#[tokio::test]
+async fn without_a_limit_nothing_waits() {
+    let budget = Arc::new(TokenBudget::default());
+    let _held = budget.acquire(1_000_000, None).await;
+    let _more = budget.acquire(1_000_000, None).await;
+    assert_eq!(budget.usage().active, 2);
+}
+
+#[tokio::test]
+async fn oversized_request_runs_alone() {
+    let budget = Arc::new(TokenBudget::default());
+    let held = budget.acquire(10, LIMIT).await;
+    let oversized = spawn_acquire(&budget, 500);
+    tokio::time::sleep(SETTLE).await;
+    assert!(!oversized.is_finished());
+
+    drop(held);
+    let permit = oversized.await.unwrap();
+    assert_eq!(budget.usage().tokens, 500);
+    drop(permit);
+}
+
+#[tokio::test]
+async fn large_request_is_not_starved_by_later_small_ones() {
+    let budget = Arc::new(TokenBudget::default());
+    let held = budget.acquire(60, LIMIT).await;
+    let large = spawn_acquire(&budget, 90);
+    tokio::time::sleep(SETTLE).await;
+    // Would fit next to `held`, but arrived after `large`
+    let small = spawn_acquire(&budget, 20);
+    tokio::time::sleep(SETTLE).await;
+    assert!(!large.is_finished());
+    assert!(!small.is_finished());
+    assert_eq!(budget.usage().queued, 2);
+
+    drop(held);
+    let large = large.await.unwrap();
+    tokio::time::sleep(SETTLE).await;
+    // 90 + 20 is past the limit
+    assert!(!small.is_finished());
+    assert_eq!(budget.usage().tokens, 90);
+
+    drop(large);
+    let small = small.await.unwrap();
+    assert_eq!(budget.usage().tokens, 20);
+    drop(small);
+}
+
+#[tokio::test]
+async fn queued_requests_behind_the_head_go_together() {
+    let budget = Arc::new(TokenBudget::default());
+    let held = budget.acquire(100, LIMIT).await;
+    let waiters: Vec<_> = (0..3).map(|_| spawn_acquire(&budget, 30)).collect();
+    tokio::time::sleep(SETTLE).await;
+    assert_eq!(budget.usage().queued, 3);
+
+    drop(held);
+    let mut permits = Vec::new();
+    for waiter in waiters {
+        permits.push(waiter.await.unwrap());
+    }
+    let usage = budget.usage();
+    assert_eq!((usage.tokens, usage.active, usage.queued), (90, 3, 0));
+}
+
+#[tokio::test]
+async fn cancelled_waiter_leaves_the_queue() {
+    let budget = Arc::new(TokenBudget::default());
+    let held = budget.acquire(60, LIMIT).await;
+    let cancelled = spawn_acquire(&budget, 90);
+    tokio::time::sleep(SETTLE).await;
+    let behind = spawn_acquire(&budget, 30);
+    tokio::time::sleep(SETTLE).await;
+    assert_eq!(budget.usage().queued, 2);
+
+    // The client went away: the request behind it is first now, and fits
+    cancelled.abort();
+    let permit = behind.await.unwrap();
+    let usage = budget.usage();
+    assert_eq!((usage.tokens, usage.active, usage.queued), (90, 2, 0));
+    drop(permit);
+    drop(held);
+}